@@ -0,0 +1,164 @@
+//! Latencia por tick de las estadísticas rodantes frente al esquema anterior
+//! (`Vec::remove(0)` + recálculo completo de media/desviación en cada update).
+//!
+//! cargo bench --bench rolling
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use motor_fix_rust::features::{FeatureCollector, Signals};
+use motor_fix_rust::gaussian::GaussianFilter;
+use motor_fix_rust::rolling::RollingStats;
+use motor_fix_rust::state::OrderBook;
+use motor_fix_rust::trades::TradeTape;
+use motor_fix_rust::velocity::VelocityRates;
+use ndarray::{Array1, Array2, Axis};
+
+/// Reproducción del FeatureCollector anterior: ventana en Vec y Array2 por tick
+struct NaiveCollector {
+    window_size: usize,
+    data: Vec<Vec<f64>>,
+    means: Array1<f64>,
+    stds: Array1<f64>,
+}
+
+impl NaiveCollector {
+    fn push(&mut self, row: Vec<f64>) {
+        if self.data.len() >= self.window_size {
+            self.data.remove(0);
+        }
+        self.data.push(row);
+        let (rows, cols) = (self.data.len(), self.data[0].len());
+        let mut matrix = Array2::zeros((rows, cols));
+        for (i, row) in self.data.iter().enumerate() {
+            for (j, &val) in row.iter().enumerate() {
+                matrix[[i, j]] = val;
+            }
+        }
+        self.means = matrix.mean_axis(Axis(0)).unwrap();
+        self.stds = matrix.std_axis(Axis(0), 0.0);
+    }
+}
+
+/// Precio sintético determinista
+fn price(i: usize) -> f64 {
+    1.1 + 0.0005 * ((i as f64) * 0.37).sin() + 0.00001 * (i % 7) as f64
+}
+
+fn book() -> OrderBook {
+    let mut book = OrderBook::new();
+    for level in 0..5 {
+        let offset = level as f64 * 0.00001;
+        book.update('0', '0', 1.1 - offset, 100.0 + level as f64);
+        book.update('0', '1', 1.10001 + offset, 120.0 - level as f64);
+    }
+    book
+}
+
+/// El estado se crea y se llena una vez fuera de la rutina medida: criterion la
+/// invoca varias veces y solo interesa el régimen estacionario (ventana llena).
+fn bench_feature_stats(c: &mut Criterion) {
+    let mut group = c.benchmark_group("feature_stats");
+    for window in [100, 1000] {
+        let row = |i: usize| vec![price(i), i as f64, 0.1, 0.5, 0.2, -0.1, 0.3];
+
+        let mut naive = NaiveCollector {
+            window_size: window,
+            data: Vec::new(),
+            means: Array1::zeros(0),
+            stds: Array1::zeros(0),
+        };
+        let mut columns = vec![RollingStats::new(window); 7];
+        for i in 0..window {
+            naive.push(row(i));
+            for (s, x) in columns.iter_mut().zip(row(i)) {
+                s.push(x);
+            }
+        }
+
+        let mut i = window;
+        group.bench_function(BenchmarkId::new("naive", window), |b| {
+            b.iter(|| {
+                i += 1;
+                naive.push(row(i));
+                black_box(&naive.stds);
+            })
+        });
+        group.bench_function(BenchmarkId::new("welford", window), |b| {
+            b.iter(|| {
+                i += 1;
+                for (s, x) in columns.iter_mut().zip(row(i)) {
+                    s.push(x);
+                }
+                black_box(columns.iter().map(|s| s.std()).sum::<f64>());
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_collector(c: &mut Criterion) {
+    let book = book();
+    let trades = TradeTape::new(30.0);
+    let signals = Signals {
+        rates: VelocityRates {
+            total: 5.0,
+            ..VelocityRates::default()
+        },
+        noise: 0.1,
+        context: 0.5,
+        ..Signals::default()
+    };
+    let mut group = c.benchmark_group("feature_collector");
+    for window in [100, 1000] {
+        let mut collector = FeatureCollector::new(window);
+        for i in 0..window {
+            collector
+                .push_features(&book, &trades, i as f64 * 0.01, &signals)
+                .unwrap();
+        }
+        let mut i = window;
+        group.bench_function(BenchmarkId::from_parameter(window), |b| {
+            b.iter(|| {
+                i += 1;
+                collector
+                    .push_features(&book, &trades, i as f64 * 0.01, &signals)
+                    .unwrap();
+                black_box(collector.get_standardized_vector());
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_gaussian(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gaussian_filter");
+    for window in [20, 200] {
+        let mut filter = GaussianFilter::new(window, 1.5, 1.0);
+        for i in 0..window {
+            filter.add_price(price(i));
+        }
+        let mut i = window;
+        group.bench_function(BenchmarkId::new("tick", window), |b| {
+            b.iter(|| {
+                i += 1;
+                filter.add_price(price(i));
+                black_box(filter.compute_uncertainty());
+            })
+        });
+        group.bench_function(BenchmarkId::new("posterior", window), |b| {
+            b.iter(|| {
+                i += 1;
+                filter.add_price(price(i));
+                black_box(filter.posterior());
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_feature_stats,
+    bench_collector,
+    bench_gaussian
+);
+criterion_main!(benches);
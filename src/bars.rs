@@ -0,0 +1,552 @@
+use crate::bayesian::BayesianNetwork;
+use crate::pipeline::{MarketPipeline, PipelineConfig};
+use crate::recorder::read_recording;
+use log::info;
+use serde::Serialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Criterio de cierre de una barra
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarSpec {
+    /// Intervalo fijo en segundos, alineado a múltiplos del periodo
+    Time(f64),
+    /// N eventos (cambios del mejor nivel y operaciones)
+    Tick(usize),
+    /// Volumen negociado
+    Volume(f64),
+    /// Importe negociado (precio · volumen)
+    Dollar(f64),
+}
+
+impl BarSpec {
+    /// "1s", "30s", "1m", "1h", "time:15", "tick:100", "volume:1e6", "dollar:5e6"
+    pub fn parse(spec: &str) -> Option<Self> {
+        let spec = spec.trim();
+        if let Some((kind, value)) = spec.split_once(':') {
+            let spec = match kind {
+                "time" => BarSpec::Time(value.parse().ok()?),
+                "tick" => BarSpec::Tick(value.parse().ok()?),
+                "volume" => BarSpec::Volume(value.parse().ok()?),
+                "dollar" => BarSpec::Dollar(value.parse().ok()?),
+                _ => return None,
+            };
+            return spec.is_valid().then_some(spec);
+        }
+        let split = spec.find(|c: char| c.is_alphabetic())?;
+        let (n, unit) = spec.split_at(split);
+        let n: f64 = n.parse().ok()?;
+        let seconds = match unit {
+            "s" => n,
+            "m" => n * 60.0,
+            "h" => n * 3600.0,
+            "d" => n * 86400.0,
+            _ => return None,
+        };
+        let spec = BarSpec::Time(seconds);
+        spec.is_valid().then_some(spec)
+    }
+
+    fn is_valid(&self) -> bool {
+        match *self {
+            BarSpec::Time(v) | BarSpec::Volume(v) | BarSpec::Dollar(v) => v > 0.0 && v.is_finite(),
+            BarSpec::Tick(n) => n > 0,
+        }
+    }
+}
+
+/// Vela OHLC del precio medio con el volumen negociado en su intervalo
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Bar {
+    /// Segundos; en barras de tiempo, los límites del intervalo [start, end)
+    pub start: f64,
+    pub end: f64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Último mejor bid/ask de la barra
+    pub bid: f64,
+    pub ask: f64,
+    pub volume: f64,
+    pub dollar_volume: f64,
+    /// Operaciones (una operación repartida entre barras cuenta en cada una)
+    pub trades: usize,
+    /// Eventos de cualquier tipo
+    pub ticks: usize,
+}
+
+impl Bar {
+    fn open_at(start: f64, mid: f64, bid: f64, ask: f64) -> Self {
+        Self {
+            start,
+            end: start,
+            open: mid,
+            high: mid,
+            low: mid,
+            close: mid,
+            bid,
+            ask,
+            volume: 0.0,
+            dollar_volume: 0.0,
+            trades: 0,
+            ticks: 0,
+        }
+    }
+
+    /// Precio medio ponderado por volumen de las operaciones de la barra
+    pub fn vwap(&self) -> Option<f64> {
+        (self.volume > 0.0).then(|| self.dollar_volume / self.volume)
+    }
+
+    fn mark(&mut self, mid: f64) {
+        self.high = self.high.max(mid);
+        self.low = self.low.min(mid);
+        self.close = mid;
+    }
+}
+
+/// Agrega los updates del libro y las operaciones en barras. Los precios OHLC son
+/// del mid; las operaciones aportan volumen (no mueven el OHLC) y no abren barra
+/// hasta que hay un mid de referencia.
+pub struct BarAggregator {
+    pub spec: BarSpec,
+    current: Option<Bar>,
+    /// Último (mid, bid, ask)
+    quote: Option<(f64, f64, f64)>,
+}
+
+impl BarAggregator {
+    pub fn new(spec: BarSpec) -> Self {
+        Self {
+            spec,
+            current: None,
+            quote: None,
+        }
+    }
+
+    /// Barra en curso (sin cerrar)
+    pub fn current(&self) -> Option<&Bar> {
+        self.current.as_ref()
+    }
+
+    /// Update del mejor nivel. Devuelve las barras cerradas por el evento.
+    pub fn on_quote(&mut self, time: f64, bid: f64, ask: f64) -> Vec<Bar> {
+        let mid = (bid + ask) / 2.0;
+        self.quote = Some((mid, bid, ask));
+        let mut closed = self.roll_time(time);
+        let bar = self.bar_at(time);
+        bar.mark(mid);
+        bar.bid = bid;
+        bar.ask = ask;
+        bar.ticks += 1;
+        if let BarSpec::Tick(n) = self.spec {
+            closed.extend(self.close_if(time, |b| b.ticks >= n));
+        }
+        closed
+    }
+
+    /// Operación ejecutada. En barras de volumen o importe una operación que
+    /// supera el umbral se reparte entre las barras que completa. Precio y
+    /// volumen deben ser finitos y positivos (si no, el reparto no termina).
+    pub fn on_trade(&mut self, time: f64, price: f64, size: f64) -> Vec<Bar> {
+        let valid = size > 0.0 && size.is_finite() && price > 0.0 && price.is_finite();
+        if self.quote.is_none() || !valid {
+            return Vec::new();
+        }
+        let spec = self.spec;
+        let mut closed = self.roll_time(time);
+        let mut remaining = size;
+        while remaining > 0.0 {
+            let bar = self.bar_at(time);
+            let take = match spec {
+                BarSpec::Volume(v) => remaining.min(v - bar.volume),
+                BarSpec::Dollar(d) => remaining.min((d - bar.dollar_volume) / price),
+                _ => remaining,
+            };
+            bar.volume += take;
+            bar.dollar_volume += take * price;
+            bar.trades += 1;
+            bar.ticks += 1;
+            remaining -= take;
+            // Tolerancia relativa para el redondeo de la resta
+            let full = match spec {
+                BarSpec::Volume(v) => bar.volume >= v * (1.0 - 1e-12),
+                BarSpec::Dollar(d) => bar.dollar_volume >= d * (1.0 - 1e-12),
+                BarSpec::Tick(n) => bar.ticks >= n,
+                BarSpec::Time(_) => false,
+            };
+            if full {
+                closed.extend(self.close_if(time, |_| true));
+            }
+            if remaining <= size * 1e-12 {
+                break;
+            }
+        }
+        closed
+    }
+
+    /// Cierra la barra de tiempo en curso si `now` ya superó su final
+    /// (para emitirla sin esperar al siguiente evento)
+    pub fn flush(&mut self, now: f64) -> Option<Bar> {
+        self.roll_time(now).pop()
+    }
+
+    /// Cierra la barra en curso aunque no esté completa (fin de la grabación)
+    pub fn finish(&mut self) -> Option<Bar> {
+        self.current.take()
+    }
+
+    /// En barras de tiempo, cierra la barra en curso si `time` cae fuera de su
+    /// intervalo; los intervalos sin eventos no generan barra
+    fn roll_time(&mut self, time: f64) -> Vec<Bar> {
+        match (self.spec, &self.current) {
+            (BarSpec::Time(_), Some(bar)) if time >= bar.end => {
+                self.current.take().into_iter().collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Barra en curso, abriendo una nueva con el último mid si no la hay
+    fn bar_at(&mut self, time: f64) -> &mut Bar {
+        let spec = self.spec;
+        let (mid, bid, ask) = self.quote.unwrap_or((0.0, 0.0, 0.0));
+        let bar = self.current.get_or_insert_with(|| match spec {
+            BarSpec::Time(period) => {
+                let start = (time / period).floor() * period;
+                let mut bar = Bar::open_at(start, mid, bid, ask);
+                bar.end = start + period;
+                bar
+            }
+            _ => Bar::open_at(time, mid, bid, ask),
+        });
+        if !matches!(spec, BarSpec::Time(_)) {
+            bar.end = bar.end.max(time);
+        }
+        bar
+    }
+
+    fn close_if(&mut self, time: f64, full: impl Fn(&Bar) -> bool) -> Option<Bar> {
+        if !self.current.as_ref().is_some_and(full) {
+            return None;
+        }
+        let mut bar = self.current.take()?;
+        bar.end = bar.end.max(time);
+        Some(bar)
+    }
+}
+
+/// Columnas de la exportación, en orden
+const COLUMNS: [&str; 13] = [
+    "start",
+    "end",
+    "open",
+    "high",
+    "low",
+    "close",
+    "bid",
+    "ask",
+    "volume",
+    "dollar_volume",
+    "vwap",
+    "trades",
+    "ticks",
+];
+
+/// CSV con cabecera; `vwap` vacío en barras sin operaciones
+pub fn write_csv(path: &str, bars: &[Bar]) -> Result<(), Box<dyn Error>> {
+    let mut w = BufWriter::new(File::create(path)?);
+    writeln!(w, "{}", COLUMNS.join(","))?;
+    for b in bars {
+        writeln!(
+            w,
+            "{:.6},{:.6},{},{},{},{},{},{},{},{},{},{},{}",
+            b.start,
+            b.end,
+            b.open,
+            b.high,
+            b.low,
+            b.close,
+            b.bid,
+            b.ask,
+            b.volume,
+            b.dollar_volume,
+            b.vwap().map_or(String::new(), |v| v.to_string()),
+            b.trades,
+            b.ticks
+        )?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Parquet (un row group, compresión Snappy); `vwap` nulo en barras sin operaciones
+#[cfg(feature = "parquet")]
+pub fn write_parquet(path: &str, bars: &[Bar]) -> Result<(), Box<dyn Error>> {
+    use parquet::basic::Compression;
+    use parquet::data_type::{DoubleType, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+    use std::sync::Arc;
+
+    let fields: Vec<String> = COLUMNS
+        .iter()
+        .map(|c| match *c {
+            "vwap" => format!("OPTIONAL DOUBLE {};", c),
+            "trades" | "ticks" => format!("REQUIRED INT64 {};", c),
+            _ => format!("REQUIRED DOUBLE {};", c),
+        })
+        .collect();
+    let schema = Arc::new(parse_message_type(&format!(
+        "message bar {{ {} }}",
+        fields.join(" ")
+    ))?);
+    let props = Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build(),
+    );
+    let mut writer = SerializedFileWriter::new(File::create(path)?, schema, props)?;
+    let mut row_group = writer.next_row_group()?;
+    for name in COLUMNS {
+        let mut column = row_group
+            .next_column()?
+            .ok_or_else(|| format!("falta la columna {}", name))?;
+        match name {
+            "trades" | "ticks" => {
+                let values: Vec<i64> = bars
+                    .iter()
+                    .map(|b| if name == "trades" { b.trades } else { b.ticks } as i64)
+                    .collect();
+                column
+                    .typed::<Int64Type>()
+                    .write_batch(&values, None, None)?;
+            }
+            "vwap" => {
+                let values: Vec<f64> = bars.iter().filter_map(Bar::vwap).collect();
+                let levels: Vec<i16> = bars.iter().map(|b| b.vwap().is_some() as i16).collect();
+                column
+                    .typed::<DoubleType>()
+                    .write_batch(&values, Some(&levels), None)?;
+            }
+            _ => {
+                let values: Vec<f64> = bars.iter().map(|b| column_value(b, name)).collect();
+                column
+                    .typed::<DoubleType>()
+                    .write_batch(&values, None, None)?;
+            }
+        }
+        column.close()?;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+fn column_value(bar: &Bar, name: &str) -> f64 {
+    match name {
+        "start" => bar.start,
+        "end" => bar.end,
+        "open" => bar.open,
+        "high" => bar.high,
+        "low" => bar.low,
+        "close" => bar.close,
+        "bid" => bar.bid,
+        "ask" => bar.ask,
+        "volume" => bar.volume,
+        _ => bar.dollar_volume,
+    }
+}
+
+#[cfg(not(feature = "parquet"))]
+pub fn write_parquet(_path: &str, _bars: &[Bar]) -> Result<(), Box<dyn Error>> {
+    Err("exportación Parquet no disponible: compilar con --features parquet".into())
+}
+
+/// Exporta según la extensión: .parquet o CSV
+pub fn export(path: &str, bars: &[Bar]) -> Result<(), Box<dyn Error>> {
+    if path.ends_with(".parquet") {
+        write_parquet(path, bars)
+    } else {
+        write_csv(path, bars)
+    }
+}
+
+/// Opciones de `bars`
+#[derive(Debug, Clone)]
+pub struct BarsOptions {
+    pub recording: String,
+    pub spec: BarSpec,
+    pub output: String,
+}
+
+impl BarsOptions {
+    /// `bars <grabación> [--bar 1m] [--out bars.csv|bars.parquet]`
+    pub fn from_args(args: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut opts = Self {
+            recording: String::new(),
+            spec: BarSpec::Time(60.0),
+            output: "bars.csv".to_string(),
+        };
+        let mut it = args.iter();
+        while let Some(arg) = it.next() {
+            if !arg.starts_with("--") {
+                opts.recording = arg.clone();
+                continue;
+            }
+            let value = it
+                .next()
+                .ok_or_else(|| format!("falta el valor de {}", arg))?;
+            match arg.as_str() {
+                "--bar" => {
+                    opts.spec =
+                        BarSpec::parse(value).ok_or_else(|| format!("barra inválida: {}", value))?
+                }
+                "--out" => opts.output = value.clone(),
+                _ => return Err(format!("opción desconocida: {}", arg).into()),
+            }
+        }
+        if opts.recording.is_empty() {
+            return Err("uso: bars <grabación> [--bar 1m] [--out bars.csv]".into());
+        }
+        Ok(opts)
+    }
+}
+
+/// Reproduce una grabación por el pipeline y exporta sus barras
+pub fn run(opts: &BarsOptions) -> Result<(), Box<dyn Error>> {
+    let messages = read_recording(&opts.recording)?;
+    let config = PipelineConfig {
+        bars: Some(opts.spec),
+        ..PipelineConfig::default()
+    };
+    let mut pipeline = MarketPipeline::new(&config, BayesianNetwork::default_edges());
+    let mut bars = Vec::new();
+    for (time, msg) in &messages {
+        if let Some(snap) = pipeline.on_message(msg, *time) {
+            bars.extend(snap.bars);
+        }
+    }
+    bars.extend(pipeline.bars.as_mut().and_then(BarAggregator::finish));
+    export(&opts.output, &bars)?;
+    info!(
+        "{} barras ({:?}) de {} mensajes exportadas a {}",
+        bars.len(),
+        opts.spec,
+        messages.len(),
+        opts.output
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bar_specs() {
+        assert_eq!(BarSpec::parse("1m"), Some(BarSpec::Time(60.0)));
+        assert_eq!(BarSpec::parse("tick:100"), Some(BarSpec::Tick(100)));
+        assert_eq!(BarSpec::parse("dollar:5e6"), Some(BarSpec::Dollar(5e6)));
+        assert_eq!(BarSpec::parse("tick:0"), None);
+        assert_eq!(BarSpec::parse("5x"), None);
+    }
+
+    #[test]
+    fn time_bars_are_aligned_and_close_on_the_next_interval() {
+        let mut bars = BarAggregator::new(BarSpec::Time(1.0));
+        for (t, bid, ask) in [(0.2, 1.0, 3.0), (0.5, 3.0, 5.0), (0.9, 0.0, 2.0)] {
+            assert!(bars.on_quote(t, bid, ask).is_empty());
+        }
+        let closed = bars.on_quote(1.3, 4.0, 6.0);
+        assert_eq!(closed.len(), 1);
+        let bar = &closed[0];
+        assert_eq!((bar.start, bar.end), (0.0, 1.0));
+        assert_eq!(
+            (bar.open, bar.high, bar.low, bar.close),
+            (2.0, 4.0, 1.0, 1.0)
+        );
+        assert_eq!((bar.bid, bar.ask, bar.ticks), (0.0, 2.0, 3));
+        assert_eq!(bars.current().map(|b| (b.start, b.open)), Some((1.0, 5.0)));
+        assert_eq!(bars.flush(2.0).map(|b| b.close), Some(5.0));
+    }
+
+    #[test]
+    fn volume_bars_split_a_large_trade() {
+        let mut bars = BarAggregator::new(BarSpec::Volume(100.0));
+        // Sin mid de referencia las operaciones no abren barra
+        assert!(bars.on_trade(0.0, 10.0, 50.0).is_empty());
+        assert!(bars.current().is_none());
+        bars.on_quote(0.5, 9.0, 11.0);
+        let closed = bars.on_trade(1.0, 10.0, 250.0);
+        assert_eq!(closed.len(), 2);
+        for bar in &closed {
+            assert_eq!(
+                (bar.volume, bar.dollar_volume, bar.trades),
+                (100.0, 1000.0, 1)
+            );
+            assert_eq!(bar.vwap(), Some(10.0));
+        }
+        let rest = bars.finish().unwrap();
+        assert_eq!((rest.volume, rest.trades), (50.0, 1));
+    }
+
+    #[test]
+    fn invalid_trades_are_ignored() {
+        let mut bars = BarAggregator::new(BarSpec::Dollar(1000.0));
+        bars.on_quote(0.0, 9.0, 11.0);
+        for (price, size) in [
+            (-10.0, 50.0),
+            (0.0, 50.0),
+            (f64::NAN, 50.0),
+            (10.0, f64::INFINITY),
+        ] {
+            assert!(bars.on_trade(1.0, price, size).is_empty());
+        }
+        assert_eq!(bars.current().map(|b| (b.trades, b.volume)), Some((0, 0.0)));
+    }
+
+    #[test]
+    fn tick_bars_count_quotes_and_trades() {
+        let mut bars = BarAggregator::new(BarSpec::Tick(3));
+        bars.on_quote(0.0, 1.0, 3.0);
+        bars.on_trade(1.0, 2.0, 5.0);
+        let closed = bars.on_quote(2.0, 2.0, 4.0);
+        assert_eq!(closed.len(), 1);
+        assert_eq!(
+            (closed[0].ticks, closed[0].trades, closed[0].close),
+            (3, 1, 3.0)
+        );
+        assert_eq!((closed[0].start, closed[0].end), (0.0, 2.0));
+    }
+
+    /// Mensaje 35=X con entradas (lado, precio, volumen)
+    fn book_message(entries: &[(u8, f64, f64)]) -> String {
+        let body: String = entries
+            .iter()
+            .map(|(side, price, size)| format!("279=0|269={}|270={}|271={}|", side, price, size))
+            .collect();
+        format!("|35=X|55=1|{}", body)
+    }
+
+    #[test]
+    fn pipeline_samples_bars_only_on_top_of_book_changes() {
+        let config = PipelineConfig {
+            bars: Some(BarSpec::Tick(10)),
+            ..PipelineConfig::default()
+        };
+        let mut pipeline = MarketPipeline::new(&config, BayesianNetwork::default_edges());
+        let ticks = |p: &MarketPipeline| p.bars.as_ref().and_then(|b| b.current()).map(|b| b.ticks);
+        pipeline.on_message(&book_message(&[(0, 1.25, 100.0), (1, 1.5, 100.0)]), 0.0);
+        assert_eq!(ticks(&pipeline), Some(1));
+        // Solo profundidad o volumen del mejor nivel: el mejor bid/ask no cambia
+        pipeline.on_message(&book_message(&[(0, 1.0, 50.0), (1, 1.5, 70.0)]), 1.0);
+        assert_eq!(ticks(&pipeline), Some(1));
+        pipeline.on_message(&book_message(&[(0, 1.375, 10.0)]), 2.0);
+        assert_eq!(ticks(&pipeline), Some(2));
+    }
+}
@@ -1,95 +1,858 @@
-#[derive(Debug)]
+use crate::regimes::RegimeEdges;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum MarketState {
     Low,
     Normal,
     High,
 }
 
-pub struct BayesianNetwork {
-    pub context_threshold: f64,
+impl MarketState {
+    /// Etiqueta un bin según su posición relativa entre `n_bins`
+    /// (con 3 bins: 0 = Low, 1 = Normal, 2 = High)
+    pub fn from_bin(bin: usize, n_bins: usize) -> Self {
+        match bin * 3 / n_bins.max(1) {
+            0 => MarketState::Low,
+            1 => MarketState::Normal,
+            _ => MarketState::High,
+        }
+    }
 }
 
-impl BayesianNetwork {
-    pub fn new(threshold: f64) -> Self {
+/// Discretizador por puntos de corte: `cuts` ordenados de menor a mayor.
+/// Con 2 cortes produce 3 bins (Low/Normal/High).
+#[derive(Debug, Clone)]
+pub struct Discretizer {
+    pub cuts: Vec<f64>,
+}
+
+impl Discretizer {
+    pub fn new(cuts: Vec<f64>) -> Self {
+        Self { cuts }
+    }
+
+    pub fn cardinality(&self) -> usize {
+        self.cuts.len() + 1
+    }
+
+    pub fn bin(&self, value: f64) -> usize {
+        self.cuts.iter().take_while(|&&c| value >= c).count()
+    }
+}
+
+/// Tabla de Probabilidad Condicional P(X | Padres) con prior de Dirichlet.
+/// Guarda pseudo-conteos: prior (alpha) + observaciones reales.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cpt {
+    pub card: usize,
+    pub parent_cards: Vec<usize>,
+    pub counts: Vec<f64>,
+}
+
+impl Cpt {
+    /// CPT con prior de Dirichlet simétrico `alpha` en cada fila
+    pub fn new(card: usize, parent_cards: Vec<usize>, alpha: f64) -> Self {
+        let rows: usize = parent_cards.iter().product();
         Self {
-            context_threshold: threshold,
+            card,
+            parent_cards,
+            counts: vec![alpha; rows * card],
         }
     }
 
-    fn discretize_spread(&self, spread: f64) -> MarketState {
-        if spread < 1.5 {
-            MarketState::Low
-        } else if spread < 4.0 {
-            MarketState::Normal
-        } else {
-            MarketState::High
+    fn row_offset(&self, parents: &[usize]) -> usize {
+        let mut row = 0;
+        for (&p, &c) in parents.iter().zip(&self.parent_cards) {
+            row = row * c + p;
         }
+        row * self.card
     }
 
-    fn discretize_velocity(&self, velocity: f64) -> MarketState {
-        if velocity < 5.0 {
-            MarketState::Low
-        } else if velocity < 25.0 {
-            MarketState::Normal
-        } else {
-            MarketState::High
+    /// Reemplaza el prior de una fila (pseudo-conteos de Dirichlet)
+    pub fn set_prior(&mut self, parents: &[usize], alpha: &[f64]) {
+        let off = self.row_offset(parents);
+        self.counts[off..off + self.card].copy_from_slice(alpha);
+    }
+
+    /// Media posterior de Dirichlet: P(X = value | padres)
+    pub fn prob(&self, value: usize, parents: &[usize]) -> f64 {
+        let off = self.row_offset(parents);
+        let row = &self.counts[off..off + self.card];
+        row[value] / row.iter().sum::<f64>()
+    }
+
+    /// Actualización Bayesiana conjugada: un conteo más en la celda observada
+    pub fn observe(&mut self, value: usize, parents: &[usize]) {
+        let off = self.row_offset(parents);
+        self.counts[off + value] += 1.0;
+    }
+
+    /// Dimensiones de la tabla: padres y después la propia variable
+    fn dims(&self) -> Vec<usize> {
+        let mut dims = self.parent_cards.clone();
+        dims.push(self.card);
+        dims
+    }
+
+    /// Suma a `self` las observaciones de `old` (conteos menos su `prior`), llevadas
+    /// a las cardinalidades de `self` con `maps[d][bin viejo] = [(bin nuevo, peso)]`
+    fn add_remapped(&mut self, old: &Cpt, prior: &Cpt, maps: &[Vec<Vec<(usize, f64)>>]) {
+        let (old_dims, new_dims) = (old.dims(), self.dims());
+        for (cell, (&count, &alpha)) in old.counts.iter().zip(&prior.counts).enumerate() {
+            let observed = count - alpha;
+            if observed <= 0.0 {
+                continue;
+            }
+            // Índice multidimensional de la celda (orden de fila: el último varía antes)
+            let mut index = vec![0; old_dims.len()];
+            let mut rest = cell;
+            for d in (0..old_dims.len()).rev() {
+                index[d] = rest % old_dims[d];
+                rest /= old_dims[d];
+            }
+            // Producto cartesiano de los destinos de cada dimensión
+            let mut targets = vec![(0usize, observed)];
+            for (d, &bin) in index.iter().enumerate() {
+                let card = new_dims[d];
+                targets = targets
+                    .iter()
+                    .flat_map(|&(offset, weight)| {
+                        maps[d][bin]
+                            .iter()
+                            .map(move |&(k, w)| (offset * card + k, weight * w))
+                    })
+                    .collect();
+            }
+            for (offset, weight) in targets {
+                self.counts[offset] += weight;
+            }
         }
     }
+}
+
+/// Reparto de `from` bins en `to` bins por solape de sus rangos de cuantiles
+/// (el bin j de n cubre [j/n, (j+1)/n]); identidad si no cambia la cardinalidad
+fn quantile_map(from: usize, to: usize) -> Vec<Vec<(usize, f64)>> {
+    (0..from)
+        .map(|j| {
+            let (lo, hi) = (j as f64 / from as f64, (j + 1) as f64 / from as f64);
+            (0..to)
+                .filter_map(|k| {
+                    let overlap = (hi.min((k + 1) as f64 / to as f64)
+                        - lo.max(k as f64 / to as f64))
+                    .max(0.0);
+                    (overlap > 1e-12).then_some((k, overlap * from as f64))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Conteos aprendidos de las CPTs (prior + observaciones), persistidos junto a
+/// los cortes de los regímenes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkCounts {
+    pub spread: Cpt,
+    pub velocity: Cpt,
+    pub intensity: Cpt,
+    pub imbalance: Cpt,
+    pub favorable: Cpt,
+}
+
+/// Muestra registrada para el aprendizaje de las CPTs
+#[derive(Debug, Clone, Copy)]
+pub struct ContextSample {
+    /// Spread en ticks
+    pub spread: f64,
+    pub velocity: f64,
+    pub intensity: f64,
+    /// Imbalance agregado de profundidad (ver `depth_imbalance`)
+    pub imbalance: f64,
+    /// Régimen más probable del HMM cuando se registró (None sin detector)
+    pub regime: Option<usize>,
+    pub favorable: bool,
+}
+
+impl ContextSample {
+    /// Resultado de la entrada: favorable si el movimiento, en ticks, cubre el spread
+    pub fn resolve(&mut self, entry: f64, exit: f64, tick_size: f64) {
+        self.favorable = (exit - entry).abs() / tick_size > self.spread;
+    }
+}
 
-    /// Nueva discretización para la Intensidad (Liquidez)
-    fn discretize_intensity(&self, intensity: f64) -> MarketState {
-        if intensity < 100000.0 {
-            MarketState::Low
-        } else if intensity < 1000000.0 {
-            MarketState::Normal
-        } else {
-            MarketState::High
+/// Agrega el vector de `OrderBook::get_depth_vector` en un único imbalance,
+/// ponderando cada nivel por 1/nivel (el nivel 1 pesa más).
+pub fn depth_imbalance(depth: &[f64]) -> f64 {
+    let (num, den) = depth
+        .iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(num, den), (lvl, &imb)| {
+            let w = 1.0 / (lvl + 1) as f64;
+            (num + w * imb, den + w)
+        });
+    if den == 0.0 {
+        0.0
+    } else {
+        num / den
+    }
+}
+
+/// Evidencia discreta (bins) de cada nodo. `None` = nodo no observado.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Evidence {
+    pub spread: Option<usize>,
+    pub velocity: Option<usize>,
+    pub intensity: Option<usize>,
+    pub imbalance: Option<usize>,
+}
+
+/// Contribución de un nodo al score: P(F | toda la evidencia) - P(F | evidencia sin el nodo)
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeContribution {
+    pub node: &'static str,
+    pub value: f64,
+    pub bin: usize,
+    pub state: MarketState,
+    pub contribution: f64,
+}
+
+/// Explicación del score de contexto
+#[derive(Debug, Clone, Serialize)]
+pub struct ContextExplanation {
+    pub score: f64,
+    /// P(F = 1) sin evidencia (tasa base aprendida)
+    pub base_rate: f64,
+    pub contributions: Vec<NodeContribution>,
+}
+
+/// Red Bayesiana discreta:
+///   Spread, Velocity, Intensity (raíces)
+///   Imbalance | Intensity, Spread
+///   Favorable | Spread, Velocity, Intensity, Imbalance
+/// El score de contexto es P(Favorable = 1 | evidencia), calculado por enumeración exacta.
+pub struct BayesianNetwork {
+    pub context_threshold: f64,
+    pub spread_bins: Discretizer,
+    pub velocity_bins: Discretizer,
+    pub intensity_bins: Discretizer,
+    /// Cortes sobre |imbalance| agregado (acotado en [0, 1], no necesita ser adaptativo)
+    pub imbalance_bins: Discretizer,
+    spread_cpt: Cpt,
+    velocity_cpt: Cpt,
+    intensity_cpt: Cpt,
+    imbalance_cpt: Cpt,
+    favorable_cpt: Cpt,
+}
+
+impl BayesianNetwork {
+    /// Fuerza del prior experto (número equivalente de observaciones por fila)
+    const PRIOR_STRENGTH: f64 = 10.0;
+
+    pub fn new(threshold: f64) -> Self {
+        let edges = Self::default_edges();
+        Self::with_bins(
+            threshold,
+            Discretizer::new(edges.spread),
+            Discretizer::new(edges.velocity),
+            Discretizer::new(edges.intensity),
+            Discretizer::new(vec![0.3, 0.7]),
+        )
+    }
+
+    /// Cortes absolutos originales (un instrumento, unidades de volumen del broker)
+    pub fn default_edges() -> RegimeEdges {
+        RegimeEdges {
+            spread: vec![1.5, 4.0],
+            velocity: vec![5.0, 25.0],
+            intensity: vec![100000.0, 1000000.0],
         }
     }
 
-    pub fn compute_context_score(
-        &self,
-        spread: f64,
-        velocity: f64,
-        imbalance: f64,
-        intensity: f64,
-    ) -> f64 {
-        let s_state = self.discretize_spread(spread);
-        let v_state = self.discretize_velocity(velocity);
-        let i_state = self.discretize_intensity(intensity);
+    pub fn with_bins(
+        threshold: f64,
+        spread_bins: Discretizer,
+        velocity_bins: Discretizer,
+        intensity_bins: Discretizer,
+        imbalance_bins: Discretizer,
+    ) -> Self {
+        let (s, v, i, b) = (
+            spread_bins.cardinality(),
+            velocity_bins.cardinality(),
+            intensity_bins.cardinality(),
+            imbalance_bins.cardinality(),
+        );
+        let mut favorable_cpt = Cpt::new(2, vec![s, v, i, b], 1.0);
+
+        // Prior experto: las reglas originales (+0.15, -0.25, ...) fijan la media
+        // del Dirichlet, de modo que sin datos el score coincide con el anterior.
+        for sb in 0..s {
+            for vb in 0..v {
+                for ib in 0..i {
+                    for bb in 0..b {
+                        let p = Self::expert_prior(
+                            MarketState::from_bin(sb, s),
+                            MarketState::from_bin(vb, v),
+                            MarketState::from_bin(ib, i),
+                            MarketState::from_bin(bb, b),
+                        );
+                        let alpha = [
+                            (1.0 - p).max(0.01) * Self::PRIOR_STRENGTH,
+                            p.max(0.01) * Self::PRIOR_STRENGTH,
+                        ];
+                        favorable_cpt.set_prior(&[sb, vb, ib, bb], &alpha);
+                    }
+                }
+            }
+        }
+
+        Self {
+            context_threshold: threshold,
+            spread_cpt: Cpt::new(s, vec![], 1.0),
+            velocity_cpt: Cpt::new(v, vec![], 1.0),
+            intensity_cpt: Cpt::new(i, vec![], 1.0),
+            imbalance_cpt: Cpt::new(b, vec![i, s], 1.0),
+            favorable_cpt,
+            spread_bins,
+            velocity_bins,
+            intensity_bins,
+            imbalance_bins,
+        }
+    }
 
+    /// Reglas heurísticas originales, usadas solo como media del prior
+    fn expert_prior(s: MarketState, v: MarketState, i: MarketState, b: MarketState) -> f64 {
         let mut score: f64 = 0.5; // Punto de partida neutral
 
         // REGLA 1: Spread (Costo de entrada/salida)
-        match s_state {
+        match s {
             MarketState::Low => score += 0.15,
             MarketState::Normal => score += 0.05,
             MarketState::High => score -= 0.25,
         }
 
         // REGLA 2: Velocidad (Actividad del mercado)
-        match v_state {
+        match v {
             MarketState::Low => score -= 0.15, // Demasiado lento, sin momentum
             MarketState::Normal => score += 0.10, // Actividad ideal
             MarketState::High => score -= 0.10, // Posible sobre-reacción o ruido
         }
 
         // REGLA 3: Intensidad (Respaldo de la Liquidez)
-        match i_state {
+        match i {
             MarketState::Low => score -= 0.20, // Libro "delgado", peligro de manipulación
             MarketState::Normal => score += 0.05,
             MarketState::High => score += 0.15, // Mercado institucional profundo
         }
 
-        // REGLA CAUSAL COMBINADA: Imbalance en libro vacío es falso
-        if imbalance.abs() > 0.7 && intensity < 200000.0 {
+        // REGLA CAUSAL COMBINADA: Imbalance extremo en libro vacío es falso
+        if b == MarketState::High && i == MarketState::Low {
             score -= 0.20;
         }
 
         score.clamp(0.0, 1.0)
     }
 
+    /// Discretiza las observaciones continuas en evidencia completa
+    pub fn evidence(&self, spread: f64, velocity: f64, depth: &[f64], intensity: f64) -> Evidence {
+        Evidence {
+            spread: Some(self.spread_bins.bin(spread)),
+            velocity: Some(self.velocity_bins.bin(velocity)),
+            intensity: Some(self.intensity_bins.bin(intensity)),
+            imbalance: Some(self.imbalance_bins.bin(depth_imbalance(depth).abs())),
+        }
+    }
+
+    /// Inferencia exacta por enumeración: P(Favorable = 1 | evidencia).
+    /// Los nodos sin evidencia (`None`) se marginalizan con sus CPTs.
+    pub fn posterior_favorable(&self, evidence: &Evidence) -> f64 {
+        let candidates = |e: Option<usize>, card: usize| -> Vec<usize> {
+            match e {
+                Some(b) => vec![b],
+                None => (0..card).collect(),
+            }
+        };
+
+        let mut joint_fav = 0.0;
+        let mut joint_all = 0.0;
+        for s in candidates(evidence.spread, self.spread_cpt.card) {
+            for v in candidates(evidence.velocity, self.velocity_cpt.card) {
+                for i in candidates(evidence.intensity, self.intensity_cpt.card) {
+                    for b in candidates(evidence.imbalance, self.imbalance_cpt.card) {
+                        let p_parents = self.spread_cpt.prob(s, &[])
+                            * self.velocity_cpt.prob(v, &[])
+                            * self.intensity_cpt.prob(i, &[])
+                            * self.imbalance_cpt.prob(b, &[i, s]);
+                        joint_fav += p_parents * self.favorable_cpt.prob(1, &[s, v, i, b]);
+                        joint_all += p_parents;
+                    }
+                }
+            }
+        }
+
+        if joint_all == 0.0 {
+            return 0.5;
+        }
+        joint_fav / joint_all
+    }
+
+    /// `depth` es el vector de `OrderBook::get_depth_vector` (nivel 1 primero)
+    pub fn compute_context_score(
+        &self,
+        spread: f64,
+        velocity: f64,
+        depth: &[f64],
+        intensity: f64,
+    ) -> f64 {
+        self.posterior_favorable(&self.evidence(spread, velocity, depth, intensity))
+    }
+
+    /// Score con la contribución de cada nodo (para explicar un rechazo).
+    /// La contribución es el cambio en P(F = 1) al retirar la evidencia del nodo.
+    pub fn explain_context(
+        &self,
+        spread: f64,
+        velocity: f64,
+        depth: &[f64],
+        intensity: f64,
+    ) -> ContextExplanation {
+        let full = self.evidence(spread, velocity, depth, intensity);
+        let score = self.posterior_favorable(&full);
+
+        let nodes: [(&'static str, f64, usize, usize, Evidence); 4] = [
+            (
+                "spread",
+                spread,
+                full.spread.unwrap_or(0),
+                self.spread_cpt.card,
+                Evidence {
+                    spread: None,
+                    ..full
+                },
+            ),
+            (
+                "velocity",
+                velocity,
+                full.velocity.unwrap_or(0),
+                self.velocity_cpt.card,
+                Evidence {
+                    velocity: None,
+                    ..full
+                },
+            ),
+            (
+                "intensity",
+                intensity,
+                full.intensity.unwrap_or(0),
+                self.intensity_cpt.card,
+                Evidence {
+                    intensity: None,
+                    ..full
+                },
+            ),
+            (
+                "imbalance",
+                depth_imbalance(depth),
+                full.imbalance.unwrap_or(0),
+                self.imbalance_cpt.card,
+                Evidence {
+                    imbalance: None,
+                    ..full
+                },
+            ),
+        ];
+
+        let contributions = nodes
+            .into_iter()
+            .map(|(node, value, bin, card, without)| NodeContribution {
+                node,
+                value,
+                bin,
+                state: MarketState::from_bin(bin, card),
+                contribution: score - self.posterior_favorable(&without),
+            })
+            .collect();
+
+        ContextExplanation {
+            score,
+            base_rate: self.posterior_favorable(&Evidence::default()),
+            contributions,
+        }
+    }
+
+    /// Sustituye los cortes de los nodos. Si las cardinalidades no cambian, las
+    /// CPTs aprendidas se conservan (los bins son cuantiles: mismo régimen relativo);
+    /// si cambian, la red parte del prior experto de la nueva forma y recibe las
+    /// observaciones anteriores repartidas por solape de cuantiles.
+    pub fn set_bins(&mut self, edges: &RegimeEdges) {
+        let spread_bins = Discretizer::new(edges.spread.clone());
+        let velocity_bins = Discretizer::new(edges.velocity.clone());
+        let intensity_bins = Discretizer::new(edges.intensity.clone());
+
+        let same_shape = spread_bins.cardinality() == self.spread_bins.cardinality()
+            && velocity_bins.cardinality() == self.velocity_bins.cardinality()
+            && intensity_bins.cardinality() == self.intensity_bins.cardinality();
+
+        if same_shape {
+            self.spread_bins = spread_bins;
+            self.velocity_bins = velocity_bins;
+            self.intensity_bins = intensity_bins;
+        } else {
+            let mut rebuilt = Self::with_bins(
+                self.context_threshold,
+                spread_bins,
+                velocity_bins,
+                intensity_bins,
+                self.imbalance_bins.clone(),
+            );
+            rebuilt.add_observations_from(self);
+            *self = rebuilt;
+        }
+    }
+
+    /// Suma las observaciones de `old` (sus conteos menos su prior experto)
+    fn add_observations_from(&mut self, old: &Self) {
+        let prior = Self::with_bins(
+            old.context_threshold,
+            old.spread_bins.clone(),
+            old.velocity_bins.clone(),
+            old.intensity_bins.clone(),
+            old.imbalance_bins.clone(),
+        );
+        let map = |from: &Discretizer, to: &Discretizer| {
+            quantile_map(from.cardinality(), to.cardinality())
+        };
+        let s = map(&old.spread_bins, &self.spread_bins);
+        let v = map(&old.velocity_bins, &self.velocity_bins);
+        let i = map(&old.intensity_bins, &self.intensity_bins);
+        let b = map(&old.imbalance_bins, &self.imbalance_bins);
+        let favorable = quantile_map(2, 2);
+
+        self.spread_cpt
+            .add_remapped(&old.spread_cpt, &prior.spread_cpt, std::slice::from_ref(&s));
+        self.velocity_cpt.add_remapped(
+            &old.velocity_cpt,
+            &prior.velocity_cpt,
+            std::slice::from_ref(&v),
+        );
+        self.intensity_cpt.add_remapped(
+            &old.intensity_cpt,
+            &prior.intensity_cpt,
+            std::slice::from_ref(&i),
+        );
+        self.imbalance_cpt.add_remapped(
+            &old.imbalance_cpt,
+            &prior.imbalance_cpt,
+            &[i.clone(), s.clone(), b.clone()],
+        );
+        self.favorable_cpt.add_remapped(
+            &old.favorable_cpt,
+            &prior.favorable_cpt,
+            &[s, v, i, b, favorable],
+        );
+    }
+
+    /// Conteos aprendidos para persistirlos
+    pub fn counts(&self) -> NetworkCounts {
+        NetworkCounts {
+            spread: self.spread_cpt.clone(),
+            velocity: self.velocity_cpt.clone(),
+            intensity: self.intensity_cpt.clone(),
+            imbalance: self.imbalance_cpt.clone(),
+            favorable: self.favorable_cpt.clone(),
+        }
+    }
+
+    /// Restaura conteos guardados con los cortes actuales; si su forma no coincide
+    /// con la de la red los rechaza
+    pub fn restore_counts(&mut self, counts: NetworkCounts) -> Result<(), String> {
+        let expected = self.counts();
+        let pairs = [
+            (&counts.spread, &expected.spread),
+            (&counts.velocity, &expected.velocity),
+            (&counts.intensity, &expected.intensity),
+            (&counts.imbalance, &expected.imbalance),
+            (&counts.favorable, &expected.favorable),
+        ];
+        for (saved, current) in pairs {
+            if saved.dims() != current.dims() || saved.counts.len() != current.counts.len() {
+                return Err(format!(
+                    "CPT {:?} guardada, la red espera {:?}",
+                    saved.dims(),
+                    current.dims()
+                ));
+            }
+        }
+        self.spread_cpt = counts.spread;
+        self.velocity_cpt = counts.velocity;
+        self.intensity_cpt = counts.intensity;
+        self.imbalance_cpt = counts.imbalance;
+        self.favorable_cpt = counts.favorable;
+        Ok(())
+    }
+
+    /// Aprende de un resultado observado (conteo conjugado en todas las CPTs)
+    pub fn observe(&mut self, sample: &ContextSample) {
+        let s = self.spread_bins.bin(sample.spread);
+        let v = self.velocity_bins.bin(sample.velocity);
+        let i = self.intensity_bins.bin(sample.intensity);
+        let b = self.imbalance_bins.bin(sample.imbalance.abs());
+
+        self.spread_cpt.observe(s, &[]);
+        self.velocity_cpt.observe(v, &[]);
+        self.intensity_cpt.observe(i, &[]);
+        self.imbalance_cpt.observe(b, &[i, s]);
+        self.favorable_cpt
+            .observe(usize::from(sample.favorable), &[s, v, i, b]);
+    }
+
+    /// Aprendizaje por lotes sobre resultados grabados
+    pub fn learn(&mut self, samples: &[ContextSample]) {
+        for sample in samples {
+            self.observe(sample);
+        }
+    }
+
     pub fn is_context_favorable(&self, score: f64) -> bool {
         score >= self.context_threshold
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evidence(spread: Option<usize>, velocity: Option<usize>) -> Evidence {
+        Evidence {
+            spread,
+            velocity,
+            intensity: Some(0),
+            imbalance: Some(1),
+        }
+    }
+
+    /// Red de dos bins por nodo con P(s = 0) = 0.75, P(v = 0) = 0.5 y
+    /// P(F = 1 | s, v) = 0.1 + 0.6·[s = 0] + 0.2·[v = 0] (aditiva en s y v)
+    fn small_network() -> BayesianNetwork {
+        let half = || Discretizer::new(vec![0.0]);
+        let mut net = BayesianNetwork::with_bins(0.5, half(), half(), half(), half());
+        net.spread_cpt.counts = vec![3.0, 1.0];
+        for (s, v, i, b) in (0..16).map(|k| (k & 1, (k >> 1) & 1, (k >> 2) & 1, k >> 3)) {
+            let p = 0.1 + 0.6 * f64::from(s == 0) + 0.2 * f64::from(v == 0);
+            net.favorable_cpt
+                .set_prior(&[s, v, i, b], &[10.0 * (1.0 - p), 10.0 * p]);
+        }
+        net
+    }
+
+    #[test]
+    fn resolve_compares_the_move_in_ticks_with_the_spread() {
+        let mut sample = ContextSample {
+            spread: 2.0,
+            velocity: 0.0,
+            intensity: 0.0,
+            imbalance: 0.0,
+            regime: None,
+            favorable: false,
+        };
+        // 3 ticks de 0.5 cubren un spread de 2 ticks; 0.75 son 1.5 ticks
+        sample.resolve(10.0, 8.5, 0.5);
+        assert!(sample.favorable);
+        sample.resolve(10.0, 10.75, 0.5);
+        assert!(!sample.favorable);
+    }
+
+    #[test]
+    fn discretizer_bins_include_the_cut() {
+        let bins = Discretizer::new(vec![1.5, 4.0]);
+        assert_eq!(bins.cardinality(), 3);
+        let found: Vec<usize> = [0.0, 1.5, 3.9, 4.0, 9.0]
+            .iter()
+            .map(|&x| bins.bin(x))
+            .collect();
+        assert_eq!(found, vec![0, 1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn dirichlet_counts_update_the_posterior_mean() {
+        let mut cpt = Cpt::new(2, vec![3], 1.0);
+        cpt.observe(1, &[2]);
+        cpt.observe(1, &[2]);
+        assert_eq!(cpt.prob(1, &[2]), 0.75);
+        // Las demás filas conservan el prior uniforme
+        assert_eq!(cpt.prob(1, &[0]), 0.5);
+        assert_eq!(cpt.counts.iter().sum::<f64>(), 8.0);
+    }
+
+    #[test]
+    fn posterior_enumeration_matches_hand_computation() {
+        let net = small_network();
+        let close = |e: Evidence, p: f64| (net.posterior_favorable(&e) - p).abs() < 1e-12;
+        assert!(close(evidence(Some(0), Some(1)), 0.7));
+        // Spread marginalizado: 0.75 · 0.7 + 0.25 · 0.1
+        assert!(close(evidence(None, Some(1)), 0.55));
+        // Sin evidencia: 0.75 · 0.8 + 0.25 · 0.2
+        assert!(close(Evidence::default(), 0.65));
+    }
+
+    #[test]
+    fn observations_move_the_score_towards_the_outcome() {
+        let mut net = small_network();
+        let sample = ContextSample {
+            spread: 1.0,
+            velocity: 1.0,
+            intensity: 1.0,
+            imbalance: -0.5,
+            regime: None,
+            favorable: true,
+        };
+        for _ in 0..10 {
+            net.observe(&sample);
+        }
+        // Fila (1, 1, 1, 1): conteos 9 + 0 frente a 1 + 10
+        let full = Evidence {
+            spread: Some(1),
+            velocity: Some(1),
+            intensity: Some(1),
+            imbalance: Some(1),
+        };
+        assert!((net.posterior_favorable(&full) - 11.0 / 20.0).abs() < 1e-12);
+        assert_eq!(net.spread_cpt.prob(1, &[]), 11.0 / 14.0);
+        // El imbalance se discretiza en valor absoluto
+        assert_eq!(net.imbalance_cpt.prob(1, &[1, 1]), 11.0 / 12.0);
+    }
+
+    #[test]
+    fn contributions_are_the_evidence_removal_deltas() {
+        let net = small_network();
+        // s = 0, v = 0 (bins por encima/debajo del corte 0.0)
+        let explanation = net.explain_context(-1.0, -1.0, &[0.5], -1.0);
+        assert!((explanation.score - 0.9).abs() < 1e-12);
+        assert!((explanation.base_rate - 0.65).abs() < 1e-12);
+        let by_node: Vec<(&str, f64)> = explanation
+            .contributions
+            .iter()
+            .map(|c| (c.node, c.contribution))
+            .collect();
+        // Spread: 0.9 - (0.75 · 0.9 + 0.25 · 0.3); velocidad: 0.9 - 0.8
+        let expected = [
+            ("spread", 0.15),
+            ("velocity", 0.1),
+            ("intensity", 0.0),
+            ("imbalance", 0.0),
+        ];
+        for ((node, found), (name, value)) in by_node.iter().zip(expected) {
+            assert_eq!(*node, name);
+            assert!((found - value).abs() < 1e-12, "{}: {}", node, found);
+        }
+        // Con una CPT aditiva las contribuciones suman score - tasa base
+        let total: f64 = by_node.iter().map(|(_, c)| c).sum();
+        assert!((total - (explanation.score - explanation.base_rate)).abs() < 1e-12);
+        let spread = &explanation.contributions[0];
+        assert_eq!((spread.bin, spread.state), (0, MarketState::Low));
+    }
+
+    #[test]
+    fn depth_imbalance_weights_the_first_level() {
+        // Pesos 1 y 1/2: (1 - 0.5) / 1.5
+        assert!((depth_imbalance(&[1.0, -1.0]) - 1.0 / 3.0).abs() < 1e-15);
+        assert_eq!(depth_imbalance(&[]), 0.0);
+    }
+
+    #[test]
+    fn expert_prior_is_the_score_without_data() {
+        let net = BayesianNetwork::new(0.6);
+        // Spread bajo, velocidad normal, intensidad alta: 0.5 + 0.15 + 0.10 + 0.15
+        let score = net.compute_context_score(1.0, 10.0, &[0.0], 2e6);
+        assert!((score - 0.9).abs() < 1e-12);
+        assert!(net.is_context_favorable(score));
+        // Imbalance extremo con libro delgado: 0.5 + 0.15 + 0.10 - 0.20 - 0.20
+        let thin = net.compute_context_score(1.0, 10.0, &[-0.9], 1.0);
+        assert!((thin - 0.35).abs() < 1e-12);
+    }
+
+    /// Observaciones acumuladas en una CPT (conteos menos los de su prior)
+    fn observed(cpt: &Cpt, prior: &Cpt) -> f64 {
+        cpt.counts
+            .iter()
+            .zip(&prior.counts)
+            .map(|(c, a)| c - a)
+            .sum()
+    }
+
+    fn trained_network(n: usize) -> BayesianNetwork {
+        let mut net = BayesianNetwork::new(0.45);
+        for k in 0..n {
+            net.observe(&ContextSample {
+                spread: [1.0, 2.0, 6.0][k % 3],
+                velocity: [2.0, 30.0][k % 2],
+                intensity: 5e5,
+                imbalance: [-0.6, 0.0, 0.6][k % 3],
+                regime: None,
+                favorable: k % 4 != 0,
+            });
+        }
+        net
+    }
+
+    #[test]
+    fn quantile_map_splits_bins_by_overlap() {
+        assert_eq!(quantile_map(2, 2), vec![vec![(0, 1.0)], vec![(1, 1.0)]]);
+        assert_eq!(quantile_map(2, 4)[1], vec![(2, 0.5), (3, 0.5)]);
+        assert_eq!(quantile_map(4, 2)[1], vec![(0, 1.0)]);
+        for row in quantile_map(3, 5) {
+            let total: f64 = row.iter().map(|(_, w)| w).sum();
+            assert!((total - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn set_bins_keeps_or_remaps_the_observations() {
+        let mut net = trained_network(12);
+        let before = net.counts();
+        // Mismas cardinalidades: los conteos no cambian
+        let mut edges = BayesianNetwork::default_edges();
+        edges.spread = vec![1.0, 5.0];
+        net.set_bins(&edges);
+        assert_eq!(net.counts(), before);
+
+        // Spread en 4 bins: prior experto nuevo más las 12 observaciones repartidas
+        edges.spread = vec![1.0, 2.0, 5.0];
+        net.set_bins(&edges);
+        let mut prior = BayesianNetwork::new(0.45);
+        prior.set_bins(&edges);
+        let (counts, prior) = (net.counts(), prior.counts());
+        assert_eq!(counts.spread.card, 4);
+        for (cpt, alpha) in [
+            (&counts.spread, &prior.spread),
+            (&counts.velocity, &prior.velocity),
+            (&counts.intensity, &prior.intensity),
+            (&counts.imbalance, &prior.imbalance),
+            (&counts.favorable, &prior.favorable),
+        ] {
+            assert!((observed(cpt, alpha) - 12.0).abs() < 1e-9);
+        }
+        let score = net.posterior_favorable(&Evidence::default());
+        assert!(score > 0.0 && score < 1.0);
+    }
+
+    #[test]
+    fn restore_counts_round_trips_and_rejects_other_shapes() {
+        let trained = trained_network(8);
+        let mut fresh = BayesianNetwork::new(0.45);
+        fresh.restore_counts(trained.counts()).unwrap();
+        assert_eq!(fresh.counts(), trained.counts());
+        assert_eq!(
+            fresh.posterior_favorable(&Evidence::default()),
+            trained.posterior_favorable(&Evidence::default())
+        );
+
+        let mut edges = BayesianNetwork::default_edges();
+        edges.velocity = vec![5.0];
+        let mut other = BayesianNetwork::new(0.45);
+        other.set_bins(&edges);
+        let err = other.restore_counts(trained.counts()).unwrap_err();
+        assert!(err.starts_with("CPT"), "{}", err);
+        assert_eq!(other.counts().velocity.card, 2);
+    }
+}
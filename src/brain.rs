@@ -1,93 +1,999 @@
-use ndarray::{Array1, Array2};
+use crate::features::FeatureSchema;
+use crate::optim::{clip_global_norm, LrSchedule, Optimizer, ParamState};
+use log::warn;
+use ndarray::{concatenate, s, Array1, Array2, Axis};
 use ndarray_rand::RandomExt;
-use rand_distr::Normal;
-use std::f64::consts::E;
+use rand_distr::{Normal, StandardNormal};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{E, PI};
 
+/// Activación de las capas ocultas
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Activation {
+    Identity,
+    Sigmoid,
+    Tanh,
+    Relu,
+    Gelu,
+}
+
+impl Activation {
+    /// "identity", "sigmoid", "tanh", "relu", "gelu"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "identity" => Some(Activation::Identity),
+            "sigmoid" => Some(Activation::Sigmoid),
+            "tanh" => Some(Activation::Tanh),
+            "relu" => Some(Activation::Relu),
+            "gelu" => Some(Activation::Gelu),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, z: f64) -> f64 {
+        match self {
+            Activation::Identity => z,
+            Activation::Sigmoid => sigmoid(z),
+            Activation::Tanh => z.tanh(),
+            Activation::Relu => z.max(0.0),
+            Activation::Gelu => 0.5 * z * (1.0 + gelu_inner(z).tanh()),
+        }
+    }
+
+    /// Derivada respecto a la pre-activación z
+    fn derivative(&self, z: f64) -> f64 {
+        match self {
+            Activation::Identity => 1.0,
+            Activation::Sigmoid => {
+                let s = sigmoid(z);
+                s * (1.0 - s)
+            }
+            Activation::Tanh => 1.0 - z.tanh().powi(2),
+            Activation::Relu => {
+                if z > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Activation::Gelu => {
+                let t = gelu_inner(z).tanh();
+                let du = (2.0 / PI).sqrt() * (1.0 + 3.0 * 0.044715 * z * z);
+                0.5 * (1.0 + t) + 0.5 * z * (1.0 - t * t) * du
+            }
+        }
+    }
+}
+
+/// Activación de la capa de salida. Sigmoid y Softmax se entrenan con entropía
+/// cruzada, cuyo gradiente respecto a los logits es (p - y).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OutputActivation {
+    /// Una o varias salidas binarias independientes
+    Sigmoid,
+    /// Distribución categórica sobre `output_dim` clases
+    Softmax,
+    /// Regresión heterocedástica: salidas (media, log-varianza), pérdida NLL Gaussiana.
+    /// Requiere `output_dim = 2`.
+    Gaussian,
+}
+
+/// Predicción de la cabeza Gaussiana
+#[derive(Debug, Clone, Copy)]
+pub struct RegressionPrediction {
+    pub mean: f64,
+    /// Ruido inherente del mercado (media de las varianzas predichas)
+    pub aleatoric_var: f64,
+    /// Desacuerdo entre muestras de pesos (varianza de las medias)
+    pub epistemic_var: f64,
+}
+
+impl RegressionPrediction {
+    pub fn total_var(&self) -> f64 {
+        self.aleatoric_var + self.epistemic_var
+    }
+}
+
+/// Rango de la log-varianza de la cabeza Gaussiana (estabilidad numérica)
+const LOG_VAR_RANGE: (f64, f64) = (-20.0, 10.0);
+
+/// Inicialización de las medias de los pesos
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Init {
+    /// N(0, std²) fijo (el esquema original usaba 0.1)
+    Normal(f64),
+    /// He: N(0, 2 / fan_in), para ReLU/GELU
+    He,
+    /// Xavier/Glorot: N(0, 2 / (fan_in + fan_out)), para sigmoid/tanh
+    Xavier,
+}
+
+impl Init {
+    /// "normal[:std]" (0.1 por defecto), "he", "xavier"
+    pub fn parse(spec: &str) -> Option<Self> {
+        match spec.split_once(':') {
+            Some(("normal", std)) => std
+                .parse()
+                .ok()
+                .filter(|s: &f64| s.is_finite() && *s > 0.0)
+                .map(Init::Normal),
+            Some(_) => None,
+            None => match spec {
+                "normal" => Some(Init::Normal(0.1)),
+                "he" => Some(Init::He),
+                "xavier" => Some(Init::Xavier),
+                _ => None,
+            },
+        }
+    }
+
+    fn std(&self, fan_in: usize, fan_out: usize) -> f64 {
+        match self {
+            Init::Normal(std) => *std,
+            Init::He => (2.0 / fan_in as f64).sqrt(),
+            Init::Xavier => (2.0 / (fan_in + fan_out) as f64).sqrt(),
+        }
+    }
+}
+
+/// Arquitectura e hiperparámetros de la red
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BrainConfig {
+    pub input_dim: usize,
+    /// Tamaño de cada capa oculta, en orden
+    pub hidden: Vec<usize>,
+    pub activation: Activation,
+    pub output_dim: usize,
+    pub output: OutputActivation,
+    pub use_bias: bool,
+    pub init: Init,
+    pub learning_rate: f64,
+    pub optimizer: Optimizer,
+    pub schedule: LrSchedule,
+    /// Penalización L2 sobre las medias de los pesos
+    pub weight_decay: f64,
+    /// Norma global máxima del gradiente (None = sin recorte)
+    pub clip_norm: Option<f64>,
+    /// Varianza inicial de cada peso
+    pub init_variance: f64,
+    /// Desviación del prior N(0, sigma²) de cada peso
+    pub prior_sigma: f64,
+    /// Peso del término KL por muestra (≈ 1 / tamaño efectivo del dataset)
+    pub kl_weight: f64,
+    /// Muestras Monte Carlo para la predicción
+    pub mc_samples: usize,
+    /// Columnas de entrada esperadas (None en checkpoints anteriores al esquema)
+    #[serde(default)]
+    pub schema: Option<FeatureSchema>,
+}
+
+impl BrainConfig {
+    /// Arquitectura original: una capa oculta sigmoide, sin bias, salida sigmoide
+    pub fn legacy(input_dim: usize, hidden_dim: usize, lr: f64) -> Self {
+        Self {
+            input_dim,
+            hidden: vec![hidden_dim],
+            activation: Activation::Sigmoid,
+            output_dim: 1,
+            output: OutputActivation::Sigmoid,
+            use_bias: false,
+            init: Init::Normal(0.1),
+            learning_rate: lr,
+            optimizer: Optimizer::sgd(),
+            schedule: LrSchedule::Constant,
+            weight_decay: 0.0,
+            clip_norm: None,
+            init_variance: 0.05,
+            prior_sigma: 1.0,
+            kl_weight: 1e-3,
+            mc_samples: 30,
+            schema: None,
+        }
+    }
+
+    /// Tamaños de las capas ocultas separados por comas ("32,16")
+    pub fn parse_hidden(spec: &str) -> Option<Vec<usize>> {
+        spec.split(',')
+            .map(|n| n.trim().parse().ok().filter(|&n: &usize| n > 0))
+            .collect()
+    }
+
+    /// Fija el esquema de entrada; la dimensión de entrada pasa a ser la suya
+    pub fn with_schema(mut self, schema: FeatureSchema) -> Self {
+        self.input_dim = schema.len();
+        self.schema = Some(schema);
+        self
+    }
+}
+
+/// Capa lineal Bayesiana (Bayes by Backprop):
+/// cada peso es una Normal q(w) = N(mu, sigma²) con sigma = softplus(rho).
+/// Con bias, la última fila de la matriz son los pesos del bias (entrada constante 1).
+#[derive(Clone, Serialize, Deserialize)]
+struct BayesLinear {
+    mu: Array2<f64>,
+    rho: Array2<f64>,
+    use_bias: bool,
+    /// Momentos del optimizador: Adam/RMSProp continúan donde quedaron al cargar
+    #[serde(default)]
+    mu_state: ParamState,
+    #[serde(default)]
+    rho_state: ParamState,
+}
+
+/// Gradientes de la pérdida total respecto a (mu, rho) de una capa
+struct LayerGrads {
+    mu: Array2<f64>,
+    rho: Array2<f64>,
+}
+
+impl BayesLinear {
+    fn new(
+        input_dim: usize,
+        output_dim: usize,
+        use_bias: bool,
+        init: Init,
+        init_variance: f64,
+    ) -> Self {
+        let rows = input_dim + usize::from(use_bias);
+        let std = init.std(input_dim, output_dim);
+        let mut mu = Array2::random((rows, output_dim), Normal::new(0.0, std).unwrap());
+        if use_bias {
+            mu.row_mut(input_dim).fill(0.0);
+        }
+        let rho0 = softplus_inv(init_variance.sqrt());
+        Self {
+            mu,
+            rho: Array2::from_elem((rows, output_dim), rho0),
+            use_bias,
+            mu_state: ParamState::default(),
+            rho_state: ParamState::default(),
+        }
+    }
+
+    fn sigma(&self) -> Array2<f64> {
+        self.rho.mapv(softplus)
+    }
+
+    fn variance(&self) -> Array2<f64> {
+        self.sigma().mapv(|s| s * s)
+    }
+
+    /// Añade la columna constante del bias si corresponde (filas = muestras)
+    fn augment(&self, x: &Array2<f64>) -> Array2<f64> {
+        if self.use_bias {
+            concatenate![Axis(1), x.view(), Array2::ones((x.nrows(), 1)).view()]
+        } else {
+            x.clone()
+        }
+    }
+
+    /// Truco de reparametrización: w = mu + sigma * eps, eps ~ N(0, 1)
+    fn sample(&self) -> (Array2<f64>, Array2<f64>) {
+        let eps = Array2::random(self.mu.raw_dim(), StandardNormal);
+        let w = &self.mu + &(self.sigma() * &eps);
+        (w, eps)
+    }
+
+    /// Gradientes sobre (mu, rho) de la pérdida
+    /// L = -log p(y | w) + kl_weight * KL(q(w) || N(0, prior_sigma²)) + L2 sobre mu
+    fn gradients(
+        &self,
+        grad_w: &Array2<f64>,
+        eps: &Array2<f64>,
+        kl_weight: f64,
+        prior_sigma: f64,
+        weight_decay: f64,
+    ) -> LayerGrads {
+        let prior_var = prior_sigma * prior_sigma;
+        let sigma = self.sigma();
+
+        // dKL/dmu = mu / sp²  ;  dKL/dsigma = -1/sigma + sigma / sp²
+        let grad_mu = grad_w + &(&self.mu * (kl_weight / prior_var + weight_decay));
+        let kl_sigma = sigma.mapv(|s| -1.0 / s + s / prior_var);
+        let grad_sigma = grad_w * eps + &(kl_sigma * kl_weight);
+        // dsigma/drho = sigmoid(rho)
+        let grad_rho = grad_sigma * &self.rho.mapv(sigmoid);
+
+        LayerGrads {
+            mu: grad_mu,
+            rho: grad_rho,
+        }
+    }
+
+    fn apply(&mut self, grads: &LayerGrads, optimizer: &Optimizer, lr: f64, step: u64) {
+        optimizer.apply(&mut self.mu, &grads.mu, &mut self.mu_state, lr, step);
+        optimizer.apply(&mut self.rho, &grads.rho, &mut self.rho_state, lr, step);
+    }
+}
+
+/// Valores intermedios de un forward para el backpropagation (filas = muestras)
+struct ForwardTrace {
+    /// Entrada (aumentada con bias) de cada capa
+    inputs: Vec<Array2<f64>>,
+    /// Pre-activación de cada capa oculta
+    pre_activations: Vec<Array2<f64>>,
+    output: Array2<f64>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct BayesianBrain {
-    // Pesos (W1: Input -> Hidden, W2: Hidden -> Output)
-    weights1: Array2<f64>,
-    weights2: Array1<f64>,
-    // Incertidumbre de los pesos (Varianza)
-    variance1: Array2<f64>,
-    variance2: Array1<f64>,
-    learning_rate: f64,
+    config: BrainConfig,
+    layers: Vec<BayesLinear>,
+    /// Pasos de optimización realizados (para Adam y el calendario de LR)
+    step: u64,
+    /// Multiplicador temporal del LR (factor, hasta el paso) tras un cambio de régimen
+    #[serde(skip)]
+    lr_boost: Option<(f64, u64)>,
 }
 
 impl BayesianBrain {
     pub fn new(input_dim: usize, hidden_dim: usize, lr: f64) -> Self {
+        Self::build(BrainConfig::legacy(input_dim, hidden_dim, lr))
+    }
+
+    /// Red con la arquitectura de `config`, o el motivo por el que no es válida
+    pub fn from_config(config: BrainConfig) -> Result<Self, String> {
+        if let Some(schema) = &config.schema {
+            if schema.len() != config.input_dim {
+                return Err(format!(
+                    "el esquema de features ({}) no coincide con input_dim {}",
+                    schema.columns.join(", "),
+                    config.input_dim
+                ));
+            }
+        }
+        if config.input_dim == 0 || config.output_dim == 0 || config.hidden.contains(&0) {
+            return Err(format!(
+                "capas vacías: {} -> {:?} -> {}",
+                config.input_dim, config.hidden, config.output_dim
+            ));
+        }
+        if config.output == OutputActivation::Gaussian && config.output_dim != 2 {
+            return Err("la cabeza Gaussiana requiere output_dim = 2".to_string());
+        }
+        Ok(Self::build(config))
+    }
+
+    fn build(config: BrainConfig) -> Self {
+        let mut dims = vec![config.input_dim];
+        dims.extend(&config.hidden);
+        dims.push(config.output_dim);
+
+        let layers = dims
+            .windows(2)
+            .map(|w| {
+                BayesLinear::new(
+                    w[0],
+                    w[1],
+                    config.use_bias,
+                    config.init,
+                    config.init_variance,
+                )
+            })
+            .collect();
+
         Self {
-            weights1: Array2::random((input_dim, hidden_dim), Normal::new(0.0, 0.1).unwrap()),
-            weights2: Array1::random(hidden_dim, Normal::new(0.0, 0.1).unwrap()),
-            variance1: Array2::from_elem((input_dim, hidden_dim), 0.05),
-            variance2: Array1::from_elem(hidden_dim, 0.05),
-            learning_rate: lr,
+            config,
+            layers,
+            step: 0,
+            lr_boost: None,
         }
     }
 
-    /// Activación Sigmoide
-    fn sigmoid(&self, x: f64) -> f64 {
-        1.0 / (1.0 + E.powf(-x))
+    pub fn config(&self) -> &BrainConfig {
+        &self.config
     }
 
-    /// Derivada de Sigmoide para Backpropagation
-    fn sigmoid_derivative(&self, x: f64) -> f64 {
-        let s = self.sigmoid(x);
-        s * (1.0 - s)
+    pub fn schema(&self) -> Option<&FeatureSchema> {
+        self.config.schema.as_ref()
     }
 
-    /// Forward pass que devuelve (Predicción, Incertidumbre de la Red)
-    pub fn predict_with_uncertainty(&self, inputs: &Array1<f64>) -> (f64, f64) {
+    /// Tasa de aprendizaje vigente según el calendario
+    pub fn current_lr(&self) -> f64 {
+        let lr = self
+            .config
+            .schedule
+            .lr(self.config.learning_rate, self.step);
+        match self.lr_boost {
+            Some((factor, until)) if self.step < until => lr * factor,
+            _ => lr,
+        }
+    }
+
+    /// Multiplica el LR por `factor` durante los próximos `steps` pasos
+    pub fn boost_learning_rate(&mut self, factor: f64, steps: u64) {
+        self.lr_boost = Some((factor, self.step + steps));
+    }
+
+    /// Multiplica la varianza de todos los pesos por `factor`: la red vuelve a
+    /// "dudar" y aprende más rápido de los datos nuevos. Olvida los momentos del optimizador.
+    /// Sigma nunca supera la del prior: derivas repetidas no hacen crecer los pesos
+    /// sin límite.
+    pub fn inflate_variance(&mut self, factor: f64) {
+        let scale = factor.max(0.0).sqrt();
+        let max_sigma = self.config.prior_sigma;
+        for layer in self.layers.iter_mut() {
+            layer.rho.mapv_inplace(|r| {
+                let sigma = softplus(r);
+                softplus_inv((sigma * scale).min(max_sigma.max(sigma)))
+            });
+            layer.mu_state.reset();
+            layer.rho_state.reset();
+        }
+    }
+
+    /// Varianzas aprendidas de los pesos, una matriz por capa
+    pub fn variances(&self) -> Vec<Array2<f64>> {
+        self.layers.iter().map(|l| l.variance()).collect()
+    }
+
+    /// Forward vectorizado con pesos concretos (uno por capa)
+    fn forward(&self, inputs: &Array2<f64>, weights: &[Array2<f64>]) -> ForwardTrace {
+        let last = self.layers.len() - 1;
+        let mut trace = ForwardTrace {
+            inputs: Vec::with_capacity(self.layers.len()),
+            pre_activations: Vec::with_capacity(last),
+            output: Array2::zeros((0, 0)),
+        };
+
+        let mut a = inputs.clone();
+        for (idx, (layer, w)) in self.layers.iter().zip(weights).enumerate() {
+            let x = layer.augment(&a);
+            let mut z = x.dot(w);
+            trace.inputs.push(x);
+            if idx == last {
+                match self.config.output {
+                    OutputActivation::Sigmoid => z.mapv_inplace(sigmoid),
+                    OutputActivation::Softmax => {
+                        for mut row in z.rows_mut() {
+                            let p = softmax(&row.to_owned());
+                            row.assign(&p);
+                        }
+                    }
+                    OutputActivation::Gaussian => {
+                        let (lo, hi) = LOG_VAR_RANGE;
+                        z.column_mut(1).mapv_inplace(|v| v.clamp(lo, hi));
+                    }
+                }
+                trace.output = z;
+            } else {
+                a = z.mapv(|v| self.config.activation.apply(v));
+                trace.pre_activations.push(z);
+            }
+        }
+        trace
+    }
+
+    /// Salidas de `mc_samples` redes muestreadas (filas = muestras)
+    fn mc_outputs(&self, inputs: &Array1<f64>) -> Array2<f64> {
+        let x = inputs.clone().insert_axis(Axis(0));
+        let n = self.config.mc_samples.max(2);
+        let mut samples = Array2::zeros((n, self.config.output_dim));
+        for mut row in samples.rows_mut() {
+            let weights: Vec<Array2<f64>> = self.layers.iter().map(|l| l.sample().0).collect();
+            row.assign(&self.forward(&x, &weights).output.row(0));
+        }
+        samples
+    }
+
+    /// Predicción de la cabeza Gaussiana por Monte Carlo:
+    /// varianza total = E[sigma²] (aleatoria) + Var[mu] (epistémica)
+    pub fn predict_regression(&self, inputs: &Array1<f64>) -> RegressionPrediction {
+        if inputs.is_empty() || self.config.output != OutputActivation::Gaussian {
+            return RegressionPrediction {
+                mean: 0.0,
+                aleatoric_var: 1.0,
+                epistemic_var: 1.0,
+            };
+        }
+        let samples = self.mc_outputs(inputs);
+        let means = samples.column(0);
+        RegressionPrediction {
+            mean: means.mean().unwrap_or(0.0),
+            aleatoric_var: samples.column(1).mapv(f64::exp).mean().unwrap_or(1.0),
+            epistemic_var: means.var(1.0),
+        }
+    }
+
+    /// Distribución predictiva Monte Carlo: (probabilidades medias, incertidumbre epistémica)
+    ///
+    /// La incertidumbre es Σ Var_q[p_k] / Σ p̄_k (1 - p̄_k): la varianza de las
+    /// predicciones entre muestras de pesos, normalizada por el máximo posible
+    /// para esa media. 0 = todas las redes muestreadas coinciden; 1 = se reparten
+    /// entre los extremos. Con cabeza Gaussiana devuelve ([media, varianza total],
+    /// fracción epistémica de la varianza).
+    pub fn predict_distribution(&self, inputs: &Array1<f64>) -> (Array1<f64>, f64) {
+        let k = self.config.output_dim;
         if inputs.is_empty() {
-            return (0.5, 1.0);
+            return (Array1::from_elem(k, 1.0 / k as f64), 1.0);
         }
 
-        // Capa Oculta
-        let z1 = inputs.dot(&self.weights1);
-        let a1 = z1.mapv(|x| self.sigmoid(x));
+        if self.config.output == OutputActivation::Gaussian {
+            let pred = self.predict_regression(inputs);
+            let total = pred.total_var().max(1e-12);
+            return (
+                Array1::from_vec(vec![pred.mean, total]),
+                (pred.epistemic_var / total).clamp(0.0, 1.0),
+            );
+        }
 
-        // Salida
-        let z2 = a1.dot(&self.weights2);
-        let prediction = self.sigmoid(z2);
+        let samples = self.mc_outputs(inputs);
+        let mean = samples.mean_axis(Axis(0)).unwrap();
+        let var = samples.var_axis(Axis(0), 1.0);
+        let max_var = mean.mapv(|p| p * (1.0 - p)).sum().max(1e-12);
 
-        // Cálculo de Incertidumbre Epistémica (basado en la varianza de los pesos)
-        // A mayor varianza en los pesos internos, mayor incertidumbre en la salida
-        let uncertainty = self.variance1.sum() * 0.1 + self.variance2.sum() * 0.9;
+        (mean, (var.sum() / max_var).clamp(0.0, 1.0))
+    }
 
-        (prediction, uncertainty.clamp(0.0, 1.0))
+    /// Predicción Monte Carlo que devuelve (Score alcista en [0, 1], Incertidumbre Epistémica).
+    ///
+    /// - Sigmoid: P(sube).
+    /// - Softmax: índice de clase esperado normalizado, Σ p_k · k / (K - 1), con las
+    ///   clases ordenadas de bajista a alcista (todo "plano" da 0.5).
+    /// - Gaussian: P(retorno > 0) = Φ(media / sigma_total).
+    pub fn predict_with_uncertainty(&self, inputs: &Array1<f64>) -> (f64, f64) {
+        let (mean, uncertainty) = self.predict_distribution(inputs);
+        let score = match self.config.output {
+            OutputActivation::Sigmoid => mean[mean.len() - 1],
+            OutputActivation::Softmax => {
+                let k = mean.len().max(2);
+                mean.iter()
+                    .enumerate()
+                    .map(|(i, p)| p * i as f64 / (k - 1) as f64)
+                    .sum()
+            }
+            OutputActivation::Gaussian => normal_cdf(mean[0] / mean[1].sqrt()),
+        };
+        (score, uncertainty)
     }
 
-    /// Entrenamiento Online (Backpropagation Bayesiano simplificado)
+    /// Codifica un target binario (1.0 = sube) en el formato de la salida
+    fn encode_binary(&self, target: f64) -> Array1<f64> {
+        match self.config.output {
+            OutputActivation::Sigmoid => Array1::from_elem(self.config.output_dim, target),
+            OutputActivation::Softmax => {
+                let k = self.config.output_dim;
+                let mut one_hot = Array1::zeros(k);
+                one_hot[if target > 0.5 { k - 1 } else { 0 }] = 1.0;
+                one_hot
+            }
+            OutputActivation::Gaussian => Array1::from_vec(vec![target, 0.0]),
+        }
+    }
+
+    /// Entrenamiento Online binario (target 1.0 = sube, 0.0 = baja)
     pub fn train(&mut self, inputs: &Array1<f64>, target: f64) {
+        let targets = self.encode_binary(target);
+        self.train_targets(inputs, &targets);
+    }
+
+    /// Un paso con una sola muestra. `targets` tiene `output_dim` componentes
+    /// (one-hot con softmax).
+    pub fn train_targets(&mut self, inputs: &Array1<f64>, targets: &Array1<f64>) {
         if inputs.is_empty() {
             return;
         }
+        self.train_batch(
+            &inputs.clone().insert_axis(Axis(0)),
+            &targets.clone().insert_axis(Axis(0)),
+        );
+    }
 
-        // 1. Forward
-        let z1 = inputs.dot(&self.weights1);
-        let a1 = z1.mapv(|x| self.sigmoid(x));
-        let z2 = a1.dot(&self.weights2);
-        let prediction = self.sigmoid(z2);
+    /// Mini-batch de pares (features, target binario)
+    pub fn train_binary_batch(&mut self, inputs: &Array2<f64>, targets: &Array1<f64>) {
+        let mut encoded = Array2::zeros((targets.len(), self.config.output_dim));
+        for (mut row, &t) in encoded.rows_mut().into_iter().zip(targets) {
+            row.assign(&self.encode_binary(t));
+        }
+        self.train_batch(inputs, &encoded);
+    }
 
-        // 2. Cálculo del Error
-        let error = prediction - target;
+    /// Gradiente de la pérdida por muestra respecto a la última pre-activación
+    fn output_delta(&self, output: &Array2<f64>, targets: &Array2<f64>) -> Array2<f64> {
+        match self.config.output {
+            // Entropía cruzada: p - y
+            OutputActivation::Sigmoid | OutputActivation::Softmax => output - targets,
+            // NLL = ½ (s + (y - mu)² e^-s), con s = log sigma²
+            OutputActivation::Gaussian => {
+                let mut delta = Array2::zeros(output.raw_dim());
+                for ((mut d, o), t) in delta
+                    .rows_mut()
+                    .into_iter()
+                    .zip(output.rows())
+                    .zip(targets.rows())
+                {
+                    let inv_var = (-o[1]).exp();
+                    let err = o[0] - t[0];
+                    d[0] = err * inv_var;
+                    // Con la log-varianza recortada la salida no depende de s
+                    let (lo, hi) = LOG_VAR_RANGE;
+                    d[1] = if o[1] > lo && o[1] < hi {
+                        0.5 * (1.0 - err * err * inv_var)
+                    } else {
+                        0.0
+                    };
+                }
+                delta
+            }
+        }
+    }
 
-        // 3. Backpropagation para W2
-        let d_w2 = error * self.sigmoid_derivative(z2);
-        for i in 0..self.weights2.len() {
-            let grad = d_w2 * a1[i];
-            self.weights2[i] -= self.learning_rate * grad;
-            // Reducimos la varianza (ganamos confianza) si el error es pequeño
-            self.variance2[i] *= 0.99 + (error.abs() * 0.01);
+    /// Primera entrada no finita del batch, con el nombre de su columna si hay esquema
+    pub fn invalid_input(&self, inputs: &Array2<f64>) -> Option<String> {
+        let ((row, col), value) = inputs.indexed_iter().find(|(_, v)| !v.is_finite())?;
+        let name = self
+            .schema()
+            .map_or_else(|| format!("columna {}", col), |s| s.name(col).to_string());
+        Some(format!("{} = {} (fila {})", name, value, row))
+    }
+
+    /// Bayes by Backprop sobre un mini-batch (filas = muestras), con una muestra
+    /// de pesos compartida por el batch. La verosimilitud se promedia por muestra.
+    /// Un batch con entradas o targets no finitos se descarta sin tocar los pesos.
+    pub fn train_batch(&mut self, inputs: &Array2<f64>, targets: &Array2<f64>) {
+        let batch = inputs.nrows();
+        if batch == 0 || inputs.ncols() == 0 {
+            return;
+        }
+        if let Some(bad) = self.invalid_input(inputs) {
+            warn!("Batch descartado, entrada no finita: {}", bad);
+            return;
+        }
+        if targets.iter().any(|t| !t.is_finite()) {
+            warn!("Batch descartado, target no finito");
+            return;
         }
 
-        // 4. Backpropagation para W1
-        let d_z1 = d_w2 * &self.weights2;
-        for i in 0..self.weights1.nrows() {
-            for j in 0..self.weights1.ncols() {
-                let grad = d_z1[j] * self.sigmoid_derivative(z1[j]) * inputs[i];
-                self.weights1[[i, j]] -= self.learning_rate * grad;
-                self.variance1[[i, j]] *= 0.99 + (error.abs() * 0.01);
+        // 1. Muestreo de pesos y Forward
+        let (weights, eps): (Vec<_>, Vec<_>) = self.layers.iter().map(|l| l.sample()).unzip();
+        let trace = self.forward(inputs, &weights);
+
+        // 2. Gradiente de la pérdida respecto a las salidas lineales
+        let mut delta = self.output_delta(&trace.output, targets) / batch as f64;
+
+        // 3. Backpropagation capa a capa (de la salida hacia la entrada)
+        let mut grad_w = vec![Array2::zeros((0, 0)); self.layers.len()];
+        for idx in (0..self.layers.len()).rev() {
+            let x = &trace.inputs[idx];
+            grad_w[idx] = x.t().dot(&delta);
+
+            if idx > 0 {
+                // Gradiente hacia la capa anterior (sin la fila del bias)
+                let fan_in = x.ncols() - usize::from(self.layers[idx].use_bias);
+                let w_no_bias = weights[idx].slice(s![..fan_in, ..]);
+                let d_a = delta.dot(&w_no_bias.t());
+                let z = &trace.pre_activations[idx - 1];
+                delta = d_a * z.mapv(|v| self.config.activation.derivative(v));
             }
         }
+
+        // 4. Gradientes de (mu, rho): verosimilitud + KL + L2
+        let mut grads: Vec<LayerGrads> = self
+            .layers
+            .iter()
+            .zip(&grad_w)
+            .zip(&eps)
+            .map(|((layer, g), e)| {
+                layer.gradients(
+                    g,
+                    e,
+                    self.config.kl_weight,
+                    self.config.prior_sigma,
+                    self.config.weight_decay,
+                )
+            })
+            .collect();
+
+        // 5. Recorte por norma global
+        if let Some(max_norm) = self.config.clip_norm {
+            let mut all: Vec<&mut Array2<f64>> = grads
+                .iter_mut()
+                .flat_map(|g| [&mut g.mu, &mut g.rho])
+                .collect();
+            clip_global_norm(&mut all, max_norm);
+        }
+
+        // 6. Paso del optimizador
+        self.step += 1;
+        let lr = self.current_lr();
+        for (layer, g) in self.layers.iter_mut().zip(&grads) {
+            layer.apply(g, &self.config.optimizer, lr, self.step);
+        }
+    }
+}
+
+/// Activación Sigmoide
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + E.powf(-x))
+}
+
+/// Función de distribución de la Normal estándar (erf de Abramowitz-Stegun 7.1.26)
+fn normal_cdf(x: f64) -> f64 {
+    let z = x.abs() / 2f64.sqrt();
+    let t = 1.0 / (1.0 + 0.3275911 * z);
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf = 1.0 - poly * (-z * z).exp();
+    if x >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+/// Argumento de tanh en la aproximación de GELU
+fn gelu_inner(x: f64) -> f64 {
+    (2.0 / PI).sqrt() * (x + 0.044715 * x.powi(3))
+}
+
+/// Softmax numéricamente estable
+fn softmax(z: &Array1<f64>) -> Array1<f64> {
+    let max = z.fold(f64::NEG_INFINITY, |m, &v| m.max(v));
+    let exp = z.mapv(|v| (v - max).exp());
+    let sum = exp.sum();
+    exp / sum
+}
+
+/// softplus(x) = ln(1 + e^x), estable para x grande
+fn softplus(x: f64) -> f64 {
+    if x > 30.0 {
+        x
+    } else {
+        x.exp().ln_1p()
+    }
+}
+
+/// softplus^-1(sigma) = ln(e^sigma - 1)
+fn softplus_inv(sigma: f64) -> f64 {
+    if sigma > 30.0 {
+        sigma
+    } else {
+        sigma.exp_m1().max(1e-300).ln()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    const H: f64 = 1e-6;
+
+    /// Diferencia central de `f` respecto a cada elemento de `param`
+    fn numeric_grad(param: &Array2<f64>, f: impl Fn(&Array2<f64>) -> f64) -> Array2<f64> {
+        let mut grad = Array2::zeros(param.raw_dim());
+        for (idx, g) in grad.indexed_iter_mut() {
+            let mut plus = param.clone();
+            let mut minus = param.clone();
+            plus[idx] += H;
+            minus[idx] -= H;
+            *g = (f(&plus) - f(&minus)) / (2.0 * H);
+        }
+        grad
+    }
+
+    fn assert_close(a: &Array2<f64>, b: &Array2<f64>, tol: f64) {
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).abs() <= tol * (1.0 + y.abs()), "{} != {}", x, y);
+        }
+    }
+
+    /// Red casi determinista (sigma ≈ 1e-10) con LR 1 y sin KL: un paso resta
+    /// exactamente el gradiente de la verosimilitud a las medias
+    fn deterministic_brain(output: OutputActivation, output_dim: usize) -> BayesianBrain {
+        BayesianBrain::from_config(BrainConfig {
+            hidden: vec![4, 3],
+            activation: Activation::Tanh,
+            output_dim,
+            output,
+            use_bias: true,
+            init: Init::Normal(0.5),
+            learning_rate: 1.0,
+            init_variance: 1e-20,
+            kl_weight: 0.0,
+            ..BrainConfig::legacy(3, 4, 1.0)
+        })
+        .unwrap()
+    }
+
+    /// Entropía cruzada media del batch con las medias de los pesos
+    fn batch_loss(
+        brain: &BayesianBrain,
+        weights: &[Array2<f64>],
+        x: &Array2<f64>,
+        y: &Array2<f64>,
+    ) -> f64 {
+        let out = brain.forward(x, weights).output;
+        let total: f64 = out
+            .rows()
+            .into_iter()
+            .zip(y.rows())
+            .map(|(o, t)| match brain.config.output {
+                OutputActivation::Sigmoid => -o
+                    .iter()
+                    .zip(t)
+                    .map(|(p, t)| t * p.ln() + (1.0 - t) * (1.0 - p).ln())
+                    .sum::<f64>(),
+                OutputActivation::Softmax => -o.iter().zip(t).map(|(p, t)| t * p.ln()).sum::<f64>(),
+                OutputActivation::Gaussian => 0.5 * (o[1] + (t[0] - o[0]).powi(2) * (-o[1]).exp()),
+            })
+            .sum();
+        total / x.nrows() as f64
+    }
+
+    fn check_backprop(output: OutputActivation, y: Array2<f64>) {
+        let x = array![[0.5, -1.0, 0.3], [-0.2, 0.8, 1.1]];
+        let mut brain = deterministic_brain(output, y.ncols());
+        let mus: Vec<Array2<f64>> = brain.layers.iter().map(|l| l.mu.clone()).collect();
+        brain.train_batch(&x, &y);
+        for (idx, layer) in brain.layers.iter().enumerate() {
+            let analytic = &mus[idx] - &layer.mu;
+            let numeric = numeric_grad(&mus[idx], |p| {
+                let mut weights = mus.clone();
+                weights[idx] = p.clone();
+                batch_loss(&brain, &weights, &x, &y)
+            });
+            assert_close(&analytic, &numeric, 1e-5);
+        }
+    }
+
+    #[test]
+    fn backprop_matches_finite_differences_sigmoid() {
+        check_backprop(OutputActivation::Sigmoid, array![[1.0], [0.0]]);
+    }
+
+    #[test]
+    fn backprop_matches_finite_differences_softmax() {
+        check_backprop(
+            OutputActivation::Softmax,
+            array![[0.0, 1.0, 0.0], [1.0, 0.0, 0.0]],
+        );
+    }
+
+    #[test]
+    fn backprop_matches_finite_differences_gaussian() {
+        check_backprop(OutputActivation::Gaussian, array![[0.7, 0.0], [-0.4, 0.0]]);
+    }
+
+    #[test]
+    fn activation_derivatives_match_finite_differences() {
+        let activations = [
+            Activation::Identity,
+            Activation::Sigmoid,
+            Activation::Tanh,
+            Activation::Relu,
+            Activation::Gelu,
+        ];
+        for activation in activations {
+            for z in [-2.0, -0.3, 0.4, 1.7] {
+                let numeric = (activation.apply(z + H) - activation.apply(z - H)) / (2.0 * H);
+                assert!(
+                    (activation.derivative(z) - numeric).abs() < 1e-7,
+                    "{:?} en {}",
+                    activation,
+                    z
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn variational_gradients_match_finite_differences() {
+        // Pérdida lineal en w (dL/dw = c) + KL a N(0, sp²) + L2 sobre mu
+        let layer = BayesLinear {
+            mu: array![[0.3, -0.7], [1.2, 0.1]],
+            rho: array![[-1.0, 0.5], [-2.0, 0.0]],
+            use_bias: false,
+            mu_state: ParamState::default(),
+            rho_state: ParamState::default(),
+        };
+        let eps = array![[0.4, -1.3], [0.9, 0.2]];
+        let c = array![[0.5, -0.2], [0.1, 0.8]];
+        let (kl_weight, prior_sigma, decay) = (0.3, 0.8, 0.05);
+        let prior_var = prior_sigma * prior_sigma;
+        let loss = |mu: &Array2<f64>, rho: &Array2<f64>| -> f64 {
+            let sigma = rho.mapv(softplus);
+            let w = mu + &(&sigma * &eps);
+            let kl: f64 = mu
+                .iter()
+                .zip(&sigma)
+                .map(|(m, s)| (prior_sigma / s).ln() + (s * s + m * m) / (2.0 * prior_var) - 0.5)
+                .sum();
+            (&c * &w).sum() + kl_weight * kl + 0.5 * decay * mu.mapv(|m| m * m).sum()
+        };
+
+        let grads = layer.gradients(&c, &eps, kl_weight, prior_sigma, decay);
+        assert_close(
+            &grads.mu,
+            &numeric_grad(&layer.mu, |mu| loss(mu, &layer.rho)),
+            1e-7,
+        );
+        assert_close(
+            &grads.rho,
+            &numeric_grad(&layer.rho, |rho| loss(&layer.mu, rho)),
+            1e-7,
+        );
+    }
+
+    #[test]
+    fn near_zero_variance_gives_no_epistemic_uncertainty() {
+        let brain = deterministic_brain(OutputActivation::Softmax, 3);
+        let (mean, uncertainty) = brain.predict_distribution(&array![0.5, -1.0, 0.3]);
+        assert!(uncertainty < 1e-8, "incertidumbre {}", uncertainty);
+        assert!((mean.sum() - 1.0).abs() < 1e-12);
+        let (empty, _) = brain.predict_distribution(&array![]);
+        assert_eq!(empty, array![1.0, 1.0, 1.0] / 3.0);
+    }
+
+    #[test]
+    fn layers_follow_the_config() {
+        let brain = deterministic_brain(OutputActivation::Sigmoid, 1);
+        let shapes: Vec<_> = brain.layers.iter().map(|l| l.mu.dim()).collect();
+        // Una fila extra por el bias, inicializada a cero
+        assert_eq!(shapes, vec![(4, 4), (5, 3), (4, 1)]);
+        assert!(brain.layers[0].mu.row(3).iter().all(|&b| b == 0.0));
+        let legacy = BayesianBrain::new(3, 2, 0.1);
+        assert!(legacy.variances()[0]
+            .iter()
+            .all(|v| (v - 0.05).abs() < 1e-12));
+    }
+
+    #[test]
+    fn softplus_inverse_round_trips() {
+        for sigma in [1e-6, 0.05, 1.0, 40.0] {
+            assert!((softplus(softplus_inv(sigma)) - sigma).abs() < 1e-9 * sigma.max(1.0));
+        }
+    }
+
+    #[test]
+    fn from_config_rejects_invalid_architectures() {
+        let mut config = BrainConfig::legacy(3, 4, 0.01)
+            .with_schema(FeatureSchema::new(vec!["mid".into(), "ofi".into()]));
+        config.input_dim = 3;
+        let err = BayesianBrain::from_config(config).err().unwrap();
+        assert_eq!(
+            err,
+            "el esquema de features (mid, ofi) no coincide con input_dim 3"
+        );
+        let empty = BrainConfig {
+            hidden: vec![4, 0],
+            ..BrainConfig::legacy(3, 4, 0.01)
+        };
+        assert_eq!(
+            BayesianBrain::from_config(empty).err().unwrap(),
+            "capas vacías: 3 -> [4, 0] -> 1"
+        );
+        let gaussian = BrainConfig {
+            output: OutputActivation::Gaussian,
+            ..BrainConfig::legacy(3, 4, 0.01)
+        };
+        assert!(BayesianBrain::from_config(gaussian).is_err());
+    }
+
+    #[test]
+    fn clamped_log_variance_has_no_gradient() {
+        let brain = deterministic_brain(OutputActivation::Gaussian, 2);
+        let targets = array![[1.0, 0.0], [1.0, 0.0], [1.0, 0.0]];
+        let output = array![[0.5, 0.0], [0.5, LOG_VAR_RANGE.1], [0.5, LOG_VAR_RANGE.0]];
+        let delta = brain.output_delta(&output, &targets);
+        assert!((delta[[0, 1]] - 0.5 * (1.0 - 0.25)).abs() < 1e-15);
+        assert_eq!((delta[[1, 1]], delta[[2, 1]]), (0.0, 0.0));
+        // La media sigue recibiendo gradiente
+        assert!(delta[[1, 0]] < 0.0 && delta[[2, 0]] < 0.0);
+    }
+
+    #[test]
+    fn parse_layers_activations_and_init() {
+        assert_eq!(BrainConfig::parse_hidden("32, 16"), Some(vec![32, 16]));
+        assert_eq!(BrainConfig::parse_hidden("32,0"), None);
+        assert_eq!(BrainConfig::parse_hidden(""), None);
+        assert_eq!(Activation::from_name("gelu"), Some(Activation::Gelu));
+        assert_eq!(Activation::from_name("swish"), None);
+        assert_eq!(Init::parse("normal:0.05"), Some(Init::Normal(0.05)));
+        assert_eq!(Init::parse("he"), Some(Init::He));
+        assert_eq!(Init::parse("normal:0"), None);
+        assert_eq!(Init::parse("xavier:1"), None);
     }
 }
@@ -0,0 +1,408 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Probabilidades recortadas para que el log-loss sea finito
+const EPS: f64 = 1e-12;
+
+/// Evaluación online (prequential) de probabilidades sobre una ventana móvil:
+/// cada par (p, y) se registra antes de entrenar con la muestra.
+/// `y` puede ser fraccional (p.ej. 0.5 = plano en tres clases).
+pub struct OnlineMetrics {
+    window_size: usize,
+    samples: VecDeque<(f64, f64)>,
+    total_seen: u64,
+}
+
+/// Cubeta del diagrama de fiabilidad
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReliabilityBin {
+    pub lower: f64,
+    pub upper: f64,
+    pub mean_prob: f64,
+    pub observed_freq: f64,
+    pub count: usize,
+}
+
+/// Resumen de la salud del modelo en la ventana actual
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationReport {
+    pub samples: usize,
+    pub total_seen: u64,
+    pub log_loss: f64,
+    pub brier: f64,
+    /// None si la ventana no contiene ambas clases
+    pub auc: Option<f64>,
+    /// Error de calibración esperado (media ponderada de |p - frecuencia|)
+    pub ece: f64,
+    pub reliability: Vec<ReliabilityBin>,
+}
+
+impl OnlineMetrics {
+    pub fn new(window_size: usize) -> Self {
+        Self {
+            window_size,
+            samples: VecDeque::with_capacity(window_size),
+            total_seen: 0,
+        }
+    }
+
+    pub fn observe(&mut self, prob: f64, outcome: f64) {
+        if self.samples.len() >= self.window_size {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((prob, outcome));
+        self.total_seen += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn log_loss(&self) -> f64 {
+        let sum: f64 = self
+            .samples
+            .iter()
+            .map(|&(p, y)| {
+                let p = p.clamp(EPS, 1.0 - EPS);
+                -(y * p.ln() + (1.0 - y) * (1.0 - p).ln())
+            })
+            .sum();
+        sum / self.samples.len().max(1) as f64
+    }
+
+    pub fn brier(&self) -> f64 {
+        let sum: f64 = self.samples.iter().map(|&(p, y)| (p - y).powi(2)).sum();
+        sum / self.samples.len().max(1) as f64
+    }
+
+    /// AUC por Mann-Whitney (rangos medios en empates). Positivos: y > 0.5,
+    /// negativos: y < 0.5; los planos no cuentan.
+    pub fn auc(&self) -> Option<f64> {
+        let mut scored: Vec<(f64, bool)> = self
+            .samples
+            .iter()
+            .filter(|&&(_, y)| y != 0.5)
+            .map(|&(p, y)| (p, y > 0.5))
+            .collect();
+        let n_pos = scored.iter().filter(|s| s.1).count();
+        let n_neg = scored.len() - n_pos;
+        if n_pos == 0 || n_neg == 0 {
+            return None;
+        }
+        scored.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut rank_sum = 0.0;
+        let mut i = 0;
+        while i < scored.len() {
+            let mut j = i;
+            while j + 1 < scored.len() && scored[j + 1].0 == scored[i].0 {
+                j += 1;
+            }
+            // Rango medio (1-based) del grupo empatado
+            let rank = (i + j) as f64 / 2.0 + 1.0;
+            rank_sum += rank * scored[i..=j].iter().filter(|s| s.1).count() as f64;
+            i = j + 1;
+        }
+        let u = rank_sum - (n_pos * (n_pos + 1)) as f64 / 2.0;
+        Some(u / (n_pos * n_neg) as f64)
+    }
+
+    /// Diagrama de fiabilidad con `n_bins` cubetas iguales en [0, 1]
+    pub fn reliability(&self, n_bins: usize) -> Vec<ReliabilityBin> {
+        let n_bins = n_bins.max(1);
+        let mut sums = vec![(0.0, 0.0, 0usize); n_bins];
+        for &(p, y) in &self.samples {
+            let b = ((p * n_bins as f64) as usize).min(n_bins - 1);
+            sums[b].0 += p;
+            sums[b].1 += y;
+            sums[b].2 += 1;
+        }
+        sums.iter()
+            .enumerate()
+            .map(|(b, &(sp, sy, n))| ReliabilityBin {
+                lower: b as f64 / n_bins as f64,
+                upper: (b + 1) as f64 / n_bins as f64,
+                mean_prob: if n > 0 { sp / n as f64 } else { 0.0 },
+                observed_freq: if n > 0 { sy / n as f64 } else { 0.0 },
+                count: n,
+            })
+            .collect()
+    }
+
+    pub fn report(&self, n_bins: usize) -> CalibrationReport {
+        let reliability = self.reliability(n_bins);
+        let n = self.samples.len().max(1) as f64;
+        let ece = reliability
+            .iter()
+            .map(|b| b.count as f64 / n * (b.mean_prob - b.observed_freq).abs())
+            .sum();
+        CalibrationReport {
+            samples: self.samples.len(),
+            total_seen: self.total_seen,
+            log_loss: self.log_loss(),
+            brier: self.brier(),
+            auc: self.auc(),
+            ece,
+            reliability,
+        }
+    }
+}
+
+/// Recalibración opcional de la probabilidad de salida
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Calibrator {
+    None,
+    /// Platt: p' = sigmoid(a * logit(p) + b), ajustado online por SGD
+    Platt {
+        a: f64,
+        b: f64,
+        learning_rate: f64,
+    },
+    /// Regresión isotónica (PAV) sobre la ventana, reajustada cada `refit_every` muestras
+    Isotonic(Isotonic),
+}
+
+impl Calibrator {
+    /// Nombre en configuración (CALIBRATION): none, platt, isotonic
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Calibrator::None),
+            "platt" => Some(Calibrator::Platt {
+                a: 1.0,
+                b: 0.0,
+                learning_rate: 0.01,
+            }),
+            "isotonic" => Some(Calibrator::Isotonic(Isotonic::new(2000, 200))),
+            _ => None,
+        }
+    }
+
+    pub fn calibrate(&self, prob: f64) -> f64 {
+        match self {
+            Calibrator::None => prob,
+            Calibrator::Platt { a, b, .. } => sigmoid(a * logit(prob) + b),
+            Calibrator::Isotonic(iso) => iso.calibrate(prob),
+        }
+    }
+
+    /// Aprende de la probabilidad cruda y el resultado observado
+    pub fn observe(&mut self, raw_prob: f64, outcome: f64) {
+        match self {
+            Calibrator::None => {}
+            Calibrator::Platt {
+                a,
+                b,
+                learning_rate,
+            } => {
+                // Gradiente de la entropía cruzada respecto a (a, b)
+                let x = logit(raw_prob);
+                let err = sigmoid(*a * x + *b) - outcome;
+                *a -= *learning_rate * err * x;
+                *b -= *learning_rate * err;
+            }
+            Calibrator::Isotonic(iso) => iso.observe(raw_prob, outcome),
+        }
+    }
+}
+
+/// Mapa isotónico creciente p -> frecuencia, por pool-adjacent-violators
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Isotonic {
+    window_size: usize,
+    refit_every: usize,
+    /// Nodos (p, frecuencia) del mapa ajustado, ordenados por p
+    knots: Vec<(f64, f64)>,
+    #[serde(skip)]
+    samples: VecDeque<(f64, f64)>,
+    #[serde(skip)]
+    since_refit: usize,
+}
+
+impl Isotonic {
+    pub fn new(window_size: usize, refit_every: usize) -> Self {
+        Self {
+            window_size,
+            refit_every: refit_every.max(1),
+            knots: Vec::new(),
+            samples: VecDeque::with_capacity(window_size),
+            since_refit: 0,
+        }
+    }
+
+    fn observe(&mut self, prob: f64, outcome: f64) {
+        if self.samples.len() >= self.window_size {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((prob, outcome));
+        self.since_refit += 1;
+        if self.since_refit >= self.refit_every {
+            self.refit();
+            self.since_refit = 0;
+        }
+    }
+
+    fn refit(&mut self) {
+        let mut sorted: Vec<(f64, f64)> = self.samples.iter().copied().collect();
+        sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Bloques (suma de p, suma de y, n); se fusionan mientras violen la monotonía
+        let mut blocks: Vec<(f64, f64, f64)> = Vec::with_capacity(sorted.len());
+        for (p, y) in sorted {
+            blocks.push((p, y, 1.0));
+            while blocks.len() > 1 {
+                let (p2, y2, n2) = blocks[blocks.len() - 1];
+                let (p1, y1, n1) = blocks[blocks.len() - 2];
+                if y1 / n1 <= y2 / n2 {
+                    break;
+                }
+                blocks.pop();
+                *blocks.last_mut().unwrap() = (p1 + p2, y1 + y2, n1 + n2);
+            }
+        }
+        self.knots = blocks.iter().map(|&(p, y, n)| (p / n, y / n)).collect();
+    }
+
+    /// Interpolación lineal entre nodos; identidad hasta el primer ajuste
+    fn calibrate(&self, prob: f64) -> f64 {
+        let (Some(first), Some(last)) = (self.knots.first(), self.knots.last()) else {
+            return prob;
+        };
+        if prob <= first.0 {
+            return first.1;
+        }
+        if prob >= last.0 {
+            return last.1;
+        }
+        let idx = self.knots.partition_point(|k| k.0 <= prob);
+        let (x0, y0) = self.knots[idx - 1];
+        let (x1, y1) = self.knots[idx];
+        if x1 == x0 {
+            return y1;
+        }
+        y0 + (y1 - y0) * (prob - x0) / (x1 - x0)
+    }
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+fn logit(p: f64) -> f64 {
+    let p = p.clamp(1e-6, 1.0 - 1e-6);
+    (p / (1.0 - p)).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fitted(samples: &[(f64, f64)]) -> Isotonic {
+        let mut iso = Isotonic::new(100, samples.len());
+        for &(p, y) in samples {
+            iso.observe(p, y);
+        }
+        iso
+    }
+
+    #[test]
+    fn pav_pools_adjacent_violators() {
+        let iso = fitted(&[(0.3, 0.0), (0.1, 0.0), (0.4, 1.0), (0.2, 1.0)]);
+        assert_eq!(iso.knots, vec![(0.1, 0.0), (0.25, 0.5), (0.4, 1.0)]);
+        assert!((iso.calibrate(0.325) - 0.75).abs() < 1e-12);
+        assert_eq!(iso.calibrate(0.05), 0.0);
+        assert_eq!(iso.calibrate(0.9), 1.0);
+    }
+
+    #[test]
+    fn pav_merges_backwards_until_monotone() {
+        let iso = fitted(&[(0.1, 1.0), (0.2, 1.0), (0.3, 0.0)]);
+        assert_eq!(iso.knots.len(), 1);
+        assert!((iso.knots[0].0 - 0.2).abs() < 1e-12);
+        assert!((iso.knots[0].1 - 2.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn isotonic_is_identity_until_fitted() {
+        let iso = Isotonic::new(100, 10);
+        assert_eq!(iso.calibrate(0.37), 0.37);
+    }
+
+    #[test]
+    fn platt_step_follows_the_cross_entropy_gradient() {
+        let mut platt = Calibrator::Platt {
+            a: 1.0,
+            b: 0.0,
+            learning_rate: 0.1,
+        };
+        // logit(0.5) = 0: solo se mueve el sesgo, b -= 0.1 * (0.5 - 1)
+        platt.observe(0.5, 1.0);
+        let Calibrator::Platt { a, b, .. } = platt else {
+            unreachable!()
+        };
+        assert_eq!(a, 1.0);
+        assert!((b - 0.05).abs() < 1e-12);
+    }
+
+    #[test]
+    fn platt_recovers_a_known_miscalibration() {
+        // Frecuencias reales sigmoid(2 logit(p) - 0.5): el SGD converge a (2, -0.5)
+        let mut platt = Calibrator::Platt {
+            a: 1.0,
+            b: 0.0,
+            learning_rate: 0.05,
+        };
+        for _ in 0..4000 {
+            for i in 1..20 {
+                let p = i as f64 / 20.0;
+                platt.observe(p, sigmoid(2.0 * logit(p) - 0.5));
+            }
+        }
+        let Calibrator::Platt { a, b, .. } = platt else {
+            unreachable!()
+        };
+        assert!(
+            (a - 2.0).abs() < 1e-3 && (b + 0.5).abs() < 1e-3,
+            "a={} b={}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn metrics_known_answers() {
+        let mut metrics = OnlineMetrics::new(10);
+        for (p, y) in [(0.1, 0.0), (0.4, 0.0), (0.35, 1.0), (0.8, 1.0)] {
+            metrics.observe(p, y);
+        }
+        // Un par discordante (0.4 > 0.35) de cuatro
+        assert_eq!(metrics.auc(), Some(0.75));
+        let brier = (0.01 + 0.16 + 0.4225 + 0.04) / 4.0;
+        assert!((metrics.brier() - brier).abs() < 1e-12);
+        let log_loss = -(0.9f64.ln() + 0.6f64.ln() + 0.35f64.ln() + 0.8f64.ln()) / 4.0;
+        assert!((metrics.log_loss() - log_loss).abs() < 1e-12);
+    }
+
+    #[test]
+    fn auc_ties_count_half_and_window_slides() {
+        let mut metrics = OnlineMetrics::new(2);
+        metrics.observe(0.9, 1.0);
+        metrics.observe(0.5, 1.0);
+        metrics.observe(0.5, 0.0);
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics.auc(), Some(0.5));
+        assert_eq!(metrics.report(10).total_seen, 3);
+    }
+
+    #[test]
+    fn ece_of_a_miscalibrated_bin() {
+        let mut metrics = OnlineMetrics::new(10);
+        for y in [1.0, 0.0, 0.0, 0.0] {
+            metrics.observe(0.75, y);
+        }
+        assert!((metrics.report(4).ece - 0.5).abs() < 1e-12);
+    }
+}
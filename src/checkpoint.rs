@@ -0,0 +1,224 @@
+use crate::brain::BayesianBrain;
+use crate::calibration::{CalibrationReport, Calibrator};
+use crate::ensemble::SavedMember;
+use crate::features::FeatureSchema;
+use crate::labels::{Horizon, LabelScheme};
+use crate::model::LogisticModel;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+
+/// Estado persistido de una cabeza: red, calibración y métricas al guardar
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub saved_at: String,
+    pub scheme: LabelScheme,
+    pub horizon: Horizon,
+    /// None = la red no se entrenó (checkpoint solo del modelo logístico)
+    pub brain: Option<BayesianBrain>,
+    pub calibrator: Calibrator,
+    pub metrics: Option<CalibrationReport>,
+    /// Modelo logístico entrenado offline (se añade al conjunto al cargar)
+    #[serde(default)]
+    pub logistic: Option<LogisticModel>,
+    /// Miembros del conjunto en vivo (sustituyen a los configurados al cargar)
+    #[serde(default)]
+    pub members: Vec<SavedMember>,
+}
+
+impl Checkpoint {
+    pub fn new(
+        scheme: LabelScheme,
+        horizon: Horizon,
+        brain: Option<BayesianBrain>,
+        calibrator: Calibrator,
+        metrics: Option<CalibrationReport>,
+    ) -> Self {
+        Self {
+            saved_at: Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string(),
+            scheme,
+            horizon,
+            brain,
+            calibrator,
+            metrics,
+            logistic: None,
+            members: Vec::new(),
+        }
+    }
+
+    /// Comprueba que el checkpoint sirve para una cabeza con esta configuración;
+    /// si no, devuelve el motivo. Los checkpoints sin esquema solo se comparan por dimensión.
+    pub fn check_compatible(
+        &self,
+        scheme: LabelScheme,
+        horizon: Horizon,
+        schema: &FeatureSchema,
+    ) -> Result<(), String> {
+        if self.scheme != scheme {
+            return Err(format!("esquema de etiquetas {:?}", self.scheme));
+        }
+        if self.horizon != horizon {
+            return Err(format!("horizonte {}", self.horizon.name()));
+        }
+        if let Some(saved) = self.brain.as_ref().and_then(BayesianBrain::schema) {
+            return schema.check(saved);
+        }
+        match self.input_dim() {
+            Some(dim) if dim != schema.len() => Err(format!(
+                "{} entradas sin esquema, se esperan {}",
+                dim,
+                schema.len()
+            )),
+            Some(_) => Ok(()),
+            None => Err("checkpoint sin modelos".to_string()),
+        }
+    }
+
+    /// Entradas de la red o, sin ella, del modelo logístico
+    fn input_dim(&self) -> Option<usize> {
+        match (&self.brain, &self.logistic) {
+            (Some(brain), _) => Some(brain.config().input_dim),
+            (None, Some(logistic)) => Some(logistic.weights.len()),
+            (None, None) => None,
+        }
+    }
+
+    /// Lee un checkpoint y verifica que su esquema cuadra con la red guardada
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let raw = fs::read_to_string(path)?;
+        let checkpoint: Self = serde_json::from_str(&raw)?;
+        if let Some((brain, schema)) = checkpoint
+            .brain
+            .as_ref()
+            .and_then(|brain| Some((brain, brain.schema()?)))
+        {
+            if schema.len() != brain.config().input_dim {
+                return Err(format!(
+                    "esquema de {} columnas para una red de {} entradas",
+                    schema.len(),
+                    brain.config().input_dim
+                )
+                .into());
+            }
+            if !schema.is_consistent() {
+                return Err("hash del esquema de features inválido".into());
+            }
+        }
+        Ok(checkpoint)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::BrainConfig;
+
+    fn schema(columns: &[&str]) -> FeatureSchema {
+        FeatureSchema::new(columns.iter().map(|c| c.to_string()).collect())
+    }
+
+    fn checkpoint(config: BrainConfig) -> Checkpoint {
+        Checkpoint::new(
+            LabelScheme::Binary,
+            Horizon::Updates(5),
+            Some(BayesianBrain::from_config(config).unwrap()),
+            Calibrator::None,
+            None,
+        )
+    }
+
+    fn with_schema(columns: &[&str]) -> Checkpoint {
+        checkpoint(BrainConfig::legacy(0, 3, 0.01).with_schema(schema(columns)))
+    }
+
+    #[test]
+    fn compatible_only_with_the_same_columns_in_order() {
+        let ckpt = with_schema(&["mid", "ofi", "spread"]);
+        let head = (LabelScheme::Binary, Horizon::Updates(5));
+        assert!(ckpt
+            .check_compatible(head.0, head.1, &schema(&["mid", "ofi", "spread"]))
+            .is_ok());
+        let err = ckpt
+            .check_compatible(head.0, head.1, &schema(&["mid", "ofi", "microprice"]))
+            .unwrap_err();
+        assert_eq!(err, "faltan [microprice], sobran [spread]");
+        let err = ckpt
+            .check_compatible(head.0, head.1, &schema(&["ofi", "mid", "spread"]))
+            .unwrap_err();
+        assert_eq!(err, "mismas columnas en distinto orden");
+        assert!(ckpt
+            .check_compatible(LabelScheme::Binary, Horizon::Updates(10), &schema(&["mid"]))
+            .unwrap_err()
+            .starts_with("horizonte"));
+    }
+
+    #[test]
+    fn checkpoints_without_schema_compare_the_dimension() {
+        let ckpt = checkpoint(BrainConfig::legacy(2, 3, 0.01));
+        let two = schema(&["mid", "ofi"]);
+        let three = schema(&["mid", "ofi", "spread"]);
+        assert!(ckpt
+            .check_compatible(LabelScheme::Binary, Horizon::Updates(5), &two)
+            .is_ok());
+        let err = ckpt
+            .check_compatible(LabelScheme::Binary, Horizon::Updates(5), &three)
+            .unwrap_err();
+        assert_eq!(err, "2 entradas sin esquema, se esperan 3");
+    }
+
+    #[test]
+    fn logistic_only_checkpoints_compare_the_logistic_dimension() {
+        let mut ckpt = Checkpoint::new(
+            LabelScheme::Binary,
+            Horizon::Updates(5),
+            None,
+            Calibrator::None,
+            None,
+        );
+        let two = schema(&["mid", "ofi"]);
+        let err = ckpt
+            .check_compatible(LabelScheme::Binary, Horizon::Updates(5), &two)
+            .unwrap_err();
+        assert_eq!(err, "checkpoint sin modelos");
+        ckpt.logistic = Some(LogisticModel::new(2, 0.1));
+        assert!(ckpt
+            .check_compatible(LabelScheme::Binary, Horizon::Updates(5), &two)
+            .is_ok());
+        let err = ckpt
+            .check_compatible(LabelScheme::Binary, Horizon::Updates(5), &schema(&["mid"]))
+            .unwrap_err();
+        assert_eq!(err, "2 entradas sin esquema, se esperan 1");
+    }
+
+    /// Guarda el checkpoint con las columnas del esquema editadas a mano
+    fn save_with_columns(ckpt: &Checkpoint, columns: &[&str], path: &str) {
+        let mut json = serde_json::to_value(ckpt).unwrap();
+        json["brain"]["config"]["schema"]["columns"] = serde_json::json!(columns);
+        fs::write(path, json.to_string()).unwrap();
+    }
+
+    #[test]
+    fn load_rejects_a_schema_that_does_not_fit_the_brain() {
+        let path = std::env::temp_dir().join(format!("ckpt-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let ckpt = with_schema(&["mid", "ofi"]);
+        ckpt.save(path).unwrap();
+        assert!(Checkpoint::load(path).is_ok());
+
+        save_with_columns(&ckpt, &["mid", "ofi", "spread"], path);
+        let err = Checkpoint::load(path).err().unwrap().to_string();
+        assert_eq!(err, "esquema de 3 columnas para una red de 2 entradas");
+
+        // Misma dimensión con una columna renombrada: el hash no cuadra
+        save_with_columns(&ckpt, &["mid", "spread"], path);
+        let err = Checkpoint::load(path).err().unwrap().to_string();
+        fs::remove_file(path).unwrap();
+        assert_eq!(err, "hash del esquema de features inválido");
+    }
+}
@@ -0,0 +1,297 @@
+use crate::bayesian::ContextExplanation;
+use crate::hmm::RegimeExplanation;
+use crate::horizons::HorizonPrediction;
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+
+/// Umbrales de la lógica de veredicto
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SignalThresholds {
+    pub max_noise: f64,
+    pub max_brain_uncertainty: f64,
+    /// Desviación máxima entre miembros del conjunto de modelos
+    pub max_disagreement: f64,
+    pub buy_above: f64,
+    pub sell_below: f64,
+}
+
+impl Default for SignalThresholds {
+    fn default() -> Self {
+        Self {
+            max_noise: 0.70,
+            max_brain_uncertainty: 0.85,
+            max_disagreement: 0.15,
+            buy_above: 0.75,
+            sell_below: 0.25,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Verdict {
+    Buy,
+    Sell,
+    Wait,
+    Blocked,
+}
+
+impl Verdict {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Verdict::Buy => "🚀 BUY",
+            Verdict::Sell => "📉 SELL",
+            Verdict::Wait => "⏳ WAIT",
+            Verdict::Blocked => "🚫 BLOCKED",
+        }
+    }
+}
+
+/// Resultado de un filtro: valor observado frente a su umbral
+#[derive(Debug, Clone, Serialize)]
+pub struct GateCheck {
+    pub gate: &'static str,
+    pub value: f64,
+    pub threshold: f64,
+    pub passed: bool,
+}
+
+/// Entradas crudas de la decisión
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DecisionInputs {
+    pub mid: f64,
+    pub spread: f64,
+    pub velocity: f64,
+    pub intensity: f64,
+    pub depth: Vec<f64>,
+    pub noise: f64,
+    pub context: f64,
+    /// Nombre de cada columna de `raw_features` / `normalized` (FeatureSchema)
+    pub columns: Vec<String>,
+    pub raw_features: Vec<f64>,
+    pub normalized: Vec<f64>,
+}
+
+/// Registro completo de una decisión de señal (depuración y cumplimiento)
+#[derive(Debug, Clone, Serialize)]
+pub struct Decision {
+    pub timestamp: String,
+    pub inputs: DecisionInputs,
+    pub prob: f64,
+    pub brain_uncertainty: f64,
+    pub gates: Vec<GateCheck>,
+    pub verdict: Verdict,
+    /// Contribuciones por nodo de la red cuando el contexto es rechazado
+    pub context_explanation: Option<ContextExplanation>,
+    /// Posterior del HMM cuando él puntuó el contexto rechazado (solo o en mezcla)
+    pub regime_explanation: Option<RegimeExplanation>,
+    /// Predicciones de todas las cabezas (la primera es la que decide)
+    pub horizons: Vec<HorizonPrediction>,
+}
+
+impl Decision {
+    /// Aplica los filtros (ruido, contexto, incertidumbre) y los cortes de probabilidad
+    pub fn evaluate(
+        inputs: DecisionInputs,
+        prob: f64,
+        brain_uncertainty: f64,
+        context_threshold: f64,
+        thresholds: &SignalThresholds,
+    ) -> Self {
+        let gates = vec![
+            GateCheck {
+                gate: "noise",
+                value: inputs.noise,
+                threshold: thresholds.max_noise,
+                passed: inputs.noise < thresholds.max_noise,
+            },
+            GateCheck {
+                gate: "context",
+                value: inputs.context,
+                threshold: context_threshold,
+                passed: inputs.context >= context_threshold,
+            },
+            GateCheck {
+                gate: "brain_uncertainty",
+                value: brain_uncertainty,
+                threshold: thresholds.max_brain_uncertainty,
+                passed: brain_uncertainty <= thresholds.max_brain_uncertainty,
+            },
+        ];
+
+        let verdict = if gates.iter().all(|g| g.passed) {
+            if prob > thresholds.buy_above {
+                Verdict::Buy
+            } else if prob < thresholds.sell_below {
+                Verdict::Sell
+            } else {
+                Verdict::Wait
+            }
+        } else {
+            Verdict::Blocked
+        };
+
+        Self {
+            timestamp: Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string(),
+            inputs,
+            prob,
+            brain_uncertainty,
+            gates,
+            verdict,
+            context_explanation: None,
+            regime_explanation: None,
+            horizons: Vec::new(),
+        }
+    }
+
+    /// Añade un filtro adicional; si falla, la señal queda bloqueada
+    pub fn with_gate(mut self, check: GateCheck) -> Self {
+        if !check.passed {
+            self.verdict = Verdict::Blocked;
+        }
+        self.gates.push(check);
+        self
+    }
+
+    /// Nombres de los filtros que bloquearon la señal
+    pub fn failed_gates(&self) -> Vec<&'static str> {
+        self.gates
+            .iter()
+            .filter(|g| !g.passed)
+            .map(|g| g.gate)
+            .collect()
+    }
+}
+
+/// Historial de decisiones: JSON lines en disco + ventana en memoria consultable
+pub struct DecisionLog {
+    capacity: usize,
+    records: VecDeque<Decision>,
+    writer: Option<BufWriter<File>>,
+}
+
+impl DecisionLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: VecDeque::with_capacity(capacity),
+            writer: None,
+        }
+    }
+
+    /// Activa la escritura JSON lines (modo append)
+    pub fn with_file(mut self, path: &str) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.writer = Some(BufWriter::new(file));
+        Ok(self)
+    }
+
+    pub fn record(&mut self, decision: Decision) -> Result<(), Box<dyn Error>> {
+        if let Some(w) = self.writer.as_mut() {
+            serde_json::to_writer(&mut *w, &decision)?;
+            w.write_all(b"\n")?;
+            w.flush()?;
+        }
+        if self.records.len() >= self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(decision);
+        Ok(())
+    }
+
+    pub fn last(&self) -> Option<&Decision> {
+        self.records.back()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Decision> {
+        self.records.iter()
+    }
+
+    pub fn by_verdict(&self, verdict: Verdict) -> impl Iterator<Item = &Decision> {
+        self.records.iter().filter(move |d| d.verdict == verdict)
+    }
+
+    /// Decisiones bloqueadas por un filtro concreto ("noise", "context", ...)
+    pub fn blocked_by<'a>(&'a self, gate: &'a str) -> impl Iterator<Item = &'a Decision> {
+        self.records
+            .iter()
+            .filter(move |d| d.gates.iter().any(|g| g.gate == gate && !g.passed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decide(prob: f64, noise: f64, context: f64, uncertainty: f64) -> Decision {
+        let inputs = DecisionInputs {
+            noise,
+            context,
+            ..Default::default()
+        };
+        Decision::evaluate(inputs, prob, uncertainty, 0.6, &SignalThresholds::default())
+    }
+
+    #[test]
+    fn probability_cuts_are_strict() {
+        assert_eq!(decide(0.76, 0.1, 0.7, 0.1).verdict, Verdict::Buy);
+        assert_eq!(decide(0.75, 0.1, 0.7, 0.1).verdict, Verdict::Wait);
+        assert_eq!(decide(0.25, 0.1, 0.7, 0.1).verdict, Verdict::Wait);
+        assert_eq!(decide(0.24, 0.1, 0.7, 0.1).verdict, Verdict::Sell);
+    }
+
+    #[test]
+    fn gate_boundaries() {
+        // Ruido estricto, contexto e incertidumbre inclusivos
+        assert_eq!(decide(0.9, 0.70, 0.7, 0.1).failed_gates(), vec!["noise"]);
+        assert!(decide(0.9, 0.69, 0.6, 0.85).failed_gates().is_empty());
+        assert_eq!(decide(0.9, 0.1, 0.59, 0.1).failed_gates(), vec!["context"]);
+        assert_eq!(
+            decide(0.9, 0.1, 0.7, 0.86).failed_gates(),
+            vec!["brain_uncertainty"]
+        );
+    }
+
+    #[test]
+    fn failed_gates_explain_a_blocked_signal() {
+        let decision = decide(0.9, 0.8, 0.2, 0.9);
+        assert_eq!(decision.verdict, Verdict::Blocked);
+        assert_eq!(
+            decision.failed_gates(),
+            vec!["noise", "context", "brain_uncertainty"]
+        );
+        let context = &decision.gates[1];
+        assert_eq!((context.value, context.threshold), (0.2, 0.6));
+        assert_eq!(decision.verdict.label(), "🚫 BLOCKED");
+    }
+
+    #[test]
+    fn log_keeps_a_bounded_window_and_writes_json_lines() {
+        let path = std::env::temp_dir().join(format!("decisions-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut log = DecisionLog::new(2).with_file(path).unwrap();
+        for prob in [0.9, 0.5, 0.1] {
+            log.record(decide(prob, 0.1, 0.7, 0.1)).unwrap();
+        }
+        log.record(decide(0.9, 0.1, 0.1, 0.1)).unwrap();
+
+        let verdicts: Vec<Verdict> = log.iter().map(|d| d.verdict).collect();
+        assert_eq!(verdicts, vec![Verdict::Sell, Verdict::Blocked]);
+        assert_eq!(log.by_verdict(Verdict::Sell).count(), 1);
+        assert_eq!(log.blocked_by("context").count(), 1);
+        assert_eq!(log.blocked_by("noise").count(), 0);
+
+        let raw = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let lines: Vec<serde_json::Value> = raw
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0]["verdict"], "Buy");
+        assert_eq!(lines[3]["gates"][1]["passed"], false);
+    }
+}
@@ -0,0 +1,435 @@
+use std::collections::VecDeque;
+
+/// Estado de un detector tras una observación
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriftState {
+    Stable,
+    /// Solo DDM: la deriva es probable pero no confirmada
+    Warning,
+    Drift,
+}
+
+/// Detector de cambio de distribución sobre un flujo de valores
+pub trait DriftDetector: Send {
+    fn update(&mut self, x: f64) -> DriftState;
+    fn reset(&mut self);
+    fn name(&self) -> &'static str;
+
+    /// true si espera errores 0/1 (acierto/fallo) en lugar de errores continuos
+    fn expects_binary(&self) -> bool {
+        false
+    }
+}
+
+/// Nombre en configuración (DRIFT_DETECTOR): adwin, page_hinkley, ddm
+pub fn detector_from_name(name: &str) -> Option<Box<dyn DriftDetector>> {
+    match name {
+        "adwin" => Some(Box::new(Adwin::new(0.002))),
+        "page_hinkley" => Some(Box::new(PageHinkley::new(0.01, 10.0))),
+        "ddm" => Some(Box::new(Ddm::new())),
+        _ => None,
+    }
+}
+
+/// ADWIN (Bifet & Gavaldà): ventana adaptativa que se corta cuando dos
+/// sub-ventanas tienen medias distintas según la cota de Bernstein.
+/// Usa la varianza observada, así que no depende de la escala del flujo.
+pub struct Adwin {
+    pub delta: f64,
+    pub max_window: usize,
+    /// Cada cuántas observaciones se buscan cortes
+    pub check_every: usize,
+    /// Tamaño mínimo de cada sub-ventana
+    pub min_split: usize,
+    window: VecDeque<f64>,
+    since_check: usize,
+}
+
+impl Adwin {
+    pub fn new(delta: f64) -> Self {
+        Self {
+            delta,
+            max_window: 2000,
+            check_every: 16,
+            min_split: 30,
+            window: VecDeque::new(),
+            since_check: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.window.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.window.is_empty()
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.window.iter().sum::<f64>() / self.window.len().max(1) as f64
+    }
+
+    /// Busca el corte más antiguo con diferencia significativa; devuelve cuántos
+    /// elementos descartar por la izquierda
+    fn find_cut(&self) -> Option<usize> {
+        let n = self.window.len();
+        if n < 2 * self.min_split {
+            return None;
+        }
+        let total: f64 = self.window.iter().sum();
+        let mean = total / n as f64;
+        let var = self.window.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n as f64;
+        let ln_term = (2.0 * (n as f64).ln() / self.delta).ln();
+
+        let mut left = 0.0;
+        for (i, &x) in self.window.iter().enumerate() {
+            left += x;
+            let n0 = i + 1;
+            let n1 = n - n0;
+            if n0 < self.min_split {
+                continue;
+            }
+            if n1 < self.min_split {
+                break;
+            }
+            let m = 1.0 / (1.0 / n0 as f64 + 1.0 / n1 as f64);
+            let eps = (2.0 / m * var * ln_term).sqrt() + 2.0 / (3.0 * m) * ln_term;
+            let diff = (left / n0 as f64 - (total - left) / n1 as f64).abs();
+            if diff > eps {
+                return Some(n0);
+            }
+        }
+        None
+    }
+}
+
+impl DriftDetector for Adwin {
+    fn update(&mut self, x: f64) -> DriftState {
+        if self.window.len() >= self.max_window {
+            self.window.pop_front();
+        }
+        self.window.push_back(x);
+        self.since_check += 1;
+        if self.since_check < self.check_every {
+            return DriftState::Stable;
+        }
+        self.since_check = 0;
+
+        let mut drifted = false;
+        while let Some(cut) = self.find_cut() {
+            self.window.drain(..cut);
+            drifted = true;
+        }
+        if drifted {
+            DriftState::Drift
+        } else {
+            DriftState::Stable
+        }
+    }
+
+    fn reset(&mut self) {
+        self.window.clear();
+        self.since_check = 0;
+    }
+
+    fn name(&self) -> &'static str {
+        "adwin"
+    }
+}
+
+/// Page-Hinkley de dos colas: suma acumulada de desviaciones respecto a la media
+/// (menos la tolerancia `delta`); alarma cuando se aleja de su extremo más de `lambda`.
+/// `delta` y `lambda` están en unidades del flujo.
+pub struct PageHinkley {
+    pub delta: f64,
+    pub lambda: f64,
+    /// Observaciones mínimas antes de poder alarmar
+    pub min_samples: u64,
+    n: u64,
+    mean: f64,
+    cum_up: f64,
+    min_up: f64,
+    cum_down: f64,
+    max_down: f64,
+}
+
+impl PageHinkley {
+    pub fn new(delta: f64, lambda: f64) -> Self {
+        Self {
+            delta,
+            lambda,
+            min_samples: 30,
+            n: 0,
+            mean: 0.0,
+            cum_up: 0.0,
+            min_up: 0.0,
+            cum_down: 0.0,
+            max_down: 0.0,
+        }
+    }
+}
+
+impl DriftDetector for PageHinkley {
+    fn update(&mut self, x: f64) -> DriftState {
+        self.n += 1;
+        self.mean += (x - self.mean) / self.n as f64;
+
+        self.cum_up += x - self.mean - self.delta;
+        self.min_up = self.min_up.min(self.cum_up);
+        self.cum_down += x - self.mean + self.delta;
+        self.max_down = self.max_down.max(self.cum_down);
+
+        if self.n >= self.min_samples
+            && (self.cum_up - self.min_up > self.lambda
+                || self.max_down - self.cum_down > self.lambda)
+        {
+            self.reset();
+            return DriftState::Drift;
+        }
+        DriftState::Stable
+    }
+
+    fn reset(&mut self) {
+        self.n = 0;
+        self.mean = 0.0;
+        self.cum_up = 0.0;
+        self.min_up = 0.0;
+        self.cum_down = 0.0;
+        self.max_down = 0.0;
+    }
+
+    fn name(&self) -> &'static str {
+        "page_hinkley"
+    }
+}
+
+/// DDM (Gama et al.): vigila la tasa de error p y su desviación s = sqrt(p(1-p)/n).
+/// Aviso si p + s >= p_min + 2 s_min, deriva si p + s >= p_min + 3 s_min.
+/// Espera errores 0/1 (acierto/fallo).
+pub struct Ddm {
+    pub min_samples: u64,
+    pub warning_level: f64,
+    pub drift_level: f64,
+    n: u64,
+    p: f64,
+    p_min: f64,
+    s_min: f64,
+}
+
+impl Ddm {
+    pub fn new() -> Self {
+        Self {
+            min_samples: 100,
+            warning_level: 2.0,
+            drift_level: 3.0,
+            n: 0,
+            p: 0.0,
+            p_min: f64::INFINITY,
+            s_min: f64::INFINITY,
+        }
+    }
+}
+
+impl Default for Ddm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DriftDetector for Ddm {
+    fn update(&mut self, x: f64) -> DriftState {
+        self.n += 1;
+        self.p += (x - self.p) / self.n as f64;
+        let s = (self.p * (1.0 - self.p) / self.n as f64).sqrt();
+        if self.n < self.min_samples {
+            return DriftState::Stable;
+        }
+
+        if self.p + s <= self.p_min + self.s_min {
+            self.p_min = self.p;
+            self.s_min = s;
+        }
+        if self.p + s >= self.p_min + self.drift_level * self.s_min {
+            self.reset();
+            DriftState::Drift
+        } else if self.p + s >= self.p_min + self.warning_level * self.s_min {
+            DriftState::Warning
+        } else {
+            DriftState::Stable
+        }
+    }
+
+    fn reset(&mut self) {
+        self.n = 0;
+        self.p = 0.0;
+        self.p_min = f64::INFINITY;
+        self.s_min = f64::INFINITY;
+    }
+
+    fn name(&self) -> &'static str {
+        "ddm"
+    }
+
+    fn expects_binary(&self) -> bool {
+        true
+    }
+}
+
+/// Reacción del modelo ante una deriva en el error de predicción
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DriftAction {
+    /// Solo registrar
+    Log,
+    /// Multiplicar la varianza de los pesos
+    InflateVariance(f64),
+    /// Multiplicar el LR durante `steps` pasos
+    BoostLearningRate { factor: f64, steps: u64 },
+    /// Volver al último checkpoint sano
+    Rollback,
+}
+
+impl DriftAction {
+    /// Nombre en configuración (DRIFT_ACTION): log, inflate, boost_lr, rollback
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "log" => Some(DriftAction::Log),
+            "inflate" => Some(DriftAction::InflateVariance(4.0)),
+            "boost_lr" => Some(DriftAction::BoostLearningRate {
+                factor: 5.0,
+                steps: 500,
+            }),
+            "rollback" => Some(DriftAction::Rollback),
+            _ => None,
+        }
+    }
+}
+
+/// Un detector ADWIN por columna de features: detecta cambios en la
+/// distribución de entrada aunque el error aún no lo refleje
+pub struct FeatureDriftMonitor {
+    pub delta: f64,
+    /// Columnas no vigiladas (p.ej. el nivel de precio, no estacionario por naturaleza)
+    pub ignored: Vec<usize>,
+    detectors: Vec<Adwin>,
+}
+
+impl FeatureDriftMonitor {
+    pub fn new(delta: f64) -> Self {
+        Self {
+            delta,
+            ignored: Vec::new(),
+            detectors: Vec::new(),
+        }
+    }
+
+    /// Devuelve los índices de las columnas con deriva
+    pub fn update(&mut self, row: &[f64]) -> Vec<usize> {
+        if self.detectors.len() != row.len() {
+            self.detectors = (0..row.len()).map(|_| Adwin::new(self.delta)).collect();
+        }
+        let mut drifted = Vec::new();
+        for (i, (detector, &x)) in self.detectors.iter_mut().zip(row).enumerate() {
+            if !self.ignored.contains(&i)
+                && x.is_finite()
+                && detector.update(x) == DriftState::Drift
+            {
+                drifted.push(i);
+            }
+        }
+        drifted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adwin_with(window: &[f64]) -> Adwin {
+        let mut adwin = Adwin::new(0.002);
+        adwin.window.extend(window);
+        adwin
+    }
+
+    #[test]
+    fn adwin_cut_matches_the_bernstein_bound() {
+        // n = 60, var = 0.25, m = 15: eps ≈ 0.896 < |0 - 1|, el único corte es el 30
+        let step: Vec<f64> = [0.0; 30].iter().chain(&[1.0; 30]).copied().collect();
+        assert_eq!(adwin_with(&step).find_cut(), Some(30));
+        // Con un salto de 0.5, var = 0.0625 y eps ≈ 0.633: no hay corte
+        let small: Vec<f64> = [0.0; 30].iter().chain(&[0.5; 30]).copied().collect();
+        assert_eq!(adwin_with(&small).find_cut(), None);
+        // Por debajo de 2 · min_split no se busca
+        assert_eq!(adwin_with(&step[1..]).find_cut(), None);
+    }
+
+    #[test]
+    fn adwin_drops_the_old_regime() {
+        let mut adwin = Adwin::new(0.002);
+        for _ in 0..500 {
+            assert_eq!(adwin.update(0.0), DriftState::Stable);
+        }
+        let detected = (1..=200).find(|_| adwin.update(1.0) == DriftState::Drift);
+        assert!(
+            detected.is_some_and(|n| n <= 64),
+            "detectada en {:?}",
+            detected
+        );
+        assert!(adwin.len() < 100, "ventana {}", adwin.len());
+        for _ in 0..200 {
+            adwin.update(1.0);
+        }
+        assert!(adwin.mean() > 0.9);
+    }
+
+    #[test]
+    fn adwin_keeps_growing_on_a_stationary_stream() {
+        let mut adwin = Adwin::new(0.002);
+        for i in 0..1000 {
+            let x = if i % 2 == 0 { 1.0 } else { -1.0 };
+            assert_eq!(adwin.update(x), DriftState::Stable);
+        }
+        assert_eq!(adwin.len(), 1000);
+    }
+
+    #[test]
+    fn page_hinkley_alarms_on_a_mean_shift_both_ways() {
+        for shift in [1.0, -1.0] {
+            let mut ph = PageHinkley::new(0.01, 10.0);
+            for _ in 0..100 {
+                assert_eq!(ph.update(0.0), DriftState::Stable);
+            }
+            let detected = (1..=100).find(|_| ph.update(shift) == DriftState::Drift);
+            assert!(
+                detected.is_some_and(|n| n <= 20),
+                "detectada en {:?}",
+                detected
+            );
+        }
+    }
+
+    #[test]
+    fn ddm_warns_before_drifting() {
+        let mut ddm = Ddm::new();
+        for i in 0..1000 {
+            assert_eq!(
+                ddm.update(f64::from(u8::from(i % 10 == 0))),
+                DriftState::Stable
+            );
+        }
+        let states: Vec<DriftState> = (0..200).map(|_| ddm.update(1.0)).collect();
+        let warning = states.iter().position(|s| *s == DriftState::Warning);
+        let drift = states.iter().position(|s| *s == DriftState::Drift);
+        assert!(matches!((warning, drift), (Some(w), Some(d)) if w < d));
+    }
+
+    #[test]
+    fn feature_monitor_skips_ignored_columns() {
+        let mut monitor = FeatureDriftMonitor::new(0.002);
+        monitor.ignored = vec![0];
+        for _ in 0..300 {
+            assert!(monitor.update(&[0.0, 0.0]).is_empty());
+        }
+        let drifted: Vec<usize> = (0..100).flat_map(|_| monitor.update(&[5.0, 5.0])).collect();
+        assert!(!drifted.is_empty() && drifted.iter().all(|&c| c == 1));
+    }
+}
@@ -0,0 +1,372 @@
+use crate::brain::BayesianBrain;
+use crate::model::LogisticModel;
+use ndarray::Array1;
+use serde::{Deserialize, Serialize};
+
+/// Modelo que puntúa la probabilidad alcista y aprende online
+pub trait Predictor: Send {
+    fn name(&self) -> &str;
+
+    /// (score alcista en [0, 1], incertidumbre epistémica normalizada en [0, 1];
+    /// 0 si el modelo no la estima)
+    fn predict(&self, features: &Array1<f64>) -> (f64, f64);
+
+    /// `targets` en el formato de la cabeza de la red; `outcome` es el resultado
+    /// en [0, 1] (`LabelScheme::outcome`) para los modelos de probabilidad directa
+    fn train(&mut self, features: &Array1<f64>, targets: &Array1<f64>, outcome: f64);
+
+    /// Estado para el checkpoint (None = el miembro no se persiste)
+    fn save(&self) -> Option<SavedMember> {
+        None
+    }
+}
+
+/// Miembro del conjunto tal como se guarda en el checkpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SavedMember {
+    Logistic(LogisticModel),
+}
+
+impl SavedMember {
+    pub fn into_predictor(self) -> Box<dyn Predictor> {
+        match self {
+            SavedMember::Logistic(model) => Box::new(model),
+        }
+    }
+}
+
+impl Predictor for BayesianBrain {
+    fn name(&self) -> &str {
+        "brain"
+    }
+
+    fn predict(&self, features: &Array1<f64>) -> (f64, f64) {
+        self.predict_with_uncertainty(features)
+    }
+
+    fn train(&mut self, features: &Array1<f64>, targets: &Array1<f64>, _outcome: f64) {
+        self.train_targets(features, targets);
+    }
+}
+
+impl Predictor for LogisticModel {
+    fn name(&self) -> &str {
+        "logistic"
+    }
+
+    fn predict(&self, features: &Array1<f64>) -> (f64, f64) {
+        if features.len() != self.weights.len() {
+            return (0.5, 1.0);
+        }
+        (LogisticModel::predict(self, features), 0.0)
+    }
+
+    fn train(&mut self, features: &Array1<f64>, _targets: &Array1<f64>, outcome: f64) {
+        if features.len() == self.weights.len() {
+            LogisticModel::train(self, features, outcome);
+        }
+    }
+
+    fn save(&self) -> Option<SavedMember> {
+        Some(SavedMember::Logistic(self.clone()))
+    }
+}
+
+/// Predicción de un miembro
+#[derive(Debug, Clone, Serialize)]
+pub struct MemberPrediction {
+    pub name: String,
+    pub score: f64,
+    pub uncertainty: f64,
+}
+
+/// Predicción combinada
+#[derive(Debug, Clone, Serialize)]
+pub struct EnsemblePrediction {
+    pub score: f64,
+    /// Media ponderada de las incertidumbres de los miembros
+    pub uncertainty: f64,
+    /// Desviación típica ponderada de los scores: desacuerdo entre miembros
+    pub disagreement: f64,
+    pub weights: Vec<f64>,
+    pub members: Vec<MemberPrediction>,
+}
+
+/// Regla de combinación
+pub enum Combiner {
+    Mean,
+    /// Pesos 1 / (MSE reciente + varianza epistémica del score)
+    InverseVariance,
+    /// Meta-modelo logístico sobre los logits de los miembros (entrenado prequential)
+    Stacking(LogisticModel),
+}
+
+impl Combiner {
+    /// Nombre en configuración (ENSEMBLE_COMBINER): mean, inverse_variance, stacking
+    pub fn from_name(name: &str, n_members: usize) -> Option<Self> {
+        match name {
+            "mean" => Some(Combiner::Mean),
+            "inverse_variance" => Some(Combiner::InverseVariance),
+            "stacking" => Some(Self::stacking(n_members)),
+            _ => None,
+        }
+    }
+
+    /// Meta-modelo para `n_members` predicciones; arranca como la media de los logits
+    pub fn stacking(n_members: usize) -> Self {
+        let mut meta = LogisticModel::new(n_members, 0.01);
+        meta.weights.fill(1.0 / n_members.max(1) as f64);
+        Combiner::Stacking(meta)
+    }
+}
+
+/// Conjunto de modelos. Puede recibir además predicciones de modelos que viven
+/// fuera (la red principal de `HorizonHead`), que van delante de las propias.
+pub struct Ensemble {
+    pub members: Vec<Box<dyn Predictor>>,
+    pub combiner: Combiner,
+    /// Factor de olvido del MSE por miembro
+    pub error_decay: f64,
+    /// MSE reciente de cada predicción combinada (externas + miembros)
+    errors: Vec<f64>,
+}
+
+impl Ensemble {
+    pub fn new(members: Vec<Box<dyn Predictor>>, combiner: Combiner) -> Self {
+        Self {
+            members,
+            combiner,
+            error_decay: 0.99,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Nombre de modelo en configuración (ENSEMBLE): logistic
+    pub fn member_from_name(name: &str, input_dim: usize) -> Option<Box<dyn Predictor>> {
+        match name {
+            "logistic" => Some(Box::new(LogisticModel::new(input_dim, 0.01))),
+            _ => None,
+        }
+    }
+
+    /// Sustituye los miembros propios y ajusta el combinador a `external`
+    /// predicciones externas más los nuevos miembros
+    pub fn set_members(&mut self, members: Vec<Box<dyn Predictor>>, external: usize) {
+        self.members = members;
+        self.errors.clear();
+        let n = external + self.members.len();
+        if matches!(&self.combiner, Combiner::Stacking(meta) if meta.weights.len() != n) {
+            self.combiner = Combiner::stacking(n);
+        }
+    }
+
+    /// Miembros persistibles, en orden
+    pub fn saved_members(&self) -> Vec<SavedMember> {
+        self.members.iter().filter_map(|m| m.save()).collect()
+    }
+
+    pub fn member_predictions(&self, features: &Array1<f64>) -> Vec<MemberPrediction> {
+        self.members
+            .iter()
+            .map(|m| {
+                let (score, uncertainty) = m.predict(features);
+                MemberPrediction {
+                    name: m.name().to_string(),
+                    score,
+                    uncertainty,
+                }
+            })
+            .collect()
+    }
+
+    fn ensure_errors(&mut self, n: usize) {
+        if self.errors.len() != n {
+            // Brier de un predictor constante 0.5
+            self.errors = vec![0.25; n];
+        }
+    }
+
+    /// Combina predicciones (externas seguidas de `member_predictions`)
+    pub fn combine(&self, predictions: Vec<MemberPrediction>) -> EnsemblePrediction {
+        let n = predictions.len();
+        let mut weights = match &self.combiner {
+            Combiner::Mean | Combiner::Stacking(_) => vec![1.0; n],
+            Combiner::InverseVariance => predictions
+                .iter()
+                .enumerate()
+                .map(|(i, p)| {
+                    let mse = self.errors.get(i).copied().unwrap_or(0.25);
+                    let epistemic = p.uncertainty * p.score * (1.0 - p.score);
+                    1.0 / (mse + epistemic).max(1e-6)
+                })
+                .collect(),
+        };
+        let total: f64 = weights.iter().sum::<f64>().max(1e-12);
+        weights.iter_mut().for_each(|w| *w /= total);
+
+        let weighted_mean: f64 = weights
+            .iter()
+            .zip(&predictions)
+            .map(|(w, p)| w * p.score)
+            .sum();
+        let score = match &self.combiner {
+            Combiner::Stacking(meta) if meta.weights.len() == n => {
+                meta.predict(&Self::logits(&predictions))
+            }
+            _ => weighted_mean,
+        };
+        let disagreement = weights
+            .iter()
+            .zip(&predictions)
+            .map(|(w, p)| w * (p.score - weighted_mean).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        let uncertainty = weights
+            .iter()
+            .zip(&predictions)
+            .map(|(w, p)| w * p.uncertainty)
+            .sum();
+
+        EnsemblePrediction {
+            score,
+            uncertainty,
+            disagreement,
+            weights,
+            members: predictions,
+        }
+    }
+
+    fn logits(predictions: &[MemberPrediction]) -> Array1<f64> {
+        predictions
+            .iter()
+            .map(|p| {
+                let s = p.score.clamp(1e-6, 1.0 - 1e-6);
+                (s / (1.0 - s)).ln()
+            })
+            .collect()
+    }
+
+    /// Actualiza los errores de cada miembro y el meta-modelo con el resultado
+    /// de unas predicciones hechas antes de conocerlo
+    pub fn observe(&mut self, predictions: &[MemberPrediction], outcome: f64) {
+        self.ensure_errors(predictions.len());
+        let decay = self.error_decay;
+        for (e, p) in self.errors.iter_mut().zip(predictions) {
+            *e = decay * *e + (1.0 - decay) * (p.score - outcome).powi(2);
+        }
+        if let Combiner::Stacking(meta) = &mut self.combiner {
+            if meta.weights.len() == predictions.len() {
+                meta.train(&Self::logits(predictions), outcome);
+            }
+        }
+    }
+
+    /// Entrena a todos los miembros propios con la muestra
+    pub fn train(&mut self, features: &Array1<f64>, targets: &Array1<f64>, outcome: f64) {
+        for m in self.members.iter_mut() {
+            m.train(features, targets, outcome);
+        }
+    }
+
+    /// Predicción prequential completa sobre los miembros propios
+    pub fn predict(&self, features: &Array1<f64>) -> EnsemblePrediction {
+        self.combine(self.member_predictions(features))
+    }
+}
+
+impl Predictor for Ensemble {
+    fn name(&self) -> &str {
+        "ensemble"
+    }
+
+    fn predict(&self, features: &Array1<f64>) -> (f64, f64) {
+        let p = Ensemble::predict(self, features);
+        // El desacuerdo cuenta como incertidumbre adicional
+        (p.score, (p.uncertainty + p.disagreement).min(1.0))
+    }
+
+    fn train(&mut self, features: &Array1<f64>, targets: &Array1<f64>, outcome: f64) {
+        let predictions = self.member_predictions(features);
+        self.observe(&predictions, outcome);
+        Ensemble::train(self, features, targets, outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prediction(name: &str, score: f64, uncertainty: f64) -> MemberPrediction {
+        MemberPrediction {
+            name: name.to_string(),
+            score,
+            uncertainty,
+        }
+    }
+
+    #[test]
+    fn inverse_variance_favours_the_lower_error_member() {
+        let mut ensemble = Ensemble::new(Vec::new(), Combiner::InverseVariance);
+        ensemble.errors = vec![0.01, 0.04];
+        // Pesos 1/0.01 y 1/0.04 normalizados: 0.8 y 0.2
+        let p = ensemble.combine(vec![prediction("a", 0.9, 0.0), prediction("b", 0.5, 0.0)]);
+        assert!((p.weights[0] - 0.8).abs() < 1e-12);
+        assert!((p.score - 0.82).abs() < 1e-12);
+        // sqrt(0.8 · 0.08² + 0.2 · 0.32²)
+        assert!((p.disagreement - 0.16).abs() < 1e-12);
+
+        // La varianza epistémica se suma al error: 1 / (0.01 + 1 · 0.25)
+        let p = ensemble.combine(vec![prediction("a", 0.5, 1.0), prediction("b", 0.5, 0.0)]);
+        let (wa, wb) = (1.0 / 0.26, 1.0 / 0.04);
+        assert!((p.weights[0] - wa / (wa + wb)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn observed_errors_drive_the_weights() {
+        let mut ensemble = Ensemble::new(Vec::new(), Combiner::InverseVariance);
+        let predictions = [prediction("a", 0.9, 0.0), prediction("b", 0.4, 0.0)];
+        ensemble.observe(&predictions, 1.0);
+        // Arranca en el Brier de 0.5 y olvida con factor 0.99
+        assert!((ensemble.errors[0] - (0.99 * 0.25 + 0.01 * 0.01)).abs() < 1e-15);
+        for _ in 0..200 {
+            ensemble.observe(&predictions, 1.0);
+        }
+        let p = ensemble.combine(predictions.to_vec());
+        assert!(p.weights[0] > 0.6, "{:?}", p.weights);
+    }
+
+    #[test]
+    fn stacking_trains_the_meta_model_on_member_logits() {
+        let combiner = Combiner::from_name("stacking", 2).unwrap();
+        let mut ensemble = Ensemble::new(Vec::new(), combiner);
+        let predictions = vec![prediction("a", 0.9, 0.0), prediction("b", 0.5, 0.0)];
+        // Media de los logits: sigmoid(0.5 · ln 9) = 0.75
+        assert!((ensemble.combine(predictions.clone()).score - 0.75).abs() < 1e-12);
+
+        ensemble.observe(&predictions, 1.0);
+        let Combiner::Stacking(meta) = &ensemble.combiner else {
+            panic!("combinador");
+        };
+        // w -= lr · (p - y) · logit: solo se mueve el peso con logit no nulo
+        assert!((meta.weights[0] - (0.5 + 0.01 * 0.25 * 9f64.ln())).abs() < 1e-12);
+        assert_eq!(meta.weights[1], 0.5);
+        assert!((meta.bias - 0.0025).abs() < 1e-12);
+    }
+
+    #[test]
+    fn mean_combiner_and_members() {
+        let mut ensemble = Ensemble::new(
+            vec![Ensemble::member_from_name("logistic", 2).unwrap()],
+            Combiner::from_name("mean", 1).unwrap(),
+        );
+        assert!(Ensemble::member_from_name("forest", 2).is_none());
+        let features = Array1::from(vec![1.0, -1.0]);
+        assert_eq!(ensemble.predict(&features).score, 0.5);
+        Predictor::train(&mut ensemble, &features, &Array1::zeros(1), 1.0);
+        assert!(ensemble.predict(&features).score > 0.5);
+        // Dimensión incorrecta: sin opinión
+        let wrong = Array1::from(vec![1.0]);
+        assert_eq!(ensemble.members[0].predict(&wrong), (0.5, 1.0));
+    }
+}
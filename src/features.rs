@@ -1,80 +1,895 @@
+use crate::bars::Bar;
+use crate::kalman::KalmanEstimate;
+use crate::scaling::{Clipping, ColumnScaler, Normalization};
 use crate::state::OrderBook;
-use ndarray::{Array1, Array2};
+use crate::trades::TradeTape;
+use crate::velocity::VelocityRates;
+use ndarray::Array1;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+/// Versión del cálculo de las features: subirla cuando cambie el significado de
+/// una columna existente aunque su nombre no cambie.
+/// v2: `velocity` pasa a ser una tasa deslizante fechada con SendingTime
+pub const FEATURE_SCHEMA_VERSION: u32 = 2;
+
+/// Descripción del vector de features: columnas con nombre, versión y hash.
+/// Viaja con la red, los checkpoints, los datasets y el registro de decisiones.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeatureSchema {
+    pub version: u32,
+    pub columns: Vec<String>,
+    /// FNV-1a de la versión y los nombres (estable entre compilaciones)
+    pub hash: u64,
+}
+
+/// FNV-1a de la versión y los nombres (separados por un byte nulo)
+fn schema_hash(version: u32, columns: &[String]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let names = columns
+        .iter()
+        .flat_map(|c| c.bytes().chain(std::iter::once(0)));
+    for b in version.to_le_bytes().into_iter().chain(names) {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+impl FeatureSchema {
+    pub fn new(columns: Vec<String>) -> Self {
+        Self {
+            version: FEATURE_SCHEMA_VERSION,
+            hash: schema_hash(FEATURE_SCHEMA_VERSION, &columns),
+            columns,
+        }
+    }
+
+    /// El hash guardado corresponde a la versión y las columnas (no fue editado a mano)
+    pub fn is_consistent(&self) -> bool {
+        self.hash == schema_hash(self.version, &self.columns)
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c == name)
+    }
+
+    pub fn name(&self, idx: usize) -> &str {
+        self.columns.get(idx).map_or("?", String::as_str)
+    }
+
+    /// Ok si `other` describe el mismo vector; si no, explica la diferencia
+    pub fn check(&self, other: &FeatureSchema) -> Result<(), String> {
+        if self == other {
+            return Ok(());
+        }
+        if self.version != other.version {
+            return Err(format!(
+                "versión de features {} frente a {}",
+                other.version, self.version
+            ));
+        }
+        let missing: Vec<&str> = self
+            .columns
+            .iter()
+            .filter(|c| !other.columns.contains(c))
+            .map(String::as_str)
+            .collect();
+        let extra: Vec<&str> = other
+            .columns
+            .iter()
+            .filter(|c| !self.columns.contains(c))
+            .map(String::as_str)
+            .collect();
+        if missing.is_empty() && extra.is_empty() {
+            Err("mismas columnas en distinto orden".to_string())
+        } else {
+            Err(format!(
+                "faltan [{}], sobran [{}]",
+                missing.join(", "),
+                extra.join(", ")
+            ))
+        }
+    }
+}
+
+/// Qué columnas produce `push_features` y con qué parámetros.
+/// El orden de las columnas es el de los campos; `names()` lo describe.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureConfig {
+    /// Precio medio crudo (no estacionario, se mantiene por compatibilidad)
+    pub mid: bool,
+    /// Eventos por segundo de todas las entradas (reloj de los mensajes)
+    pub velocity: bool,
+    /// Eventos por segundo de bid, ask y operaciones por separado
+    pub side_rates: bool,
+    pub noise: bool,
+    pub context: bool,
+    /// Imbalance por nivel, niveles 1..=N (0 = desactivado)
+    pub imbalance_levels: usize,
+    /// Log-retornos del mid a estos retardos (en updates)
+    pub return_lags: Vec<usize>,
+    /// Microprecio (ponderado por la cola contraria) menos mid, en ticks
+    pub microprice: bool,
+    /// Mid ponderado por volumen de N niveles menos mid, en ticks (0 = desactivado)
+    pub weighted_mid_levels: usize,
+    pub spread_ticks: bool,
+    /// Order flow imbalance (Cont, Kukanov, Stoikov) entre updates del mejor nivel
+    pub ofi: bool,
+    /// log(1 + volumen acumulado) de los N primeros niveles, bid y ask (0 = desactivado)
+    pub cum_depth_levels: usize,
+    /// Pendiente del volumen acumulado frente a la distancia al mid en ticks,
+    /// bid y ask, sobre N niveles (0 = desactivado)
+    pub depth_slope_levels: usize,
+    /// Volatilidad realizada de los log-retornos de 1 update en esta ventana (0 = desactivado)
+    pub realized_vol_window: usize,
+    /// Fracción de la cola del mejor nivel consumida por update, media exponencial
+    /// con esta semivida en updates, bid y ask (0 = desactivado)
+    pub depletion_halflife: usize,
+    /// Segundos desde el último cambio del mejor bid/ask (precio o volumen)
+    pub time_since_update: bool,
+    /// Flujo de operaciones en esta ventana en segundos: volumen con signo, VWAP
+    /// menos mid en ticks, operaciones por segundo e imbalance del agresor (0 = desactivado)
+    pub trade_window: f64,
+    /// Intensidad condicional del proceso de Hawkes y su ratio de ramificación
+    pub hawkes: bool,
+    /// Probabilidad filtrada de cada régimen del HMM (0 = desactivado)
+    pub regime_states: usize,
+    /// Filtro de Kalman del microprecio: precio justo menos mid y velocidad en
+    /// ticks, varianza de la innovación en ticks²
+    pub kalman: bool,
+    /// Última barra cerrada: OHLC y VWAP menos mid en ticks, log(1 + volumen) y
+    /// log-retorno cierre/apertura (se activa sola con barras)
+    pub bar: bool,
+    pub tick_size: f64,
+}
+
+impl Default for FeatureConfig {
+    /// Las 7 columnas históricas: mid, velocidad, ruido, contexto e imbalance de 3 niveles
+    fn default() -> Self {
+        Self {
+            mid: true,
+            velocity: true,
+            side_rates: false,
+            noise: true,
+            context: true,
+            imbalance_levels: 3,
+            return_lags: Vec::new(),
+            microprice: false,
+            weighted_mid_levels: 0,
+            spread_ticks: false,
+            ofi: false,
+            cum_depth_levels: 0,
+            depth_slope_levels: 0,
+            realized_vol_window: 0,
+            depletion_halflife: 0,
+            time_since_update: false,
+            trade_window: 0.0,
+            hawkes: false,
+            regime_states: 0,
+            kalman: false,
+            bar: false,
+            tick_size: 0.00001,
+        }
+    }
+}
+
+impl FeatureConfig {
+    /// Conjunto de microestructura completo, sin el precio crudo
+    pub fn microstructure() -> Self {
+        Self {
+            mid: false,
+            return_lags: vec![1, 5, 20],
+            microprice: true,
+            weighted_mid_levels: 3,
+            spread_ticks: true,
+            ofi: true,
+            cum_depth_levels: 5,
+            depth_slope_levels: 5,
+            realized_vol_window: 50,
+            depletion_halflife: 20,
+            time_since_update: true,
+            ..Self::default()
+        }
+    }
+
+    /// Lista separada por comas: "legacy", "micro" o columnas sueltas con su
+    /// parámetro, p.ej. "velocity,depth=3,returns=1/5/20,microprice,ofi,rvol=50,trades=30,tick=0.0001"
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match spec.trim() {
+            "" | "legacy" => return Ok(Self::default()),
+            "micro" => return Ok(Self::microstructure()),
+            _ => {}
+        }
+        let mut config = Self {
+            mid: false,
+            velocity: false,
+            noise: false,
+            context: false,
+            imbalance_levels: 0,
+            ..Self::default()
+        };
+        for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (name, param) = match item.split_once('=') {
+                Some((n, p)) => (n, Some(p)),
+                None => (item, None),
+            };
+            let levels = |default: usize| -> Result<usize, String> {
+                param.map_or(Ok(default), |p| {
+                    p.parse()
+                        .map_err(|_| format!("parámetro inválido en {}", item))
+                })
+            };
+            match name {
+                "mid" => config.mid = true,
+                "velocity" => config.velocity = true,
+                "rates" => config.side_rates = true,
+                "noise" => config.noise = true,
+                "context" => config.context = true,
+                "depth" => config.imbalance_levels = levels(3)?,
+                "returns" => {
+                    config.return_lags = param
+                        .unwrap_or("1/5/20")
+                        .split('/')
+                        .map(|l| {
+                            l.parse()
+                                .map_err(|_| format!("retardo inválido en {}", item))
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+                "microprice" => config.microprice = true,
+                "wmid" => config.weighted_mid_levels = levels(3)?,
+                "spread" => config.spread_ticks = true,
+                "ofi" => config.ofi = true,
+                "cum_depth" => config.cum_depth_levels = levels(5)?,
+                "slope" => config.depth_slope_levels = levels(5)?,
+                "rvol" => config.realized_vol_window = levels(50)?,
+                "depletion" => config.depletion_halflife = levels(20)?,
+                "since_update" => config.time_since_update = true,
+                "hawkes" => config.hawkes = true,
+                "regimes" => config.regime_states = levels(3)?,
+                "kalman" => config.kalman = true,
+                "bar" => config.bar = true,
+                "trades" => {
+                    config.trade_window = param
+                        .map_or(Some(30.0), |p| p.parse().ok())
+                        .filter(|w: &f64| *w > 0.0)
+                        .ok_or_else(|| format!("ventana inválida en {}", item))?
+                }
+                "tick" => {
+                    config.tick_size = param
+                        .and_then(|p| p.parse().ok())
+                        .filter(|t: &f64| t.is_finite() && *t > 0.0)
+                        .ok_or_else(|| format!("tick inválido en {}", item))?
+                }
+                _ => return Err(format!("feature desconocida: {}", name)),
+            }
+        }
+        if config.dim() == 0 {
+            return Err("ninguna feature activada".to_string());
+        }
+        Ok(config)
+    }
+
+    /// Nombre de cada columna, en el orden de `push_features`
+    pub fn names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        let flags = [
+            (self.mid, "mid"),
+            (self.velocity, "velocity"),
+            (self.noise, "noise"),
+            (self.context, "context"),
+        ];
+        names.extend(
+            flags
+                .iter()
+                .filter(|(on, _)| *on)
+                .map(|(_, n)| n.to_string()),
+        );
+        if self.side_rates {
+            names.extend(["rate_bid", "rate_ask", "rate_trade"].map(String::from));
+        }
+        names.extend((1..=self.imbalance_levels).map(|i| format!("depth_{}", i)));
+        names.extend(self.return_lags.iter().map(|l| format!("ret_{}", l)));
+        if self.microprice {
+            names.push("microprice".to_string());
+        }
+        if self.weighted_mid_levels > 0 {
+            names.push(format!("wmid_{}", self.weighted_mid_levels));
+        }
+        if self.spread_ticks {
+            names.push("spread_ticks".to_string());
+        }
+        if self.ofi {
+            names.push("ofi".to_string());
+        }
+        if self.cum_depth_levels > 0 {
+            names.push(format!("cum_bid_{}", self.cum_depth_levels));
+            names.push(format!("cum_ask_{}", self.cum_depth_levels));
+        }
+        if self.depth_slope_levels > 0 {
+            names.push(format!("slope_bid_{}", self.depth_slope_levels));
+            names.push(format!("slope_ask_{}", self.depth_slope_levels));
+        }
+        if self.realized_vol_window > 0 {
+            names.push(format!("rvol_{}", self.realized_vol_window));
+        }
+        if self.depletion_halflife > 0 {
+            names.push("depletion_bid".to_string());
+            names.push("depletion_ask".to_string());
+        }
+        if self.time_since_update {
+            names.push("since_update".to_string());
+        }
+        if self.trade_window > 0.0 {
+            let w = self.trade_window;
+            names.push(format!("signed_volume_{}s", w));
+            names.push(format!("vwap_{}s", w));
+            names.push(format!("trade_intensity_{}s", w));
+            names.push(format!("aggressor_imbalance_{}s", w));
+        }
+        if self.hawkes {
+            names.push("hawkes_intensity".to_string());
+            names.push("branching_ratio".to_string());
+        }
+        names.extend((0..self.regime_states).map(|s| format!("regime_{}", s)));
+        if self.kalman {
+            names.extend(["kalman_fair", "kalman_velocity", "kalman_innovation"].map(String::from));
+        }
+        if self.bar {
+            names.extend(
+                [
+                    "bar_open",
+                    "bar_high",
+                    "bar_low",
+                    "bar_close",
+                    "bar_vwap",
+                    "bar_volume",
+                    "bar_return",
+                ]
+                .map(String::from),
+            );
+        }
+        names
+    }
+
+    pub fn dim(&self) -> usize {
+        self.names().len()
+    }
+
+    pub fn schema(&self) -> FeatureSchema {
+        FeatureSchema::new(self.names())
+    }
+}
+
+/// Estado entre updates que necesitan las features de microestructura
+#[derive(Debug, Default)]
+struct MicroState {
+    /// Mids recientes (el último al final)
+    mids: VecDeque<f64>,
+    /// Cuadrados de los log-retornos de 1 update y su suma
+    sq_returns: VecDeque<f64>,
+    sq_sum: f64,
+    /// Mejor nivel anterior: (precio bid, volumen bid, precio ask, volumen ask)
+    prev_top: Option<(f64, f64, f64, f64)>,
+    last_top_change: Option<f64>,
+    depletion: [f64; 2],
+}
+
+/// Señales calculadas por el pipeline que entran tal cual en el vector
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Signals<'a> {
+    pub rates: VelocityRates,
+    pub noise: f64,
+    pub context: f64,
+    /// Intensidad condicional y ratio de ramificación del modelo de Hawkes
+    pub hawkes_intensity: f64,
+    pub branching_ratio: f64,
+    /// Posteriores de los regímenes del HMM (vacío mientras no hay modelo)
+    pub regimes: &'a [f64],
+    /// Estimación del filtro de Kalman (None hasta su primera corrección)
+    pub kalman: Option<KalmanEstimate>,
+    /// Barra que acaba de cerrarse (None sin barras)
+    pub bar: Option<&'a Bar>,
+}
+
+/// Valor no finito producido por una feature
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidFeature {
+    pub name: String,
+    pub value: f64,
+}
+
+impl fmt::Display for InvalidFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "feature {} = {}", self.name, self.value)
+    }
+}
+
+impl Error for InvalidFeature {}
 
 pub struct FeatureCollector {
     pub window_size: usize,
-    pub data: Vec<Vec<f64>>,
-    pub means: Array1<f64>,
-    pub stds: Array1<f64>,
+    pub config: FeatureConfig,
+    pub normalization: Normalization,
+    /// Recorte por defecto y excepciones por nombre de feature
+    pub clipping: Clipping,
+    pub feature_clipping: Vec<(String, Clipping)>,
+    /// Último vector crudo y su versión escalada
+    last: Vec<f64>,
+    scaled: Array1<f64>,
+    scalers: Vec<ColumnScaler>,
+    state: MicroState,
 }
 
 impl FeatureCollector {
     pub fn new(window_size: usize) -> Self {
         Self {
             window_size,
-            data: Vec::new(),
-            // Iniciamos con 10 dimensiones (Price, Vel, Ruido, Contexto + 3 Bids + 3 Asks)
-            // Esto se ajustará dinámicamente al primer vector real
-            means: Array1::zeros(0),
-            stds: Array1::ones(0),
+            config: FeatureConfig::default(),
+            normalization: Normalization::ZScore,
+            clipping: Clipping::None,
+            feature_clipping: Vec::new(),
+            last: Vec::new(),
+            // Vacío durante el calentamiento; luego de la dimensión de `config.schema()`
+            scaled: Array1::zeros(0),
+            scalers: Vec::new(),
+            state: MicroState::default(),
         }
     }
 
-    /// Empaqueta todas las señales en un solo vector de entrada
-    pub fn push_features(&mut self, book: &OrderBook, velocity: f64, noise: f64, context: f64) {
-        let mut current_row = Vec::new();
+    pub fn with_config(mut self, config: FeatureConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_normalization(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Recorte por defecto más excepciones por nombre de feature
+    pub fn with_clipping(
+        mut self,
+        clipping: Clipping,
+        per_feature: Vec<(String, Clipping)>,
+    ) -> Self {
+        self.clipping = clipping;
+        self.feature_clipping = per_feature;
+        self
+    }
+
+    pub fn schema(&self) -> FeatureSchema {
+        self.config.schema()
+    }
 
-        // 1. Precio medio (Normalizado internamente luego)
-        current_row.push(book.get_mid_price().unwrap_or(0.0));
+    /// Último vector sin normalizar (vacío antes del primer update)
+    pub fn last_raw(&self) -> &[f64] {
+        &self.last
+    }
 
-        // 2. Dinámica del mercado
-        current_row.push(velocity);
-        current_row.push(noise);
-        current_row.push(context);
+    /// Empaqueta todas las señales activadas en `config` en un solo vector de entrada.
+    /// `time` en segundos (reloj del mensaje o de recepción); `trades` es la cinta
+    /// ya recortada a `time`. Un valor NaN/inf se devuelve como error con el nombre
+    /// de la feature y el vector se descarta.
+    pub fn push_features(
+        &mut self,
+        book: &OrderBook,
+        trades: &TradeTape,
+        time: f64,
+        signals: &Signals<'_>,
+    ) -> Result<(), InvalidFeature> {
+        let rates = &signals.rates;
+        let cfg = &self.config;
+        let tick = cfg.tick_size;
+        let mid = book.get_mid_price().unwrap_or(0.0);
+        let mut current_row = Vec::with_capacity(cfg.dim());
 
-        // 3. Profundidad del Libro (Niveles 1 a 3)
+        // 1. Precio medio y dinámica del mercado
+        let flags = [
+            (cfg.mid, mid),
+            (cfg.velocity, rates.total),
+            (cfg.noise, signals.noise),
+            (cfg.context, signals.context),
+        ];
+        current_row.extend(flags.iter().filter(|(on, _)| *on).map(|(_, v)| v));
+        if cfg.side_rates {
+            current_row.extend([rates.bid, rates.ask, rates.trade]);
+        }
+
+        // 2. Profundidad del Libro (imbalance por nivel)
         // Esto captura la "geometría" del LOB
-        let depth_v = book.get_depth_vector(3);
-        current_row.extend(depth_v);
+        current_row.extend(book.get_depth_vector(cfg.imbalance_levels));
 
-        // Gestión de la ventana deslizante para normalización
-        if self.data.len() >= self.window_size {
-            self.data.remove(0);
+        // 3. Log-retornos y volatilidad realizada
+        let state = &mut self.state;
+        if let (Some(&prev), true) = (state.mids.back(), mid > 0.0) {
+            let r = (mid / prev).ln();
+            state.sq_returns.push_back(r * r);
+            state.sq_sum += r * r;
+            if state.sq_returns.len() > cfg.realized_vol_window {
+                state.sq_sum -= state.sq_returns.pop_front().unwrap_or(0.0);
+            }
+        }
+        if mid > 0.0 {
+            state.mids.push_back(mid);
+        }
+        let history = cfg.return_lags.iter().copied().max().unwrap_or(0) + 1;
+        while state.mids.len() > history {
+            state.mids.pop_front();
+        }
+        for &lag in &cfg.return_lags {
+            let n = state.mids.len();
+            current_row.push(if n > lag && mid > 0.0 {
+                (mid / state.mids[n - 1 - lag]).ln()
+            } else {
+                0.0
+            });
         }
-        self.data.push(current_row);
 
-        // Actualizamos estadísticas de normalización si tenemos datos suficientes
-        if self.data.len() >= 10 {
-            self.update_stats();
+        // 4. Precios de referencia dentro del spread, en ticks respecto al mid
+        let bids = book.top_levels('0', cfg.weighted_mid_levels.max(1));
+        let asks = book.top_levels('1', cfg.weighted_mid_levels.max(1));
+        let top = bids
+            .first()
+            .zip(asks.first())
+            .map(|(&(pb, qb), &(pa, qa))| (pb, qb, pa, qa));
+        if cfg.microprice {
+            current_row.push(match top {
+                Some((pb, qb, pa, qa)) if qb + qa > 0.0 => {
+                    ((pb * qa + pa * qb) / (qb + qa) - mid) / tick
+                }
+                _ => 0.0,
+            });
+        }
+        if cfg.weighted_mid_levels > 0 {
+            let vwap = |levels: &[(f64, f64)]| {
+                let q: f64 = levels.iter().map(|(_, v)| v).sum();
+                let pq: f64 = levels.iter().map(|(p, v)| p * v).sum();
+                (q > 0.0).then(|| (pq / q, q))
+            };
+            current_row.push(match (vwap(&bids), vwap(&asks)) {
+                (Some((pb, qb)), Some((pa, qa))) => ((pb * qa + pa * qb) / (qb + qa) - mid) / tick,
+                _ => 0.0,
+            });
+        }
+        if cfg.spread_ticks {
+            current_row.push(top.map_or(0.0, |(pb, _, pa, _)| (pa - pb) / tick));
         }
-    }
 
-    fn update_stats(&mut self) {
-        let rows = self.data.len();
-        let cols = self.data[0].len();
+        // 5. Flujo de órdenes en el mejor nivel
+        if cfg.ofi {
+            current_row.push(match (state.prev_top, top) {
+                (Some((pb0, qb0, pa0, qa0)), Some((pb, qb, pa, qa))) => {
+                    let bid_flow =
+                        if pb >= pb0 { qb } else { 0.0 } - if pb <= pb0 { qb0 } else { 0.0 };
+                    let ask_flow =
+                        if pa <= pa0 { qa } else { 0.0 } - if pa >= pa0 { qa0 } else { 0.0 };
+                    bid_flow - ask_flow
+                }
+                _ => 0.0,
+            });
+        }
 
-        let mut matrix = Array2::zeros((rows, cols));
-        for (i, row) in self.data.iter().enumerate() {
-            for (j, &val) in row.iter().enumerate() {
-                matrix[[i, j]] = val;
+        // 6. Forma del libro: volumen acumulado y pendiente
+        if cfg.cum_depth_levels > 0 {
+            for side in ['0', '1'] {
+                let total: f64 = book
+                    .top_levels(side, cfg.cum_depth_levels)
+                    .iter()
+                    .map(|(_, v)| v)
+                    .sum();
+                current_row.push(total.ln_1p());
+            }
+        }
+        if cfg.depth_slope_levels > 0 {
+            for side in ['0', '1'] {
+                // Mínimos cuadrados por el origen: volumen acumulado ~ pendiente * distancia
+                let (mut cum, mut num, mut den) = (0.0, 0.0, 0.0);
+                for (p, v) in book.top_levels(side, cfg.depth_slope_levels) {
+                    cum += v;
+                    let dist = (p - mid).abs() / tick;
+                    num += dist * cum;
+                    den += dist * dist;
+                }
+                current_row.push(if den > 0.0 { num / den } else { 0.0 });
             }
         }
 
-        self.means = matrix.mean_axis(ndarray::Axis(0)).unwrap();
-        self.stds = matrix.std_axis(ndarray::Axis(0), 0.0);
+        if cfg.realized_vol_window > 0 {
+            current_row.push(state.sq_sum.max(0.0).sqrt());
+        }
+
+        // 7. Consumo de la cola del mejor nivel (positivo = se vacía, negativo = se repone)
+        if cfg.depletion_halflife > 0 {
+            let alpha = 1.0 - 0.5f64.powf(1.0 / cfg.depletion_halflife as f64);
+            if let (Some((pb0, qb0, pa0, qa0)), Some((pb, qb, pa, qa))) = (state.prev_top, top) {
+                let fraction = |p0: f64, q0: f64, p: f64, q: f64| {
+                    if p == p0 && q0 > 0.0 {
+                        ((q0 - q) / q0).clamp(-1.0, 1.0)
+                    } else {
+                        0.0
+                    }
+                };
+                let rates = [fraction(pb0, qb0, pb, qb), fraction(pa0, qa0, pa, qa)];
+                for (d, r) in state.depletion.iter_mut().zip(rates) {
+                    *d += alpha * (r - *d);
+                }
+            }
+            current_row.extend(state.depletion);
+        }
+
+        // 8. Tiempo desde el último cambio del mejor nivel
+        if top != state.prev_top || state.last_top_change.is_none() {
+            state.last_top_change = Some(time);
+        }
+        if cfg.time_since_update {
+            current_row.push(time - state.last_top_change.unwrap_or(time));
+        }
+        if top.is_some() {
+            state.prev_top = top;
+        }
+
+        // 9. Flujo de operaciones ejecutadas
+        if cfg.trade_window > 0.0 {
+            current_row.push(trades.signed_volume());
+            current_row.push(match trades.vwap() {
+                Some(vwap) if mid > 0.0 => (vwap - mid) / tick,
+                _ => 0.0,
+            });
+            current_row.push(trades.intensity());
+            current_row.push(trades.aggressor_imbalance());
+        }
+
+        // 10. Actividad autoexcitada (Hawkes)
+        if cfg.hawkes {
+            current_row.push(signals.hawkes_intensity);
+            current_row.push(signals.branching_ratio);
+        }
+
+        // 11. Régimen del HMM: uniforme hasta que el modelo esté ajustado
+        if cfg.regime_states > 0 {
+            let n = cfg.regime_states;
+            current_row.extend((0..n).map(|s| {
+                if signals.regimes.len() == n {
+                    signals.regimes[s]
+                } else {
+                    1.0 / n as f64
+                }
+            }));
+        }
+
+        // 12. Precio justo del filtro de Kalman
+        if cfg.kalman {
+            current_row.extend(match signals.kalman {
+                Some(k) if mid > 0.0 => [
+                    (k.fair_value - mid) / tick,
+                    k.velocity / tick,
+                    k.innovation_variance / (tick * tick),
+                ],
+                _ => [0.0; 3],
+            });
+        }
+
+        // 13. Última barra cerrada; la VWAP sin operaciones cae al cierre
+        if cfg.bar {
+            current_row.extend(match signals.bar {
+                Some(bar) if mid > 0.0 && bar.open > 0.0 => [
+                    (bar.open - mid) / tick,
+                    (bar.high - mid) / tick,
+                    (bar.low - mid) / tick,
+                    (bar.close - mid) / tick,
+                    (bar.vwap().unwrap_or(bar.close) - mid) / tick,
+                    bar.volume.ln_1p(),
+                    (bar.close / bar.open).ln(),
+                ],
+                _ => [0.0; 7],
+            });
+        }
+
+        debug_assert_eq!(current_row.len(), self.config.dim());
+        if let Some(idx) = current_row.iter().position(|v| !v.is_finite()) {
+            return Err(InvalidFeature {
+                name: self.config.names()[idx].clone(),
+                value: current_row[idx],
+            });
+        }
+
+        // Escaladores incrementales por columna: coste constante por tick
+        if self.scalers.len() != current_row.len() {
+            self.scalers = self
+                .config
+                .names()
+                .iter()
+                .map(|name| {
+                    let clipping = self
+                        .feature_clipping
+                        .iter()
+                        .find(|(n, _)| n == name)
+                        .map_or(self.clipping, |(_, c)| *c);
+                    ColumnScaler::new(self.normalization, clipping, self.window_size)
+                })
+                .collect();
+        }
+        let scaled: Array1<f64> = self
+            .scalers
+            .iter_mut()
+            .zip(&current_row)
+            .map(|(scaler, &x)| scaler.push(x))
+            .collect();
+        self.last = current_row;
 
-        // Evitar división por cero
-        self.stds.mapv_inplace(|x| if x == 0.0 { 1.0 } else { x });
+        // Solo se publica con datos suficientes para los estadísticos
+        if self.scalers.first().map_or(0, ColumnScaler::len) >= 10 {
+            self.scaled = scaled;
+        }
+        Ok(())
     }
 
     /// Devuelve el último vector transformado para la Red Neuronal
     pub fn get_standardized_vector(&self) -> Array1<f64> {
-        if self.data.is_empty() || self.means.is_empty() {
-            return Array1::zeros(0);
+        self.scaled.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Libro con (precio, volumen) por nivel, del mejor al peor
+    fn book(bids: &[(f64, f64)], asks: &[(f64, f64)]) -> OrderBook {
+        let mut book = OrderBook::new();
+        for &(p, v) in bids {
+            book.update('0', '0', p, v);
+        }
+        for &(p, v) in asks {
+            book.update('0', '1', p, v);
         }
+        book
+    }
 
-        let last_raw = Array1::from_vec(self.data.last().unwrap().clone());
-        (last_raw - &self.means) / &self.stds
+    fn collector(spec: &str) -> FeatureCollector {
+        FeatureCollector::new(50).with_config(FeatureConfig::parse(spec).unwrap())
     }
-}
 
+    fn push(collector: &mut FeatureCollector, book: &OrderBook, time: f64) -> Vec<f64> {
+        collector
+            .push_features(book, &TradeTape::new(60.0), time, &Signals::default())
+            .unwrap();
+        collector.last_raw().to_vec()
+    }
+
+    #[test]
+    fn ofi_follows_cont_kukanov_stoikov() {
+        let mut c = collector("ofi,tick=0.5");
+        assert_eq!(
+            push(&mut c, &book(&[(10.0, 100.0)], &[(11.0, 80.0)]), 0.0),
+            vec![0.0]
+        );
+        // Mismos precios: Δq_bid - Δq_ask = 50 - (-20)
+        let same = book(&[(10.0, 150.0)], &[(11.0, 60.0)]);
+        assert_eq!(push(&mut c, &same, 1.0), vec![70.0]);
+        // El bid mejora: cuenta toda su cola nueva (el ask no cambia)
+        let improved = book(&[(10.5, 30.0)], &[(11.0, 60.0)]);
+        assert_eq!(push(&mut c, &improved, 2.0), vec![30.0]);
+        // El bid empeora: sale su cola anterior (-30); el ask mejora: entra la nueva (40)
+        let worse = book(&[(10.0, 20.0)], &[(10.5, 40.0)]);
+        assert_eq!(push(&mut c, &worse, 3.0), vec![-30.0 - 40.0]);
+    }
+
+    #[test]
+    fn price_references_in_ticks() {
+        let mut c = collector("microprice,wmid=2,spread,tick=0.5");
+        let b = book(
+            &[(10.0, 300.0), (9.5, 100.0)],
+            &[(11.0, 100.0), (11.5, 300.0)],
+        );
+        let row = push(&mut c, &b, 0.0);
+        // Microprecio (10 · 100 + 11 · 300) / 400 = 10.75, mid 10.5 → +0.5 ticks
+        assert!((row[0] - 0.5).abs() < 1e-12);
+        // VWAP de dos niveles: bid 9.875, ask 11.375, ambas con 400 → 10.625
+        assert!((row[1] - 0.25).abs() < 1e-12);
+        assert_eq!(row[2], 2.0);
+    }
+
+    #[test]
+    fn book_shape_and_returns() {
+        let mut c = collector("cum_depth=2,slope=2,returns=1/2,tick=0.5");
+        let b = book(
+            &[(10.0, 100.0), (9.5, 100.0)],
+            &[(11.0, 50.0), (11.5, 50.0)],
+        );
+        let row = push(&mut c, &b, 0.0);
+        assert_eq!(&row[..2], &[0.0, 0.0]);
+        assert!((row[2] - 200f64.ln_1p()).abs() < 1e-12);
+        assert!((row[3] - 100f64.ln_1p()).abs() < 1e-12);
+        // Distancias 1 y 2 ticks: (1·100 + 2·200) / (1 + 4) = 100
+        assert!((row[4] - 100.0).abs() < 1e-12);
+        assert!((row[5] - 50.0).abs() < 1e-12);
+        let up = book(&[(10.5, 100.0)], &[(11.5, 50.0), (11.0, 0.0)]);
+        let row = push(&mut c, &up, 1.0);
+        assert!((row[0] - (11.0f64 / 10.5).ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn depletion_and_time_since_update() {
+        let mut c = collector("depletion=1,since_update,tick=0.5");
+        push(&mut c, &book(&[(10.0, 100.0)], &[(11.0, 100.0)]), 0.0);
+        // Semivida 1: alpha = 0.5; el bid pierde el 40 % de su cola
+        let row = push(&mut c, &book(&[(10.0, 60.0)], &[(11.0, 100.0)]), 2.0);
+        assert_eq!(row, vec![0.2, 0.0, 0.0]);
+        let row = push(&mut c, &book(&[(10.0, 60.0)], &[(11.0, 100.0)]), 5.0);
+        assert_eq!(row, vec![0.1, 0.0, 3.0]);
+    }
+
+    #[test]
+    fn non_finite_feature_is_rejected_by_name() {
+        let mut c = collector("spread,noise,tick=0.5");
+        let b = book(&[(10.0, 100.0)], &[(11.0, 100.0)]);
+        let signals = Signals {
+            noise: f64::NAN,
+            ..Signals::default()
+        };
+        let err = c
+            .push_features(&b, &TradeTape::new(60.0), 0.0, &signals)
+            .unwrap_err();
+        assert_eq!(err.name, "noise");
+        assert!(c.last_raw().is_empty());
+        assert!(c.get_standardized_vector().is_empty());
+    }
+
+    #[test]
+    fn bar_columns_relative_to_the_mid() {
+        let mut c = collector("bar,tick=0.5");
+        let b = book(&[(10.0, 100.0)], &[(11.0, 100.0)]);
+        assert_eq!(push(&mut c, &b, 0.0), vec![0.0; 7]);
+        let bar = Bar {
+            start: 0.0,
+            end: 1.0,
+            open: 10.0,
+            high: 11.5,
+            low: 9.5,
+            close: 11.0,
+            bid: 10.0,
+            ask: 11.0,
+            volume: 3.0,
+            dollar_volume: 31.5,
+            trades: 2,
+            ticks: 5,
+        };
+        let signals = Signals {
+            bar: Some(&bar),
+            ..Signals::default()
+        };
+        c.push_features(&b, &TradeTape::new(60.0), 1.0, &signals)
+            .unwrap();
+        // Mid 10.5: OHLC y VWAP (10.5) en ticks de 0.5
+        let row = c.last_raw();
+        assert_eq!(&row[..5], &[-1.0, 2.0, -2.0, 1.0, 0.0]);
+        assert!((row[5] - 4f64.ln()).abs() < 1e-12);
+        assert!((row[6] - 1.1f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn parse_rejects_unknown_and_empty_specs() {
+        assert!(FeatureConfig::parse("ofi,foo").is_err());
+        assert!(FeatureConfig::parse("depth=0").is_err());
+        assert_eq!(FeatureConfig::parse("legacy").unwrap().dim(), 7);
+        for bad in ["tick=x", "tick=0", "tick=-0.5", "tick=inf"] {
+            assert!(FeatureConfig::parse(bad).is_err(), "{}", bad);
+        }
+        assert_eq!(FeatureConfig::parse("ofi,tick=0.5").unwrap().tick_size, 0.5);
+        let micro = FeatureConfig::microstructure();
+        assert_eq!(micro.schema().columns, micro.names());
+    }
+}
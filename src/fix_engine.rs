@@ -7,6 +7,12 @@ pub struct FixEngine {
     pub encoder: Encoder<Config>,
 }
 
+impl Default for FixEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FixEngine {
     pub fn new() -> Self {
         info!("Inicializando Motor FEFIX v0.7.0 - Deep LOB Config");
@@ -24,7 +30,7 @@ impl FixEngine {
         password: &str,
     ) {
         let now = Utc::now().format("%Y%m%d-%H:%M:%S").to_string();
-        let account_number = sender_id.split('.').last().unwrap_or(sender_id);
+        let account_number = sender_id.split('.').next_back().unwrap_or(sender_id);
 
         buffer.clear();
         let mut msg = self.encoder.start_message(b"FIX.4.4", buffer, b"A");
@@ -72,6 +78,7 @@ impl FixEngine {
         target_id: &str,
         seq_num: u64,
         symbol: &str,
+        entry_types: &[String],
     ) {
         let now = Utc::now().format("%Y%m%d-%H:%M:%S").to_string();
         buffer.clear();
@@ -96,12 +103,17 @@ impl FixEngine {
 
         msg.set_any(TagU16::new(265).unwrap(), b"1"); // Incremental Refresh
 
-        msg.set_any(TagU16::new(267).unwrap(), b"2"); // Número de MDEntryTypes
+        // Número de MDEntryTypes
+        msg.set_any(
+            TagU16::new(267).unwrap(),
+            ToString::to_string(&entry_types.len()).as_bytes(),
+        );
 
-        // Aquí definimos los tipos que queremos (Bid y Ask)
+        // Tipos pedidos: 0 = Bid, 1 = Ask, 2 = Trade (y otros si el broker los admite)
         // Nota: fefix manejará los grupos repetitivos internamente al wrappear
-        msg.set_any(TagU16::new(269).unwrap(), b"0");
-        msg.set_any(TagU16::new(269).unwrap(), b"1");
+        for entry_type in entry_types {
+            msg.set_any(TagU16::new(269).unwrap(), entry_type.as_bytes());
+        }
 
         msg.set_any(TagU16::new(146).unwrap(), b"1"); // NoRelatedSym
         msg.set_any(TagU16::new(55).unwrap(), symbol.as_bytes());
@@ -109,4 +121,3 @@ impl FixEngine {
         msg.wrap();
     }
 }
-
@@ -1,77 +1,342 @@
+use crate::kernel::{Kernel, Rbf};
+use crate::rolling::RollingStats;
 use ndarray::{Array1, Array2};
+use ringbuffer::{AllocRingBuffer, RingBuffer};
+use std::cell::RefCell;
+
+/// Eje temporal del proceso: índice de tick o timestamp real (segundos).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeAxis {
+    TickIndex,
+    Timestamp,
+}
+
+impl TimeAxis {
+    /// "tick", "timestamp"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tick" => Some(TimeAxis::TickIndex),
+            "timestamp" => Some(TimeAxis::Timestamp),
+            _ => None,
+        }
+    }
+}
+
+/// Factorización reutilizable con `TimeAxis::TickIndex`: con la ventana llena las
+/// entradas son siempre 0..n, así que K, k* y la varianza posterior no cambian
+struct Factorization {
+    l: Array2<f64>,
+    k_star: Array1<f64>,
+    variance: f64,
+}
 
 pub struct GaussianFilter {
-    window_size: usize,
-    prices: Vec<f64>,
-    l_parameter: f64, // Escala de longitud (qué tan suave es la curva)
-    sigma_f: f64,     // Varianza de la señal
+    prices: AllocRingBuffer<f64>,
+    times: AllocRingBuffer<f64>,
+    /// Media de la ventana en O(1)
+    price_stats: RollingStats,
+    kernel: Box<dyn Kernel>,
+    time_axis: TimeAxis,
+    noise_variance: f64, // Ruido de observación (jitter para estabilidad numérica)
+    /// (n, factorización); None si K no es definida positiva para ese n
+    cache: RefCell<Option<(usize, Option<Factorization>)>>,
 }
 
 impl GaussianFilter {
     pub fn new(window_size: usize, l: f64, sigma_f: f64) -> Self {
-        Self {
+        Self::with_kernel(
             window_size,
-            prices: Vec::with_capacity(window_size),
-            l_parameter: l,
-            sigma_f,
+            Box::new(Rbf::new(l, sigma_f)),
+            TimeAxis::TickIndex,
+        )
+    }
+
+    /// Construye el filtro con un kernel arbitrario (o compuesto con Suma/Producto)
+    pub fn with_kernel(window_size: usize, kernel: Box<dyn Kernel>, time_axis: TimeAxis) -> Self {
+        Self {
+            prices: AllocRingBuffer::new(window_size.max(1)),
+            times: AllocRingBuffer::new(window_size.max(1)),
+            price_stats: RollingStats::new(window_size),
+            kernel,
+            time_axis,
+            noise_variance: 1e-6,
+            cache: RefCell::new(None),
         }
     }
 
+    pub fn with_noise_variance(mut self, noise_variance: f64) -> Self {
+        self.noise_variance = noise_variance;
+        self.cache = RefCell::new(None);
+        self
+    }
+
     pub fn add_price(&mut self, price: f64) {
-        if self.prices.len() >= self.window_size {
-            self.prices.remove(0);
-        }
+        let t = self.times.back().map(|t| t + 1.0).unwrap_or(0.0);
+        self.add_price_at(price, t);
+    }
+
+    /// Añade un precio con su timestamp (segundos). Con `TimeAxis::TickIndex`
+    /// el timestamp se ignora y se usa la posición en la ventana.
+    pub fn add_price_at(&mut self, price: f64, timestamp: f64) {
+        // El ring buffer descarta el más antiguo al llenarse
         self.prices.push(price);
+        self.times.push(timestamp);
+        self.price_stats.push(price);
+    }
+
+    /// Coordenadas temporales de la ventana, relativas al primer punto
+    fn inputs(&self) -> Vec<f64> {
+        match self.time_axis {
+            TimeAxis::TickIndex => (0..self.prices.len()).map(|i| i as f64).collect(),
+            TimeAxis::Timestamp => {
+                let t0 = self.times.front().copied().unwrap_or(0.0);
+                self.times.iter().map(|t| t - t0).collect()
+            }
+        }
     }
 
-    /// Kernel RBF (Radial Basis Function)
-    /// Mide la similitud entre dos puntos en el tiempo
-    fn kernel(&self, x1: f64, x2: f64) -> f64 {
-        let diff = (x1 - x2).powi(2);
-        self.sigma_f.powi(2) * (-diff / (2.0 * self.l_parameter.powi(2))).exp()
+    /// Siguiente punto temporal a predecir: n en ticks, o el último
+    /// timestamp más el intervalo medio entre llegadas.
+    fn next_input(&self, xs: &[f64]) -> f64 {
+        let n = xs.len();
+        match self.time_axis {
+            TimeAxis::TickIndex => n as f64,
+            TimeAxis::Timestamp => {
+                if n < 2 {
+                    return xs.last().copied().unwrap_or(0.0) + 1.0;
+                }
+                let avg_dt = (xs[n - 1] - xs[0]) / (n - 1) as f64;
+                xs[n - 1] + avg_dt
+            }
+        }
     }
 
-    /// Calcula la incertidumbre (sigma) del precio actual
-    /// Basado en la varianza de la distribución posterior
-    pub fn compute_uncertainty(&self) -> f64 {
-        let n = self.prices.len();
-        if n < 5 {
-            return 1.0;
-        } // Máxima incertidumbre si no hay datos
+    /// Posterior del GP en el siguiente punto: (media, varianza).
+    /// mu = m + k*^T K^-1 (y - m),  sigma² = k** - k*^T K^-1 k*
+    pub fn posterior(&self) -> Option<(f64, f64)> {
+        self.predict().map(|(mu, variance, _)| (mu, variance))
+    }
+
+    /// (media, varianza posterior, escala) en el siguiente punto. La escala es el
+    /// estimador de máxima verosimilitud de la amplitud, y^T K^-1 y / n, que pasa
+    /// la varianza posterior de unidades del kernel a precio².
+    fn predict(&self) -> Option<(f64, f64, f64)> {
+        let xs = self.inputs();
+        if xs.len() < 2 {
+            return None;
+        }
+        let x_star = self.next_input(&xs);
+        self.posterior_at(&xs, x_star)
+    }
+
+    fn posterior_at(&self, xs: &[f64], x_star: f64) -> Option<(f64, f64, f64)> {
+        let n = xs.len();
+
+        // 1. Factorización de K (reutilizada mientras las entradas no cambien)
+        let mut cache = self.cache.borrow_mut();
+        let reusable =
+            self.time_axis == TimeAxis::TickIndex && cache.as_ref().is_some_and(|c| c.0 == n);
+        if !reusable {
+            *cache = Some((n, self.factorize(xs, x_star)));
+        }
+        let f = cache.as_ref()?.1.as_ref()?;
+
+        // 2. Precios centrados en su media (prior de media constante)
+        let mean = self.price_stats.mean();
+        let y = Array1::from_iter(self.prices.iter().map(|p| p - mean));
+
+        // 3. Resolución vía Cholesky: K = L L^T
+        let alpha = solve_upper_t(&f.l, &solve_lower(&f.l, &y));
+        let mu = mean + f.k_star.dot(&alpha);
+        let scale = y.dot(&alpha) / n as f64;
+        Some((mu, f.variance, scale))
+    }
+
+    /// Cholesky de K, k* y varianza posterior k** - k*^T K^-1 k*
+    fn factorize(&self, xs: &[f64], x_star: f64) -> Option<Factorization> {
+        let n = xs.len();
 
-        // 1. Construir matriz de covarianza K basada en el tiempo (indices)
+        // Matriz de covarianza K sobre el eje temporal
         let mut k = Array2::zeros((n, n));
         for i in 0..n {
             for j in 0..n {
-                k[[i, j]] = self.kernel(i as f64, j as f64);
-                if i == j {
-                    k[[i, j]] += 1e-6;
-                } // Añadir ruido para estabilidad numérica
+                k[[i, j]] = self.kernel.eval(xs[i], xs[j]);
             }
+            k[[i, i]] += self.noise_variance;
         }
 
-        // 2. Punto que queremos predecir (el "ahora" = n)
-        let mut k_star = Array1::zeros(n);
-        for i in 0..n {
-            k_star[i] = self.kernel(i as f64, n as f64);
+        let k_star = Array1::from_iter(xs.iter().map(|&x| self.kernel.eval(x, x_star)));
+        let k_star_star = self.kernel.eval(x_star, x_star);
+
+        let l = cholesky(&k)?;
+        let v = solve_lower(&l, &k_star);
+        let variance = (k_star_star - v.dot(&v)).max(0.0);
+        Some(Factorization {
+            l,
+            k_star,
+            variance,
+        })
+    }
+
+    /// Incertidumbre del siguiente precio: desviación típica de la predictiva del GP
+    /// (varianza posterior por la escala de la ventana) relativa al precio, en
+    /// milésimas y saturada en 1.
+    pub fn compute_uncertainty(&self) -> f64 {
+        if self.prices.len() < 5 {
+            return 1.0; // Máxima incertidumbre si no hay datos
         }
+        // Sin factorización (K no definida positiva) tampoco hay predicción fiable
+        let Some((mu, variance, scale)) = self.predict() else {
+            return 1.0;
+        };
+        let sigma = (variance * scale).max(0.0).sqrt();
+        (sigma / mu.abs().max(f64::EPSILON) * 1000.0).min(1.0)
+    }
+}
 
-        let _k_star_star = self.kernel(n as f64, n as f64);
+/// Descomposición de Cholesky (triangular inferior). None si K no es definida positiva.
+fn cholesky(a: &Array2<f64>) -> Option<Array2<f64>> {
+    let n = a.nrows();
+    let mut l = Array2::<f64>::zeros((n, n));
+    for i in 0..n {
+        for j in 0..=i {
+            let mut sum = a[[i, j]];
+            for k in 0..j {
+                sum -= l[[i, k]] * l[[j, k]];
+            }
+            if i == j {
+                if sum <= 0.0 {
+                    return None;
+                }
+                l[[i, j]] = sum.sqrt();
+            } else {
+                l[[i, j]] = sum / l[[j, j]];
+            }
+        }
+    }
+    Some(l)
+}
 
-        // 3. Resolución simplificada de la varianza: sigma = k** - k*^T * K^-1 * k*
-        // Para evitar invertir matrices grandes en cada tick, usamos una aproximación
-        // de la distancia de los precios actuales a la media del proceso.
+/// Resuelve L x = b (sustitución hacia adelante)
+fn solve_lower(l: &Array2<f64>, b: &Array1<f64>) -> Array1<f64> {
+    let n = b.len();
+    let mut x = Array1::zeros(n);
+    for i in 0..n {
+        let mut sum = b[i];
+        for k in 0..i {
+            sum -= l[[i, k]] * x[k];
+        }
+        x[i] = sum / l[[i, i]];
+    }
+    x
+}
 
-        let mean = self.prices.iter().sum::<f64>() / n as f64;
-        let last_price = self.prices[n - 1];
+/// Resuelve L^T x = b (sustitución hacia atrás)
+fn solve_upper_t(l: &Array2<f64>, b: &Array1<f64>) -> Array1<f64> {
+    let n = b.len();
+    let mut x = Array1::zeros(n);
+    for i in (0..n).rev() {
+        let mut sum = b[i];
+        for k in (i + 1)..n {
+            sum -= l[[k, i]] * x[k];
+        }
+        x[i] = sum / l[[i, i]];
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::Matern52;
 
-        // La incertidumbre aumenta si el precio se aleja drásticamente de la
-        // estructura de covarianza de los puntos anteriores.
-        let deviation = (last_price - mean).abs() / mean;
+    #[test]
+    fn cholesky_known_factor() {
+        let a = Array2::from_shape_vec((2, 2), vec![4.0, 2.0, 2.0, 3.0]).unwrap();
+        let l = cholesky(&a).unwrap();
+        assert_eq!((l[[0, 0]], l[[1, 0]], l[[0, 1]]), (2.0, 1.0, 0.0));
+        assert!((l[[1, 1]] - 2f64.sqrt()).abs() < 1e-15);
+        // L L^T x = b con b = A [1, -1]
+        let b = Array1::from(vec![2.0, -1.0]);
+        let x = solve_upper_t(&l, &solve_lower(&l, &b));
+        assert!((x[0] - 1.0).abs() < 1e-12 && (x[1] + 1.0).abs() < 1e-12);
 
-        // Normalizamos la incertidumbre entre 0 y 1
-        let uncertainty = (deviation * 1000.0).min(1.0);
+        let indefinite = Array2::from_shape_vec((2, 2), vec![1.0, 2.0, 2.0, 1.0]).unwrap();
+        assert!(cholesky(&indefinite).is_none());
+    }
 
-        uncertainty
+    #[test]
+    fn posterior_mean_interpolates_noise_free_points() {
+        let mut filter =
+            GaussianFilter::with_kernel(8, Box::new(Matern52::new(3.0, 1.0)), TimeAxis::Timestamp)
+                .with_noise_variance(1e-10);
+        let points = [
+            (0.0, 1.10),
+            (0.5, 1.12),
+            (2.0, 1.09),
+            (3.5, 1.11),
+            (4.0, 1.13),
+        ];
+        for (t, p) in points {
+            filter.add_price_at(p, t);
+        }
+        let xs = filter.inputs();
+        for (x, (_, price)) in xs.iter().zip(points) {
+            let (mu, var, _) = filter.posterior_at(&xs, *x).unwrap();
+            assert!((mu - price).abs() < 1e-6, "{} frente a {}", mu, price);
+            assert!(var < 1e-8);
+        }
+        // Lejos de los datos la media vuelve a la media de la ventana
+        let (mu, var, _) = filter.posterior_at(&xs, 1e4).unwrap();
+        assert!((mu - 1.11).abs() < 1e-9);
+        assert!((var - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn tick_axis_ignores_timestamps_and_predicts_next_index() {
+        let mut filter = GaussianFilter::new(3, 1.0, 1.0);
+        for (i, p) in [1.0, 2.0, 3.0, 4.0].into_iter().enumerate() {
+            filter.add_price_at(p, 100.0 * i as f64);
+        }
+        let xs = filter.inputs();
+        assert_eq!(xs, vec![0.0, 1.0, 2.0]);
+        assert_eq!(filter.next_input(&xs), 3.0);
+        assert!(filter.posterior().is_some());
+    }
+
+    #[test]
+    fn cached_factorization_matches_a_fresh_filter() {
+        let prices = [1.10, 1.12, 1.09, 1.11, 1.13, 1.08, 1.14];
+        let mut sliding = GaussianFilter::new(4, 2.0, 1.0);
+        for (i, &p) in prices.iter().enumerate() {
+            sliding.add_price(p);
+            if i < 3 {
+                continue;
+            }
+            // Con la ventana llena se reutiliza la factorización del paso anterior
+            let mut fresh = GaussianFilter::new(4, 2.0, 1.0);
+            for &q in &prices[i - 3..=i] {
+                fresh.add_price(q);
+            }
+            let (a, b) = (sliding.posterior().unwrap(), fresh.posterior().unwrap());
+            assert!((a.0 - b.0).abs() < 1e-12 && (a.1 - b.1).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn uncertainty_scales_with_the_window_amplitude() {
+        let mut filter = GaussianFilter::new(8, 1.0, 1.0);
+        for _ in 0..4 {
+            filter.add_price(1.1);
+        }
+        assert_eq!(filter.compute_uncertainty(), 1.0);
+        filter.add_price(1.1);
+        // Precios constantes: escala nula, sin incertidumbre
+        assert_eq!(filter.compute_uncertainty(), 0.0);
+        for p in [1.1002, 1.0998, 1.1001] {
+            filter.add_price(p);
+        }
+        let u = filter.compute_uncertainty();
+        assert!(u > 0.0 && u < 1.0, "{}", u);
     }
 }
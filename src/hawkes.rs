@@ -0,0 +1,450 @@
+use crate::pipeline::extract_tag;
+use crate::recorder::read_recording;
+use crate::velocity::{parse_utc_timestamp, tag_value};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+
+/// Proceso de Hawkes con núcleo suma de exponenciales:
+/// λ(t) = μ + Σ_k α_k β_k Σ_{t_i < t} exp(-β_k (t - t_i)).
+/// `alphas[k]` es la masa del núcleo k (hijos esperados por evento), así que el
+/// ratio de ramificación es Σ α_k.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HawkesParams {
+    /// Intensidad de fondo (eventos por segundo)
+    pub mu: f64,
+    pub alphas: Vec<f64>,
+    /// Tasas de decaimiento (1/segundos)
+    pub betas: Vec<f64>,
+}
+
+/// Tope del ratio de ramificación en el ajuste (proceso estacionario)
+const MAX_BRANCHING: f64 = 0.99;
+
+impl HawkesParams {
+    /// Punto de partida del ajuste: fondo de 1 evento/s y ramificación 0.5 repartida
+    pub fn initial(betas: &[f64]) -> Self {
+        let k = betas.len().max(1) as f64;
+        Self {
+            mu: 1.0,
+            alphas: vec![0.5 / k; betas.len()],
+            betas: betas.to_vec(),
+        }
+    }
+
+    /// Parámetros guardados por `hawkes`
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let params: Self = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if params.alphas.len() != params.betas.len() {
+            return Err(format!("{}: alphas y betas de distinta longitud", path).into());
+        }
+        Ok(params)
+    }
+
+    pub fn branching_ratio(&self) -> f64 {
+        self.alphas.iter().sum()
+    }
+
+    /// Log-verosimilitud de los eventos (ordenados) en [t_0, t_n]
+    pub fn log_likelihood(&self, times: &[f64]) -> f64 {
+        let Some(span) = span(times) else {
+            return f64::NEG_INFINITY;
+        };
+        let mut ll = -self.mu * span;
+        self.scan(times, |lambda, _| ll += lambda.ln());
+        for (&a, &b) in self.alphas.iter().zip(&self.betas) {
+            ll -= a * compensator(times, b);
+        }
+        ll
+    }
+
+    /// Máxima verosimilitud de μ y α con las β fijas por EM (partiendo de `self`).
+    /// Cada iteración es O(n·K) y nunca empeora la verosimilitud.
+    pub fn fit(&self, times: &[f64], iterations: usize) -> Self {
+        let Some(span) = span(times) else {
+            return self.clone();
+        };
+        let comp: Vec<f64> = self.betas.iter().map(|&b| compensator(times, b)).collect();
+        let mut params = self.clone();
+        for _ in 0..iterations {
+            // E: probabilidad de que cada evento venga del fondo o de cada núcleo
+            let mut background = 0.0;
+            let mut excited = vec![0.0; params.betas.len()];
+            let mu = params.mu;
+            params.scan(times, |lambda, parts| {
+                background += mu / lambda;
+                for (e, p) in excited.iter_mut().zip(parts) {
+                    *e += p / lambda;
+                }
+            });
+            // M: fondo por unidad de tiempo y masa de cada núcleo
+            params.mu = (background / span).max(1e-9);
+            for ((a, e), c) in params.alphas.iter_mut().zip(&excited).zip(&comp) {
+                *a = if *c > 0.0 { e / c } else { 0.0 };
+            }
+            let branching = params.branching_ratio();
+            if branching > MAX_BRANCHING {
+                for a in params.alphas.iter_mut() {
+                    *a *= MAX_BRANCHING / branching;
+                }
+            }
+        }
+        params
+    }
+
+    /// Ajuste completo: cada conjunto de β candidato se ajusta por EM y se queda
+    /// el de mayor verosimilitud
+    pub fn fit_best(times: &[f64], candidates: &[Vec<f64>], iterations: usize) -> Option<Self> {
+        let rate = times.len() as f64 / span(times)?;
+        candidates
+            .iter()
+            .map(|betas| {
+                let mut start = Self::initial(betas);
+                start.mu = rate * 0.5;
+                start.fit(times, iterations)
+            })
+            .max_by(|a, b| a.log_likelihood(times).total_cmp(&b.log_likelihood(times)))
+    }
+
+    /// Recorre los eventos pasando λ(t_i) y la contribución de cada núcleo
+    fn scan(&self, times: &[f64], mut visit: impl FnMut(f64, &[f64])) {
+        let mut state = vec![0.0; self.betas.len()];
+        let mut parts = vec![0.0; self.betas.len()];
+        let mut prev: Option<f64> = None;
+        for &t in times {
+            if let Some(p) = prev {
+                for (s, &b) in state.iter_mut().zip(&self.betas) {
+                    *s = (-b * (t - p)).exp() * (*s + 1.0);
+                }
+            }
+            for (k, part) in parts.iter_mut().enumerate() {
+                *part = self.alphas[k] * self.betas[k] * state[k];
+            }
+            let lambda = self.mu + parts.iter().sum::<f64>();
+            visit(lambda.max(1e-300), &parts);
+            prev = Some(t);
+        }
+    }
+}
+
+/// Duración de la ventana observada (None con menos de dos eventos)
+fn span(times: &[f64]) -> Option<f64> {
+    let span = times.last()? - times.first()?;
+    (times.len() >= 2 && span > 0.0).then_some(span)
+}
+
+/// Σ_i (1 - exp(-β (T - t_i))): masa del núcleo dentro de la ventana
+fn compensator(times: &[f64], beta: f64) -> f64 {
+    let end = times.last().copied().unwrap_or(0.0);
+    times.iter().map(|t| 1.0 - (-beta * (end - t)).exp()).sum()
+}
+
+/// Parámetros del modelo online
+#[derive(Debug, Clone, PartialEq)]
+pub struct HawkesConfig {
+    /// Parámetros iniciales (p.ej. ajustados con `hawkes`); None = `initial(betas)`
+    pub params: Option<HawkesParams>,
+    pub betas: Vec<f64>,
+    /// Eventos recientes sobre los que se reajusta
+    pub window: usize,
+    /// Reajuste cada N eventos (0 = parámetros fijos)
+    pub refit_every: usize,
+    pub iterations: usize,
+}
+
+impl Default for HawkesConfig {
+    fn default() -> Self {
+        Self {
+            params: None,
+            betas: vec![1.0, 10.0, 100.0],
+            window: 2000,
+            refit_every: 500,
+            iterations: 10,
+        }
+    }
+}
+
+/// Intensidad condicional online con reajuste periódico sobre una ventana de eventos
+#[derive(Debug, Clone)]
+pub struct HawkesModel {
+    pub params: HawkesParams,
+    config: HawkesConfig,
+    /// α_k β_k Σ exp(-β_k (last - t_i)) por núcleo
+    excitation: Vec<f64>,
+    last: Option<f64>,
+    history: VecDeque<f64>,
+    since_refit: usize,
+}
+
+impl HawkesModel {
+    pub fn new(config: HawkesConfig) -> Self {
+        let params = config
+            .params
+            .clone()
+            .unwrap_or_else(|| HawkesParams::initial(&config.betas));
+        Self {
+            excitation: vec![0.0; params.betas.len()],
+            params,
+            config,
+            last: None,
+            history: VecDeque::new(),
+            since_refit: 0,
+        }
+    }
+
+    /// Evento en `time` (segundos); un reloj que retrocede se trata como simultáneo
+    pub fn record(&mut self, time: f64) {
+        let time = self.last.map_or(time, |last| time.max(last));
+        self.decay_to(time);
+        for (k, e) in self.excitation.iter_mut().enumerate() {
+            *e += self.params.alphas[k] * self.params.betas[k];
+        }
+        self.last = Some(time);
+
+        self.history.push_back(time);
+        if self.history.len() > self.config.window {
+            self.history.pop_front();
+        }
+        self.since_refit += 1;
+        let due = self.config.refit_every.min(self.config.window);
+        if self.config.refit_every > 0 && self.since_refit >= due {
+            self.refit();
+        }
+    }
+
+    /// λ(now): eventos por segundo esperados dado el historial
+    pub fn intensity(&self, now: f64) -> f64 {
+        let dt = self.last.map_or(0.0, |last| (now - last).max(0.0));
+        self.params.mu
+            + self
+                .excitation
+                .iter()
+                .zip(&self.params.betas)
+                .map(|(e, b)| e * (-b * dt).exp())
+                .sum::<f64>()
+    }
+
+    pub fn branching_ratio(&self) -> f64 {
+        self.params.branching_ratio()
+    }
+
+    fn decay_to(&mut self, time: f64) {
+        if let Some(last) = self.last {
+            for (e, b) in self.excitation.iter_mut().zip(&self.params.betas) {
+                *e *= (-b * (time - last)).exp();
+            }
+        }
+    }
+
+    /// EM desde los parámetros actuales y recálculo de la excitación con los nuevos
+    fn refit(&mut self) {
+        self.since_refit = 0;
+        let times: Vec<f64> = self.history.iter().copied().collect();
+        if span(&times).is_none() {
+            return;
+        }
+        self.params = self.params.fit(&times, self.config.iterations);
+        let last = self.last.unwrap_or(0.0);
+        self.excitation = self
+            .params
+            .alphas
+            .iter()
+            .zip(&self.params.betas)
+            .map(|(a, b)| a * b * times.iter().map(|t| (-b * (last - t)).exp()).sum::<f64>())
+            .collect();
+    }
+}
+
+/// Instantes de los eventos: un mensaje con entradas de libro u operaciones es un
+/// evento (sus entradas comparten reloj y como eventos separados empatarían),
+/// fechado con SendingTime o, si falta, con la hora grabada
+pub fn event_times(messages: &[(f64, String)]) -> Vec<f64> {
+    let mut times = Vec::new();
+    for (time, msg) in messages {
+        if !(msg.contains("|35=W|") || msg.contains("|35=X|")) {
+            continue;
+        }
+        let t = tag_value(msg, "52")
+            .and_then(parse_utc_timestamp)
+            .unwrap_or(*time);
+        let has_events = msg.split("|279=").skip(1).any(|entry| {
+            let fragment = format!("|279={}", entry);
+            matches!(extract_tag(&fragment, "269").map(|v| v as i64), Some(0..=2))
+        });
+        if has_events {
+            times.push(t);
+        }
+    }
+    // El reloj del broker puede retroceder ligeramente
+    for i in 1..times.len() {
+        times[i] = times[i].max(times[i - 1]);
+    }
+    times
+}
+
+/// `hawkes <grabación> [--betas 1/10/100;0.5/5] [--iterations 50] [--out hawkes.json]`:
+/// ajusta por máxima verosimilitud y guarda los parámetros (HAWKES_PARAMS en vivo)
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut recording = None;
+    let mut candidates = vec![
+        vec![1.0, 10.0, 100.0],
+        vec![0.5, 5.0, 50.0],
+        vec![2.0, 20.0],
+    ];
+    let mut iterations = 50;
+    let mut output = "hawkes.json".to_string();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        if !arg.starts_with("--") {
+            recording = Some(arg.clone());
+            continue;
+        }
+        let value = it
+            .next()
+            .ok_or_else(|| format!("falta el valor de {}", arg))?;
+        match arg.as_str() {
+            "--betas" => {
+                candidates = value
+                    .split(';')
+                    .map(|set| set.split('/').map(str::parse).collect())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| format!("betas inválidas: {}", value))?
+            }
+            "--iterations" => iterations = value.parse()?,
+            "--out" => output = value.clone(),
+            _ => return Err(format!("opción desconocida: {}", arg).into()),
+        }
+    }
+    let recording =
+        recording.ok_or("uso: hawkes <grabación> [--betas 1/10/100] [--out hawkes.json]")?;
+
+    let times = event_times(&read_recording(&recording)?);
+    let params = HawkesParams::fit_best(&times, &candidates, iterations)
+        .ok_or("la grabación no tiene eventos suficientes")?;
+    info!(
+        "{} eventos | μ {:.3}/s | α {:?} | β {:?} | ramificación {:.3} | log-verosimilitud {:.1}",
+        times.len(),
+        params.mu,
+        params.alphas,
+        params.betas,
+        params.branching_ratio(),
+        params.log_likelihood(&times)
+    );
+    fs::write(&output, serde_json::to_string_pretty(&params)?)?;
+    info!("Parámetros guardados en {}", output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Simulación por thinning (Ogata) de un núcleo exponencial hasta `horizon`
+    fn simulate(mu: f64, alpha: f64, beta: f64, horizon: f64, seed: u64) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (mut t, mut excitation, mut times) = (0.0, 0.0, Vec::new());
+        loop {
+            // La intensidad solo decae hasta el próximo evento: cota superior
+            let bound = mu + excitation;
+            let wait = -(1.0 - rng.gen::<f64>()).ln() / bound;
+            t += wait;
+            if t > horizon {
+                return times;
+            }
+            excitation *= (-beta * wait).exp();
+            if rng.gen::<f64>() * bound <= mu + excitation {
+                times.push(t);
+                excitation += alpha * beta;
+            }
+        }
+    }
+
+    #[test]
+    fn log_likelihood_known_answer() {
+        let params = HawkesParams {
+            mu: 0.5,
+            alphas: vec![0.5],
+            betas: vec![1.0],
+        };
+        let e = |x: f64| (-x).exp();
+        let intensities = [0.5, 0.5 + 0.5 * e(1.0), 0.5 + 0.5 * (e(1.0) + e(2.0))];
+        let compensator = (1.0 - e(2.0)) + (1.0 - e(1.0));
+        let expected =
+            -0.5 * 2.0 + intensities.iter().map(|l| l.ln()).sum::<f64>() - 0.5 * compensator;
+        assert!((params.log_likelihood(&[0.0, 1.0, 2.0]) - expected).abs() < 1e-12);
+        assert_eq!(params.log_likelihood(&[1.0]), f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn em_never_decreases_the_likelihood() {
+        let times = simulate(1.0, 0.5, 2.0, 2000.0, 7);
+        let mut params = HawkesParams::initial(&[0.5, 2.0, 20.0]);
+        let mut ll = params.log_likelihood(&times);
+        for _ in 0..30 {
+            params = params.fit(&times, 1);
+            let next = params.log_likelihood(&times);
+            assert!(next >= ll - 1e-9 * ll.abs(), "{} -> {}", ll, next);
+            ll = next;
+        }
+    }
+
+    #[test]
+    fn em_recovers_simulated_parameters() {
+        let times = simulate(1.0, 0.6, 2.0, 5000.0, 11);
+        let params = HawkesParams::initial(&[2.0]).fit(&times, 200);
+        assert!((params.mu - 1.0).abs() < 0.15, "mu {}", params.mu);
+        assert!(
+            (params.alphas[0] - 0.6).abs() < 0.08,
+            "alpha {}",
+            params.alphas[0]
+        );
+        let best = HawkesParams::fit_best(&times, &[vec![20.0], vec![2.0]], 200).unwrap();
+        assert_eq!(best.betas, vec![2.0]);
+    }
+
+    #[test]
+    fn fit_caps_the_branching_ratio() {
+        // Ráfagas muy agrupadas empujan la ramificación por encima de 1
+        let times: Vec<f64> = (0..50)
+            .flat_map(|burst| (0..20).map(move |i| burst as f64 * 100.0 + i as f64 * 0.01))
+            .collect();
+        let params = HawkesParams::initial(&[10.0]).fit(&times, 100);
+        assert!(params.branching_ratio() <= MAX_BRANCHING + 1e-12);
+    }
+
+    #[test]
+    fn online_intensity_decays_between_events() {
+        let params = HawkesParams {
+            mu: 0.5,
+            alphas: vec![0.4],
+            betas: vec![2.0],
+        };
+        let mut model = HawkesModel::new(HawkesConfig {
+            params: Some(params),
+            refit_every: 0,
+            ..HawkesConfig::default()
+        });
+        assert_eq!(model.intensity(0.0), 0.5);
+        model.record(1.0);
+        assert!((model.intensity(1.0) - 1.3).abs() < 1e-12);
+        assert!((model.intensity(1.5) - (0.5 + 0.8 * (-1.0f64).exp())).abs() < 1e-12);
+        // Un reloj que retrocede cuenta como simultáneo
+        model.record(0.5);
+        assert!((model.intensity(1.0) - 2.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn event_times_skip_non_book_messages_and_never_go_back() {
+        let messages = vec![
+            (1.0, "|35=X|279=0|269=0|270=1.1|271=1|".to_string()),
+            (0.5, "|35=W|279=0|269=2|270=1.1|271=1|".to_string()),
+            (2.0, "|35=X|279=0|269=7|270=1.1|".to_string()),
+            (3.0, "|35=0|".to_string()),
+        ];
+        assert_eq!(event_times(&messages), vec![1.0, 1.0]);
+    }
+}
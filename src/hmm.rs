@@ -0,0 +1,692 @@
+use crate::bayesian::BayesianNetwork;
+use crate::pipeline::{MarketPipeline, PipelineConfig};
+use crate::recorder::read_recording;
+use crate::rolling::RollingStats;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+
+/// Dimensión de las observaciones de `RegimeObserver`: retorno, spread, volatilidad
+const OBSERVATION_DIM: usize = 3;
+
+/// HMM con emisiones Gaussianas de covarianza diagonal. Los estados se ordenan
+/// por la media de la última dimensión (volatilidad): el 0 es el más tranquilo.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GaussianHmm {
+    pub initial: Vec<f64>,
+    /// transition[i][j] = P(estado j | estado anterior i)
+    pub transition: Vec<Vec<f64>>,
+    pub means: Vec<Vec<f64>>,
+    pub variances: Vec<Vec<f64>>,
+}
+
+impl GaussianHmm {
+    pub fn n_states(&self) -> usize {
+        self.initial.len()
+    }
+
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let model: Self = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let n = model.n_states();
+        let square = model.transition.len() == n && model.transition.iter().all(|r| r.len() == n);
+        if n == 0 || !square || model.means.len() != n || model.variances.len() != n {
+            return Err(format!("{}: dimensiones del HMM incoherentes", path).into());
+        }
+        let dims_ok = model.means.iter().all(|m| m.len() == OBSERVATION_DIM)
+            && model.variances.iter().all(|v| v.len() == OBSERVATION_DIM);
+        if !dims_ok {
+            return Err(format!(
+                "{}: se esperan medias y varianzas de {} dimensiones por estado",
+                path, OBSERVATION_DIM
+            )
+            .into());
+        }
+        let valid = model.means.iter().flatten().all(|m| m.is_finite())
+            && model
+                .variances
+                .iter()
+                .flatten()
+                .all(|v| v.is_finite() && *v > 0.0)
+            && model
+                .initial
+                .iter()
+                .chain(model.transition.iter().flatten())
+                .all(|p| p.is_finite() && *p >= 0.0);
+        if !valid {
+            return Err(format!(
+                "{}: varianzas no positivas o probabilidades inválidas en el HMM",
+                path
+            )
+            .into());
+        }
+        Ok(model)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn log_emission(&self, state: usize, x: &[f64]) -> f64 {
+        self.means[state]
+            .iter()
+            .zip(&self.variances[state])
+            .zip(x)
+            .map(|((m, v), x)| -0.5 * ((x - m).powi(2) / v + (std::f64::consts::TAU * v).ln()))
+            .sum()
+    }
+
+    /// Verosimilitud de cada estado reescalada por la mayor (estable en exp);
+    /// devuelve también el logaritmo de la escala
+    fn emissions(&self, x: &[f64]) -> (Vec<f64>, f64) {
+        let logs: Vec<f64> = (0..self.n_states())
+            .map(|s| self.log_emission(s, x))
+            .collect();
+        let max = logs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        (logs.iter().map(|l| (l - max).exp()).collect(), max)
+    }
+
+    /// Baum-Welch desde una partición por cuantiles de volatilidad.
+    /// Devuelve el modelo y la log-verosimilitud final; None sin datos suficientes.
+    pub fn fit(
+        observations: &[Vec<f64>],
+        n_states: usize,
+        iterations: usize,
+    ) -> Option<(Self, f64)> {
+        let mut model = Self::initialize(observations, n_states)?;
+        let mut log_likelihood = f64::NEG_INFINITY;
+        for _ in 0..iterations {
+            let (next, ll) = model.baum_welch_step(observations);
+            let converged = (ll - log_likelihood).abs() < 1e-6 * ll.abs().max(1.0);
+            model = next;
+            log_likelihood = ll;
+            if converged {
+                break;
+            }
+        }
+        Some((model.sorted_by_volatility(), log_likelihood))
+    }
+
+    fn initialize(observations: &[Vec<f64>], n_states: usize) -> Option<Self> {
+        let dim = observations.first()?.len();
+        if n_states == 0 || dim == 0 || observations.len() < n_states * 10 {
+            return None;
+        }
+        let mut order: Vec<usize> = (0..observations.len()).collect();
+        order.sort_by(|&a, &b| observations[a][dim - 1].total_cmp(&observations[b][dim - 1]));
+        let floor = variance_floor(observations);
+        let chunk = observations.len() / n_states;
+        let (mut means, mut variances) = (Vec::new(), Vec::new());
+        for s in 0..n_states {
+            let end = if s + 1 == n_states {
+                order.len()
+            } else {
+                (s + 1) * chunk
+            };
+            let members = &order[s * chunk..end];
+            let weights = vec![1.0; members.len()];
+            let rows: Vec<&Vec<f64>> = members.iter().map(|&i| &observations[i]).collect();
+            let (m, v) = weighted_moments(&rows, &weights, &floor);
+            means.push(m);
+            variances.push(v);
+        }
+        let stay = 0.95;
+        let transition = (0..n_states)
+            .map(|i| {
+                (0..n_states)
+                    .map(|j| {
+                        if n_states == 1 {
+                            1.0
+                        } else if i == j {
+                            stay
+                        } else {
+                            (1.0 - stay) / (n_states - 1) as f64
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        Some(Self {
+            initial: vec![1.0 / n_states as f64; n_states],
+            transition,
+            means,
+            variances,
+        })
+    }
+
+    /// Una iteración EM con forward-backward escalado
+    fn baum_welch_step(&self, observations: &[Vec<f64>]) -> (Self, f64) {
+        let n = self.n_states();
+        let t_len = observations.len();
+        let emissions: Vec<(Vec<f64>, f64)> =
+            observations.iter().map(|x| self.emissions(x)).collect();
+
+        // Forward: alpha normalizado y constantes de escala
+        let mut alpha = vec![vec![0.0; n]; t_len];
+        let mut scale = vec![0.0; t_len];
+        let mut log_likelihood = 0.0;
+        for t in 0..t_len {
+            for j in 0..n {
+                let prior = if t == 0 {
+                    self.initial[j]
+                } else {
+                    (0..n)
+                        .map(|i| alpha[t - 1][i] * self.transition[i][j])
+                        .sum()
+                };
+                alpha[t][j] = prior * emissions[t].0[j];
+            }
+            scale[t] = alpha[t].iter().sum::<f64>().max(1e-300);
+            alpha[t].iter_mut().for_each(|a| *a /= scale[t]);
+            log_likelihood += scale[t].ln() + emissions[t].1;
+        }
+
+        // Backward con las mismas escalas
+        let mut beta = vec![vec![1.0; n]; t_len];
+        for t in (0..t_len.saturating_sub(1)).rev() {
+            for i in 0..n {
+                beta[t][i] = (0..n)
+                    .map(|j| self.transition[i][j] * emissions[t + 1].0[j] * beta[t + 1][j])
+                    .sum::<f64>()
+                    / scale[t + 1];
+            }
+        }
+
+        // Posteriores por instante y transiciones esperadas
+        let mut gamma = vec![vec![0.0; n]; t_len];
+        let mut xi = vec![vec![0.0; n]; n];
+        for t in 0..t_len {
+            let norm: f64 = (0..n)
+                .map(|s| alpha[t][s] * beta[t][s])
+                .sum::<f64>()
+                .max(1e-300);
+            for s in 0..n {
+                gamma[t][s] = alpha[t][s] * beta[t][s] / norm;
+            }
+            if t + 1 < t_len {
+                for i in 0..n {
+                    for j in 0..n {
+                        xi[i][j] += alpha[t][i]
+                            * self.transition[i][j]
+                            * emissions[t + 1].0[j]
+                            * beta[t + 1][j]
+                            / scale[t + 1];
+                    }
+                }
+            }
+        }
+
+        let floor = variance_floor(observations);
+        let rows: Vec<&Vec<f64>> = observations.iter().collect();
+        let mut next = self.clone();
+        next.initial = gamma[0].clone();
+        for i in 0..n {
+            let total: f64 = xi[i].iter().sum();
+            if total > 0.0 {
+                next.transition[i] = xi[i].iter().map(|x| x / total).collect();
+            }
+            let weights: Vec<f64> = gamma.iter().map(|g| g[i]).collect();
+            if weights.iter().sum::<f64>() > 1e-9 {
+                let (m, v) = weighted_moments(&rows, &weights, &floor);
+                next.means[i] = m;
+                next.variances[i] = v;
+            }
+        }
+        (next, log_likelihood)
+    }
+
+    /// Permuta los estados por volatilidad media ascendente
+    fn sorted_by_volatility(&self) -> Self {
+        let n = self.n_states();
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|&a, &b| {
+            let vol = |s: usize| self.means[s].last().copied().unwrap_or(0.0);
+            vol(a).total_cmp(&vol(b))
+        });
+        Self {
+            initial: order.iter().map(|&i| self.initial[i]).collect(),
+            transition: order
+                .iter()
+                .map(|&i| order.iter().map(|&j| self.transition[i][j]).collect())
+                .collect(),
+            means: order.iter().map(|&i| self.means[i].clone()).collect(),
+            variances: order.iter().map(|&i| self.variances[i].clone()).collect(),
+        }
+    }
+}
+
+/// Varianza mínima por dimensión (evita estados degenerados en columnas casi discretas)
+fn variance_floor(observations: &[Vec<f64>]) -> Vec<f64> {
+    let dim = observations.first().map_or(0, Vec::len);
+    let rows: Vec<&Vec<f64>> = observations.iter().collect();
+    let (_, var) = weighted_moments(&rows, &vec![1.0; rows.len()], &vec![0.0; dim]);
+    var.iter().map(|v| v * 1e-3 + 1e-12).collect()
+}
+
+fn weighted_moments(rows: &[&Vec<f64>], weights: &[f64], floor: &[f64]) -> (Vec<f64>, Vec<f64>) {
+    let dim = floor.len();
+    let total: f64 = weights.iter().sum::<f64>().max(1e-300);
+    let mut mean = vec![0.0; dim];
+    for (row, w) in rows.iter().zip(weights) {
+        for (m, x) in mean.iter_mut().zip(row.iter()) {
+            *m += w * x / total;
+        }
+    }
+    let mut var = vec![0.0; dim];
+    for (row, w) in rows.iter().zip(weights) {
+        for ((v, x), m) in var.iter_mut().zip(row.iter()).zip(&mean) {
+            *v += w * (x - m).powi(2) / total;
+        }
+    }
+    let var = var.iter().zip(floor).map(|(v, f)| v.max(*f)).collect();
+    (mean, var)
+}
+
+/// Observación del HMM en cada update: [log-retorno del mid en pb, spread en
+/// puntos, volatilidad realizada en pb sobre la ventana]
+#[derive(Clone)]
+pub struct RegimeObserver {
+    prev_mid: Option<f64>,
+    returns: RollingStats,
+}
+
+impl RegimeObserver {
+    pub fn new(vol_window: usize) -> Self {
+        Self {
+            prev_mid: None,
+            returns: RollingStats::new(vol_window),
+        }
+    }
+
+    /// None en el primer update (sin retorno)
+    pub fn observe(&mut self, mid: f64, spread: f64) -> Option<Vec<f64>> {
+        let prev = self.prev_mid.replace(mid)?;
+        if !(prev > 0.0 && mid > 0.0) {
+            return None;
+        }
+        let ret = (mid / prev).ln() * 1e4;
+        self.returns.push(ret);
+        Some(vec![ret, spread, self.returns.std()])
+    }
+}
+
+/// Parámetros del detector online
+#[derive(Debug, Clone, PartialEq)]
+pub struct HmmConfig {
+    /// Modelo ajustado offline (`hmm`); None = se ajusta tras `warmup` observaciones
+    pub model: Option<GaussianHmm>,
+    pub states: usize,
+    pub warmup: usize,
+    pub iterations: usize,
+    pub vol_window: usize,
+}
+
+impl Default for HmmConfig {
+    fn default() -> Self {
+        Self {
+            model: None,
+            states: 3,
+            warmup: 2000,
+            iterations: 20,
+            vol_window: 50,
+        }
+    }
+}
+
+/// Filtrado forward online de los regímenes y probabilidad de contexto favorable
+/// aprendida por estado (conteos Beta(1, 1))
+#[derive(Clone)]
+pub struct RegimeDetector {
+    config: HmmConfig,
+    observer: RegimeObserver,
+    model: Option<GaussianHmm>,
+    posterior: Vec<f64>,
+    /// Observaciones del calentamiento mientras no hay modelo
+    buffer: Vec<Vec<f64>>,
+    /// (favorables, total) por estado
+    outcomes: Vec<(f64, f64)>,
+}
+
+impl RegimeDetector {
+    pub fn new(config: HmmConfig) -> Self {
+        let model = config.model.clone();
+        let states = model.as_ref().map_or(config.states, GaussianHmm::n_states);
+        Self {
+            observer: RegimeObserver::new(config.vol_window),
+            posterior: model.as_ref().map_or(Vec::new(), |m| m.initial.clone()),
+            outcomes: vec![(0.0, 0.0); states],
+            buffer: Vec::new(),
+            model,
+            config,
+        }
+    }
+
+    pub fn model(&self) -> Option<&GaussianHmm> {
+        self.model.as_ref()
+    }
+
+    /// Nuevo update del libro; devuelve true si el modelo se ajustó en él
+    pub fn update(&mut self, mid: f64, spread: f64) -> bool {
+        let Some(x) = self.observer.observe(mid, spread) else {
+            return false;
+        };
+        let Some(model) = self.model.as_ref() else {
+            self.buffer.push(x);
+            if self.buffer.len() < self.config.warmup {
+                return false;
+            }
+            let fitted = GaussianHmm::fit(&self.buffer, self.config.states, self.config.iterations);
+            self.buffer.clear();
+            if let Some((model, _)) = fitted {
+                self.posterior = model.initial.clone();
+                self.model = Some(model);
+                return true;
+            }
+            return false;
+        };
+
+        // Predicción con la matriz de transición y corrección con la emisión
+        let n = model.n_states();
+        let (emission, _) = model.emissions(&x);
+        let mut next: Vec<f64> = (0..n)
+            .map(|j| {
+                (0..n)
+                    .map(|i| self.posterior[i] * model.transition[i][j])
+                    .sum::<f64>()
+                    * emission[j]
+            })
+            .collect();
+        let total: f64 = next.iter().sum();
+        if total > 0.0 && total.is_finite() {
+            next.iter_mut().for_each(|p| *p /= total);
+            self.posterior = next;
+        }
+        false
+    }
+
+    /// P(estado | observaciones hasta ahora); vacío hasta tener modelo
+    pub fn posterior(&self) -> &[f64] {
+        &self.posterior
+    }
+
+    /// Estado más probable
+    pub fn map_state(&self) -> Option<usize> {
+        self.posterior
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(s, _)| s)
+    }
+
+    /// P(favorable | estado) con prior de Laplace
+    fn favorable_rates(&self) -> Vec<f64> {
+        self.outcomes
+            .iter()
+            .map(|(fav, n)| (fav + 1.0) / (n + 2.0))
+            .collect()
+    }
+
+    /// Σ_s P(s) · P(favorable | s); None hasta tener modelo
+    pub fn context_score(&self) -> Option<f64> {
+        if self.posterior.is_empty() {
+            return None;
+        }
+        Some(
+            self.posterior
+                .iter()
+                .zip(self.favorable_rates())
+                .map(|(p, rate)| p * rate)
+                .sum(),
+        )
+    }
+
+    /// Posterior y tasa de acierto de cada régimen detrás de `context_score`
+    pub fn explain(&self) -> Option<RegimeExplanation> {
+        Some(RegimeExplanation {
+            score: self.context_score()?,
+            map_state: self.map_state()?,
+            posterior: self.posterior.clone(),
+            favorable_rates: self.favorable_rates(),
+        })
+    }
+
+    /// Resultado de una entrada tomada en el régimen `state`
+    pub fn observe(&mut self, state: usize, favorable: bool) {
+        if let Some((fav, n)) = self.outcomes.get_mut(state) {
+            *fav += f64::from(u8::from(favorable));
+            *n += 1.0;
+        }
+    }
+}
+
+/// Desglose de la puntuación de contexto del HMM
+#[derive(Debug, Clone, Serialize)]
+pub struct RegimeExplanation {
+    pub score: f64,
+    /// Régimen más probable
+    pub map_state: usize,
+    pub posterior: Vec<f64>,
+    /// P(favorable | estado) aprendida
+    pub favorable_rates: Vec<f64>,
+}
+
+/// `hmm <grabación> [--states 3] [--iterations 50] [--out hmm.json]`: ajusta el HMM
+/// por Baum-Welch sobre la grabación completa (HMM_MODEL en vivo)
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut recording = None;
+    let mut config = HmmConfig {
+        iterations: 50,
+        ..HmmConfig::default()
+    };
+    let mut output = "hmm.json".to_string();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        if !arg.starts_with("--") {
+            recording = Some(arg.clone());
+            continue;
+        }
+        let value = it
+            .next()
+            .ok_or_else(|| format!("falta el valor de {}", arg))?;
+        match arg.as_str() {
+            "--states" => config.states = value.parse()?,
+            "--iterations" => config.iterations = value.parse()?,
+            "--out" => output = value.clone(),
+            _ => return Err(format!("opción desconocida: {}", arg).into()),
+        }
+    }
+    let recording = recording.ok_or("uso: hmm <grabación> [--states 3] [--out hmm.json]")?;
+
+    // Mismo mid y spread que el pipeline en vivo
+    let messages = read_recording(&recording)?;
+    let mut pipeline =
+        MarketPipeline::new(&PipelineConfig::default(), BayesianNetwork::default_edges());
+    let mut observer = RegimeObserver::new(config.vol_window);
+    let observations: Vec<Vec<f64>> = messages
+        .iter()
+        .filter_map(|(time, msg)| pipeline.on_message(msg, *time))
+        .filter_map(|snap| observer.observe(snap.mid, snap.spread))
+        .collect();
+
+    let (model, log_likelihood) = GaussianHmm::fit(&observations, config.states, config.iterations)
+        .ok_or("la grabación no tiene observaciones suficientes")?;
+    info!(
+        "{} observaciones | {} estados | log-verosimilitud {:.1}",
+        observations.len(),
+        model.n_states(),
+        log_likelihood
+    );
+    for s in 0..model.n_states() {
+        info!(
+            "Estado {}: media {:?} | desv {:?} | permanencia {:.3}",
+            s,
+            model.means[s],
+            model.variances[s]
+                .iter()
+                .map(|v| v.sqrt())
+                .collect::<Vec<_>>(),
+            model.transition[s][s]
+        );
+    }
+    model.save(&output)?;
+    info!("HMM guardado en {}", output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use rand_distr::StandardNormal;
+
+    fn two_state() -> GaussianHmm {
+        GaussianHmm {
+            initial: vec![0.6, 0.4],
+            transition: vec![vec![0.7, 0.3], vec![0.2, 0.8]],
+            means: vec![vec![0.0], vec![2.0]],
+            variances: vec![vec![1.0], vec![0.5]],
+        }
+    }
+
+    fn normal_pdf(x: f64, m: f64, v: f64) -> f64 {
+        (-(x - m).powi(2) / (2.0 * v)).exp() / (std::f64::consts::TAU * v).sqrt()
+    }
+
+    /// Dos regímenes persistentes: tranquilo (vol 1) y agitado (vol 3)
+    fn simulate(len: usize, seed: u64) -> Vec<Vec<f64>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut state = 0;
+        (0..len)
+            .map(|_| {
+                if rng.gen::<f64>() < 0.02 {
+                    state = 1 - state;
+                }
+                let vol = [1.0, 3.0][state];
+                let mut noise = || rng.sample::<f64, _>(StandardNormal);
+                vec![
+                    vol * noise(),
+                    1.0 + vol + 0.1 * noise(),
+                    vol + 0.1 * noise(),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn forward_likelihood_matches_path_enumeration() {
+        let model = two_state();
+        let xs = [0.3, 1.8, 2.4, -0.5];
+        let observations: Vec<Vec<f64>> = xs.iter().map(|&x| vec![x]).collect();
+        let (mut total, mut first_state_one) = (0.0, 0.0);
+        for path in 0..16usize {
+            let state = |t: usize| (path >> t) & 1;
+            let mut p = model.initial[state(0)];
+            for (t, &x) in xs.iter().enumerate() {
+                if t > 0 {
+                    p *= model.transition[state(t - 1)][state(t)];
+                }
+                p *= normal_pdf(x, model.means[state(t)][0], model.variances[state(t)][0]);
+            }
+            total += p;
+            first_state_one += p * state(0) as f64;
+        }
+        let (next, ll) = model.baum_welch_step(&observations);
+        assert!((ll - total.ln()).abs() < 1e-10);
+        // La nueva distribución inicial es el posterior suavizado de t = 0
+        assert!((next.initial[1] - first_state_one / total).abs() < 1e-10);
+    }
+
+    #[test]
+    fn single_state_step_is_the_sample_moments() {
+        let model = GaussianHmm {
+            initial: vec![1.0],
+            transition: vec![vec![1.0]],
+            means: vec![vec![1.0]],
+            variances: vec![vec![1.0]],
+        };
+        let observations = vec![vec![0.0], vec![1.0], vec![2.0]];
+        let (next, ll) = model.baum_welch_step(&observations);
+        let expected = -0.5 * (2.0 + 3.0 * std::f64::consts::TAU.ln());
+        assert!((ll - expected).abs() < 1e-12);
+        assert!((next.means[0][0] - 1.0).abs() < 1e-12);
+        assert!((next.variances[0][0] - 2.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn baum_welch_never_decreases_the_likelihood() {
+        let observations = simulate(1500, 3);
+        let mut model = GaussianHmm::initialize(&observations, 3).unwrap();
+        let mut previous = f64::NEG_INFINITY;
+        for _ in 0..25 {
+            let (next, ll) = model.baum_welch_step(&observations);
+            assert!(ll >= previous - 1e-8 * ll.abs(), "{} -> {}", previous, ll);
+            previous = ll;
+            model = next;
+        }
+    }
+
+    #[test]
+    fn fit_recovers_regimes_sorted_by_volatility() {
+        let observations = simulate(3000, 5);
+        let (model, _) = GaussianHmm::fit(&observations, 2, 50).unwrap();
+        assert!((model.means[0][2] - 1.0).abs() < 0.1, "{:?}", model.means);
+        assert!((model.means[1][2] - 3.0).abs() < 0.1, "{:?}", model.means);
+        for row in &model.transition {
+            assert!((row.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+            assert!(row.iter().cloned().fold(0.0, f64::max) > 0.9);
+        }
+        assert!(GaussianHmm::fit(&observations[..15], 2, 10).is_none());
+    }
+
+    #[test]
+    fn detector_scores_with_the_filtered_posterior() {
+        let model = GaussianHmm {
+            initial: vec![0.5, 0.5],
+            transition: vec![vec![0.9, 0.1], vec![0.1, 0.9]],
+            means: vec![vec![0.0, 1.0, 0.0], vec![0.0, 4.0, 0.0]],
+            variances: vec![vec![1.0, 0.1, 0.1], vec![9.0, 0.1, 0.1]],
+        };
+        let mut detector = RegimeDetector::new(HmmConfig {
+            model: Some(model),
+            ..HmmConfig::default()
+        });
+        detector.observe(0, true);
+        // Laplace: 2/3 en el estado 0, 1/2 en el 1, posterior inicial uniforme
+        let score = detector.context_score().unwrap();
+        assert!((score - (0.5 * 2.0 / 3.0 + 0.5 * 0.5)).abs() < 1e-12);
+        // Un spread ancho lleva el posterior al régimen agitado
+        detector.update(1.0, 4.0);
+        detector.update(1.0001, 4.0);
+        assert_eq!(detector.map_state(), Some(1));
+        let explanation = detector.explain().unwrap();
+        assert_eq!(explanation.map_state, 1);
+        assert!((explanation.posterior.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn load_rejects_bad_dimensions_and_variances() {
+        let path = std::env::temp_dir().join(format!("hmm-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut model = GaussianHmm {
+            initial: vec![0.5, 0.5],
+            transition: vec![vec![0.9, 0.1], vec![0.1, 0.9]],
+            means: vec![vec![0.0; OBSERVATION_DIM]; 2],
+            variances: vec![vec![1.0; OBSERVATION_DIM]; 2],
+        };
+        model.save(path).unwrap();
+        assert_eq!(GaussianHmm::load(path).unwrap(), model);
+        model.transition[1] = vec![1.0];
+        model.save(path).unwrap();
+        assert!(GaussianHmm::load(path).is_err());
+        model.transition[1] = vec![0.1, 0.9];
+        model.variances[0][1] = 0.0;
+        model.save(path).unwrap();
+        assert!(GaussianHmm::load(path).is_err());
+        model.variances[0] = vec![1.0; 2];
+        model.save(path).unwrap();
+        assert!(GaussianHmm::load(path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,351 @@
+use crate::brain::{BayesianBrain, BrainConfig};
+use crate::calibration::{Calibrator, OnlineMetrics};
+use crate::checkpoint::Checkpoint;
+use crate::drift::{DriftAction, DriftDetector, DriftState};
+use crate::ensemble::{
+    Combiner, Ensemble, EnsemblePrediction, MemberPrediction, Predictor, SavedMember,
+};
+use crate::labels::{Horizon, LabelScheme, Labeler, Resolved};
+use log::warn;
+use ndarray::{Array1, Axis};
+use serde::Serialize;
+
+/// Cabeza de predicción para un horizonte: su propia cola de etiquetado y su red
+pub struct HorizonHead<T> {
+    pub horizon: Horizon,
+    pub brain: BayesianBrain,
+    /// La red predice y aprende; false si el checkpoint cargado no la traía
+    /// (entonces el conjunto es el único predictor)
+    pub brain_enabled: bool,
+    pub trained_samples: u64,
+    pub calibrator: Calibrator,
+    /// Evaluación prequential: cada muestra se puntúa antes de entrenar con ella
+    pub metrics: OnlineMetrics,
+    /// Modelos acompañantes de la red: si existe, el score es la combinación
+    pub ensemble: Option<Ensemble>,
+    /// Detector de deriva sobre el error de predicción
+    pub drift: Option<Box<dyn DriftDetector>>,
+    pub drift_action: DriftAction,
+    pub drift_events: u64,
+    /// Último checkpoint sin derivas posteriores (destino de `DriftAction::Rollback`)
+    last_good: Option<Checkpoint>,
+    drift_since_snapshot: bool,
+    labeler: Labeler<(Array1<f64>, T)>,
+    batch_size: usize,
+    pending_batch: Vec<(Array1<f64>, Array1<f64>)>,
+}
+
+impl<T> HorizonHead<T> {
+    pub fn new(
+        horizon: Horizon,
+        scheme: LabelScheme,
+        tick_size: f64,
+        config: BrainConfig,
+        batch_size: usize,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            horizon,
+            brain: BayesianBrain::from_config(config)?,
+            brain_enabled: true,
+            trained_samples: 0,
+            calibrator: Calibrator::None,
+            metrics: OnlineMetrics::new(2000),
+            ensemble: None,
+            drift: None,
+            drift_action: DriftAction::Log,
+            drift_events: 0,
+            last_good: None,
+            drift_since_snapshot: false,
+            labeler: Labeler::new(scheme, horizon, tick_size),
+            batch_size: batch_size.max(1),
+            pending_batch: Vec::with_capacity(batch_size.max(1)),
+        })
+    }
+
+    pub fn with_calibrator(mut self, calibrator: Calibrator) -> Self {
+        self.calibrator = calibrator;
+        self
+    }
+
+    pub fn with_metrics_window(mut self, window_size: usize) -> Self {
+        self.metrics = OnlineMetrics::new(window_size);
+        self
+    }
+
+    pub fn with_drift(mut self, detector: Box<dyn DriftDetector>, action: DriftAction) -> Self {
+        self.drift = Some(detector);
+        self.drift_action = action;
+        self
+    }
+
+    pub fn with_ensemble(mut self, ensemble: Ensemble) -> Self {
+        self.ensemble = Some(ensemble);
+        self
+    }
+
+    /// Predicción de la red principal (si está activa) seguida de las de los
+    /// miembros del conjunto
+    fn combined(&self, features: &Array1<f64>) -> Option<EnsemblePrediction> {
+        let ensemble = self.ensemble.as_ref()?;
+        let mut predictions = Vec::new();
+        if self.brain_enabled {
+            let (score, uncertainty) = self.brain.predict_with_uncertainty(features);
+            predictions.push(MemberPrediction {
+                name: "brain".to_string(),
+                score,
+                uncertainty,
+            });
+        }
+        predictions.extend(ensemble.member_predictions(features));
+        Some(ensemble.combine(predictions))
+    }
+
+    /// Guarda en memoria el estado actual como punto de retorno, salvo que haya
+    /// habido derivas desde el anterior. Devuelve el checkpoint.
+    pub fn snapshot(&mut self) -> Checkpoint {
+        let checkpoint = self.checkpoint();
+        if !self.drift_since_snapshot {
+            self.last_good = Some(checkpoint.clone());
+        }
+        self.drift_since_snapshot = false;
+        checkpoint
+    }
+
+    /// Aplica la acción configurada ante una deriva detectada en `source`
+    pub fn handle_drift(&mut self, source: &str, action: DriftAction) {
+        self.drift_events += 1;
+        self.drift_since_snapshot = true;
+        warn!(
+            "⚠️ DERIVA [{}] en {} (#{}) -> {:?}",
+            self.horizon.name(),
+            source,
+            self.drift_events,
+            action
+        );
+        match action {
+            DriftAction::Log => {}
+            DriftAction::InflateVariance(factor) => self.brain.inflate_variance(factor),
+            DriftAction::BoostLearningRate { factor, steps } => {
+                self.brain.boost_learning_rate(factor, steps)
+            }
+            DriftAction::Rollback => match self.last_good.clone() {
+                Some(checkpoint) => {
+                    warn!("   ↳ Restaurado checkpoint de {}", checkpoint.saved_at);
+                    self.restore(checkpoint);
+                }
+                None => warn!("   ↳ Sin checkpoint previo, se mantiene el modelo"),
+            },
+        }
+    }
+
+    /// Restaura red, calibración y miembros del conjunto desde un checkpoint. Los
+    /// miembros guardados sustituyen a los configurados; un modelo logístico
+    /// entrenado offline se añade a ellos. El combinador se reajusta al nuevo tamaño.
+    /// Sin red en el checkpoint, los miembros son los únicos predictores.
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        let has_brain = checkpoint.brain.is_some();
+        if let Some(brain) = checkpoint.brain {
+            self.brain = brain;
+        }
+        self.calibrator = checkpoint.calibrator;
+        let mut members: Vec<Box<dyn Predictor>> = if checkpoint.members.is_empty() {
+            match self.ensemble.as_mut() {
+                Some(ensemble) => std::mem::take(&mut ensemble.members),
+                None => Vec::new(),
+            }
+        } else {
+            checkpoint
+                .members
+                .into_iter()
+                .map(SavedMember::into_predictor)
+                .collect()
+        };
+        if let Some(logistic) = checkpoint.logistic {
+            members.push(Box::new(logistic));
+        }
+        if members.is_empty() {
+            self.brain_enabled = true;
+            return;
+        }
+        self.brain_enabled = has_brain;
+        self.ensemble
+            .get_or_insert_with(|| Ensemble::new(Vec::new(), Combiner::InverseVariance))
+            .set_members(members, usize::from(has_brain));
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        let mut checkpoint = Checkpoint::new(
+            self.labeler.scheme,
+            self.horizon,
+            self.brain_enabled.then(|| self.brain.clone()),
+            self.calibrator.clone(),
+            (!self.metrics.is_empty()).then(|| self.metrics.report(10)),
+        );
+        if let Some(ensemble) = self.ensemble.as_ref() {
+            checkpoint.members = ensemble.saved_members();
+        }
+        checkpoint
+    }
+
+    /// Nuevo precio: resuelve las entradas pendientes, entrena la red con ellas
+    /// (por mini-batch) y devuelve el payload de cada una con su etiqueta.
+    pub fn update(&mut self, price: f64, time: f64, volume: f64) -> Vec<Resolved<T>> {
+        let mut out = Vec::new();
+        for resolved in self.labeler.update(price, time, volume) {
+            let (features, payload) = resolved.payload;
+
+            // Puntuar antes de aprender de la muestra
+            let outcome = self.labeler.scheme.outcome(resolved.label);
+            let targets = self
+                .labeler
+                .scheme
+                .encode(resolved.label, self.brain.config().output_dim);
+            let raw = match self.combined(&features) {
+                Some(combined) => {
+                    let ensemble = self.ensemble.as_mut().unwrap();
+                    ensemble.observe(&combined.members, outcome);
+                    ensemble.train(&features, &targets, outcome);
+                    combined.score
+                }
+                None => self.brain.predict_with_uncertainty(&features).0,
+            };
+            self.metrics
+                .observe(self.calibrator.calibrate(raw), outcome);
+            self.calibrator.observe(raw, outcome);
+
+            if let Some(detector) = self.drift.as_mut() {
+                let error = if detector.expects_binary() {
+                    f64::from(u8::from((raw > 0.5) != (outcome > 0.5)))
+                } else {
+                    (raw - outcome).abs()
+                };
+                if detector.update(error) == DriftState::Drift {
+                    let source = format!("error ({})", detector.name());
+                    self.handle_drift(&source, self.drift_action);
+                }
+            }
+
+            if self.brain_enabled {
+                self.pending_batch.push((features, targets));
+                if self.pending_batch.len() >= self.batch_size {
+                    self.flush_batch();
+                }
+            }
+            self.trained_samples += 1;
+
+            out.push(Resolved {
+                payload,
+                label: resolved.label,
+                entry: resolved.entry,
+                exit: resolved.exit,
+                entry_time: resolved.entry_time,
+                exit_time: resolved.exit_time,
+            });
+        }
+        out
+    }
+
+    fn flush_batch(&mut self) {
+        let rows: Vec<_> = self.pending_batch.iter().map(|(f, _)| f.view()).collect();
+        let targets: Vec<_> = self.pending_batch.iter().map(|(_, t)| t.view()).collect();
+        if let (Ok(inputs), Ok(targets)) = (
+            ndarray::stack(Axis(0), &rows),
+            ndarray::stack(Axis(0), &targets),
+        ) {
+            self.brain.train_batch(&inputs, &targets);
+        }
+        self.pending_batch.clear();
+    }
+
+    /// Registra una entrada a etiquetar en este horizonte
+    pub fn push(&mut self, features: Array1<f64>, payload: T, price: f64, time: f64) {
+        self.labeler.push((features, payload), price, time);
+    }
+
+    pub fn predict(&self, features: &Array1<f64>) -> HorizonPrediction {
+        let (raw_prob, uncertainty, disagreement) = match self.combined(features) {
+            Some(c) => (c.score, c.uncertainty, Some(c.disagreement)),
+            None => {
+                let (p, u) = self.brain.predict_with_uncertainty(features);
+                (p, u, None)
+            }
+        };
+        HorizonPrediction {
+            horizon: self.horizon.name(),
+            prob: self.calibrator.calibrate(raw_prob),
+            raw_prob,
+            uncertainty,
+            disagreement,
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.labeler.len()
+    }
+}
+
+/// Predicción de una cabeza, tal como se registra en la decisión
+#[derive(Debug, Clone, Serialize)]
+pub struct HorizonPrediction {
+    pub horizon: String,
+    /// Probabilidad recalibrada (igual a `raw_prob` sin calibrador)
+    pub prob: f64,
+    pub raw_prob: f64,
+    pub uncertainty: f64,
+    /// Desacuerdo entre miembros del conjunto (None sin conjunto)
+    pub disagreement: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decision::{Decision, DecisionInputs, SignalThresholds, Verdict};
+    use ndarray::array;
+
+    /// Platt con a = 0: la probabilidad calibrada es constante
+    fn head_calibrated_to(prob: f64) -> HorizonHead<()> {
+        let calibrator = Calibrator::Platt {
+            a: 0.0,
+            b: (prob / (1.0 - prob)).ln(),
+            learning_rate: 0.0,
+        };
+        HorizonHead::new(
+            Horizon::Updates(5),
+            LabelScheme::Binary,
+            1e-5,
+            BrainConfig::legacy(2, 3, 0.1),
+            1,
+        )
+        .unwrap()
+        .with_calibrator(calibrator)
+    }
+
+    #[test]
+    fn the_decision_sees_the_calibrated_probability() {
+        let features = array![0.3, -0.2];
+        let inputs = DecisionInputs {
+            context: 1.0,
+            ..Default::default()
+        };
+        let thresholds = SignalThresholds {
+            max_brain_uncertainty: 1.0,
+            ..Default::default()
+        };
+        for (prob, verdict) in [(0.9, Verdict::Buy), (0.1, Verdict::Sell)] {
+            let prediction = head_calibrated_to(prob).predict(&features);
+            assert!((prediction.prob - prob).abs() < 1e-12);
+            assert!((prediction.raw_prob - 0.5).abs() < 0.3);
+            let decision = Decision::evaluate(
+                inputs.clone(),
+                prediction.prob,
+                prediction.uncertainty,
+                0.6,
+                &thresholds,
+            );
+            assert_eq!(
+                (decision.prob, decision.verdict),
+                (prediction.prob, verdict)
+            );
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::VecDeque;
+
+/// Varianza mínima (precio²): evita dividir por cero con un precio constante
+const MIN_VARIANCE: f64 = 1e-20;
+
+/// Modelo de espacio de estados del precio justo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KalmanModel {
+    /// Paseo aleatorio observado con ruido: nivel
+    Level,
+    /// Nivel con pendiente también aleatoria: nivel y velocidad
+    Trend,
+}
+
+/// Parámetros del filtro
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KalmanConfig {
+    pub model: KalmanModel,
+    /// Semivida en updates de las autocovarianzas con que se estiman los ruidos
+    pub halflife: usize,
+}
+
+impl Default for KalmanConfig {
+    fn default() -> Self {
+        Self {
+            model: KalmanModel::Trend,
+            halflife: 200,
+        }
+    }
+}
+
+impl KalmanConfig {
+    /// "level", "trend", "trend:500" (semivida en updates)
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (name, halflife) = spec.split_once(':').unwrap_or((spec, "200"));
+        let model = match name {
+            "level" => KalmanModel::Level,
+            "trend" => KalmanModel::Trend,
+            _ => return None,
+        };
+        let halflife = halflife.parse().ok().filter(|h: &usize| *h > 0)?;
+        Some(Self { model, halflife })
+    }
+}
+
+/// Salida del filtro tras un update
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct KalmanEstimate {
+    /// Nivel filtrado (precio)
+    pub fair_value: f64,
+    /// Pendiente filtrada (precio por update; 0 en el modelo de nivel)
+    pub velocity: f64,
+    /// Varianza de la innovación del último update (precio²)
+    pub innovation_variance: f64,
+}
+
+/// Filtro de Kalman del microprecio con los ruidos estimados online por el método
+/// de los momentos sobre las diferencias de la observación: O(1) por update, frente
+/// a la factorización de la ventana del GaussianFilter.
+///
+/// Nivel: Δz tiene γ0 = q + 2r, γ1 = -r. Tendencia: Δ²z tiene γ0 = ζ + 2q + 6r,
+/// γ1 = -q - 4r, γ2 = r (q, ζ: ruido del nivel y de la pendiente; r: observación).
+#[derive(Debug, Clone)]
+pub struct KalmanFilter {
+    config: KalmanConfig,
+    /// [nivel, pendiente] y su covarianza
+    state: [f64; 2],
+    cov: [[f64; 2]; 2],
+    /// Observaciones recientes (las necesarias para las diferencias retardadas)
+    recent: VecDeque<f64>,
+    /// Autocovarianzas exponenciales de las diferencias, retardos 0..=2
+    autocov: [f64; 3],
+    weight: f64,
+    /// Ruido de proceso [nivel, pendiente] y de observación estimados
+    process: [f64; 2],
+    measurement: f64,
+    innovation_variance: f64,
+    updates: usize,
+}
+
+impl KalmanFilter {
+    pub fn new(config: KalmanConfig) -> Self {
+        Self {
+            config,
+            state: [0.0; 2],
+            cov: [[0.0; 2]; 2],
+            recent: VecDeque::new(),
+            autocov: [0.0; 3],
+            weight: 0.0,
+            process: [MIN_VARIANCE; 2],
+            measurement: MIN_VARIANCE,
+            innovation_variance: 0.0,
+            updates: 0,
+        }
+    }
+
+    /// Nueva observación del precio (p.ej. `OrderBook::get_microprice`)
+    pub fn update(&mut self, price: f64) {
+        if !price.is_finite() || price <= 0.0 {
+            return;
+        }
+        self.updates += 1;
+        self.estimate_noise(price);
+        if self.updates == 1 {
+            self.state = [price, 0.0];
+            return;
+        }
+        let trend = self.config.model == KalmanModel::Trend;
+
+        // Predicción: nivel += pendiente
+        let [[p00, p01], [p10, p11]] = self.cov;
+        let (level, slope) = if trend {
+            (self.state[0] + self.state[1], self.state[1])
+        } else {
+            (self.state[0], 0.0)
+        };
+        let mut pred = if trend {
+            [[p00 + p01 + p10 + p11, p01 + p11], [p10 + p11, p11]]
+        } else {
+            [[p00, 0.0], [0.0, 0.0]]
+        };
+        pred[0][0] += self.process[0];
+        if trend {
+            pred[1][1] += self.process[1];
+        }
+
+        // Corrección con la innovación
+        let innovation = price - level;
+        let s = pred[0][0] + self.measurement;
+        let gain = [pred[0][0] / s, pred[1][0] / s];
+        self.state = [level + gain[0] * innovation, slope + gain[1] * innovation];
+        for (i, row) in self.cov.iter_mut().enumerate() {
+            for (j, c) in row.iter_mut().enumerate() {
+                *c = pred[i][j] - gain[i] * pred[0][j];
+            }
+        }
+        self.innovation_variance = s;
+    }
+
+    /// Actualiza las autocovarianzas de las diferencias y de ellas los ruidos
+    fn estimate_noise(&mut self, price: f64) {
+        let order = match self.config.model {
+            KalmanModel::Level => 1,
+            KalmanModel::Trend => 2,
+        };
+        self.recent.push_back(price);
+        if self.recent.len() > order + 3 {
+            self.recent.pop_front();
+        }
+        // Diferencias de orden `order`, la más reciente primero
+        let obs: Vec<f64> = self.recent.iter().rev().copied().collect();
+        let diffs: Vec<f64> = (0..obs.len().saturating_sub(order))
+            .map(|i| match order {
+                1 => obs[i] - obs[i + 1],
+                _ => obs[i] - 2.0 * obs[i + 1] + obs[i + 2],
+            })
+            .collect();
+        let Some(&d0) = diffs.first() else {
+            return;
+        };
+
+        // Media exponencial con corrección del sesgo inicial
+        let alpha = 1.0 - 0.5f64.powf(1.0 / self.config.halflife as f64);
+        self.weight = (1.0 - alpha) * self.weight + alpha;
+        let w = alpha / self.weight;
+        for (lag, gamma) in self.autocov.iter_mut().enumerate() {
+            let product = diffs.get(lag).map_or(*gamma, |d| d0 * d);
+            *gamma = (1.0 - w) * *gamma + w * product;
+        }
+
+        let [g0, g1, g2] = self.autocov;
+        let floor = (0.01 * g0).max(MIN_VARIANCE);
+        match self.config.model {
+            KalmanModel::Level => {
+                self.measurement = (-g1).max(floor);
+                self.process = [(g0 + 2.0 * g1).max(floor), 0.0];
+            }
+            KalmanModel::Trend => {
+                let r = g2.max(floor);
+                let q = (-g1 - 4.0 * r).max(floor);
+                let zeta = (g0 - 2.0 * q - 6.0 * r).max(0.01 * floor);
+                self.measurement = r;
+                self.process = [q, zeta];
+            }
+        }
+    }
+
+    /// None hasta la primera corrección
+    pub fn estimate(&self) -> Option<KalmanEstimate> {
+        (self.updates >= 2).then_some(KalmanEstimate {
+            fair_value: self.state[0],
+            velocity: self.state[1],
+            innovation_variance: self.innovation_variance,
+        })
+    }
+
+    /// Desviación de la innovación relativa al precio, en la misma escala [0, 1]
+    /// que `GaussianFilter::compute_uncertainty` (filtro de ruido alternativo)
+    pub fn compute_uncertainty(&self) -> f64 {
+        match self.estimate() {
+            Some(e) if self.updates >= 5 && e.fair_value > 0.0 => {
+                (e.innovation_variance.sqrt() / e.fair_value * 1000.0).min(1.0)
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use rand_distr::StandardNormal;
+
+    /// Nivel con pendiente aleatoria observado con ruido: varianzas (q, ζ, r)
+    fn simulate(q: f64, zeta: f64, r: f64, len: usize, seed: u64) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut noise = move |var: f64| var.sqrt() * rng.sample::<f64, _>(StandardNormal);
+        let (mut level, mut slope) = (1e7, 0.0);
+        (0..len)
+            .map(|_| {
+                level += slope + noise(q);
+                slope += noise(zeta);
+                level + noise(r)
+            })
+            .collect()
+    }
+
+    /// Filtro con semivida mayor que la serie: momentos de muestra completa
+    fn filtered(model: KalmanModel, prices: &[f64]) -> KalmanFilter {
+        let mut filter = KalmanFilter::new(KalmanConfig {
+            model,
+            halflife: 10_000_000,
+        });
+        prices.iter().for_each(|&p| filter.update(p));
+        filter
+    }
+
+    fn assert_near(name: &str, estimate: f64, truth: f64, tol: f64) {
+        assert!(
+            (estimate / truth - 1.0).abs() < tol,
+            "{}: {} frente a {}",
+            name,
+            estimate,
+            truth
+        );
+    }
+
+    #[test]
+    fn level_noise_moments_recover_q_and_r() {
+        let prices = simulate(0.01, 0.0, 0.04, 100_000, 1);
+        let filter = filtered(KalmanModel::Level, &prices);
+        assert_near("r", filter.measurement, 0.04, 0.05);
+        assert_near("q", filter.process[0], 0.01, 0.05);
+    }
+
+    #[test]
+    fn trend_noise_moments_recover_q_zeta_and_r() {
+        // γ0 = ζ + 2q + 6r = 0.28, γ1 = -q - 4r = -0.18, γ2 = r = 0.04
+        let prices = simulate(0.02, 0.0004, 0.04, 50_000, 2);
+        let filter = filtered(KalmanModel::Trend, &prices);
+        assert_near("γ0", filter.autocov[0], 0.2804, 0.05);
+        assert_near("γ1", filter.autocov[1], -0.18, 0.05);
+        assert_near("r", filter.measurement, 0.04, 0.1);
+        assert_near("q", filter.process[0], 0.02, 0.15);
+        assert!(filter.process[1] > 0.0);
+    }
+
+    #[test]
+    fn constant_price_has_no_innovation() {
+        let filter = filtered(KalmanModel::Level, &[1.1; 50]);
+        let estimate = filter.estimate().unwrap();
+        assert_eq!(estimate.fair_value, 1.1);
+        assert_eq!(estimate.velocity, 0.0);
+        assert!(estimate.innovation_variance < 1e-18);
+        assert!(filter.compute_uncertainty() < 1e-6);
+    }
+
+    #[test]
+    fn trend_model_tracks_a_ramp() {
+        let prices: Vec<f64> = (0..500)
+            .map(|i| 100.0 + 0.01 * i as f64 + if i % 2 == 0 { 0.002 } else { -0.002 })
+            .collect();
+        let estimate = filtered(KalmanModel::Trend, &prices).estimate().unwrap();
+        assert!(
+            (estimate.velocity - 0.01).abs() < 1e-3,
+            "{}",
+            estimate.velocity
+        );
+        assert!((estimate.fair_value - (100.0 + 0.01 * 499.0)).abs() < 0.01);
+    }
+
+    #[test]
+    fn needs_two_valid_prices_before_estimating() {
+        let mut filter = KalmanFilter::new(KalmanConfig::default());
+        filter.update(f64::NAN);
+        filter.update(-1.0);
+        filter.update(1.0);
+        assert!(filter.estimate().is_none());
+        assert_eq!(filter.compute_uncertainty(), 1.0);
+        filter.update(1.0);
+        assert!(filter.estimate().is_some());
+    }
+
+    #[test]
+    fn parse_model_and_halflife() {
+        let config = KalmanConfig::parse("level:50").unwrap();
+        assert_eq!((config.model, config.halflife), (KalmanModel::Level, 50));
+        assert_eq!(KalmanConfig::parse("trend"), Some(KalmanConfig::default()));
+        assert!(KalmanConfig::parse("trend:0").is_none());
+        assert!(KalmanConfig::parse("ar1").is_none());
+    }
+}
@@ -0,0 +1,368 @@
+use std::f64::consts::PI;
+
+/// Función de covarianza k(x1, x2) para el proceso Gaussiano.
+/// `x` es el eje temporal: índice de tick o timestamp en segundos.
+pub trait Kernel: Send + Sync {
+    fn eval(&self, x1: f64, x2: f64) -> f64;
+}
+
+/// Kernel RBF (Radial Basis Function)
+/// Curvas muy suaves, infinitamente diferenciables.
+pub struct Rbf {
+    pub length_scale: f64,
+    pub sigma_f: f64,
+}
+
+impl Rbf {
+    pub fn new(length_scale: f64, sigma_f: f64) -> Self {
+        Self {
+            length_scale,
+            sigma_f,
+        }
+    }
+}
+
+impl Kernel for Rbf {
+    fn eval(&self, x1: f64, x2: f64) -> f64 {
+        let diff = (x1 - x2).powi(2);
+        self.sigma_f.powi(2) * (-diff / (2.0 * self.length_scale.powi(2))).exp()
+    }
+}
+
+/// Matérn ν = 3/2: trayectorias más rugosas que RBF (una vez diferenciables).
+pub struct Matern32 {
+    pub length_scale: f64,
+    pub sigma_f: f64,
+}
+
+impl Matern32 {
+    pub fn new(length_scale: f64, sigma_f: f64) -> Self {
+        Self {
+            length_scale,
+            sigma_f,
+        }
+    }
+}
+
+impl Kernel for Matern32 {
+    fn eval(&self, x1: f64, x2: f64) -> f64 {
+        let r = (x1 - x2).abs() * 3f64.sqrt() / self.length_scale;
+        self.sigma_f.powi(2) * (1.0 + r) * (-r).exp()
+    }
+}
+
+/// Matérn ν = 5/2: punto intermedio entre Matérn 3/2 y RBF.
+pub struct Matern52 {
+    pub length_scale: f64,
+    pub sigma_f: f64,
+}
+
+impl Matern52 {
+    pub fn new(length_scale: f64, sigma_f: f64) -> Self {
+        Self {
+            length_scale,
+            sigma_f,
+        }
+    }
+}
+
+impl Kernel for Matern52 {
+    fn eval(&self, x1: f64, x2: f64) -> f64 {
+        let r = (x1 - x2).abs() * 5f64.sqrt() / self.length_scale;
+        self.sigma_f.powi(2) * (1.0 + r + r * r / 3.0) * (-r).exp()
+    }
+}
+
+/// Cuadrático Racional: mezcla de RBF con múltiples escalas de longitud.
+/// `alpha` controla el peso relativo de escalas grandes y pequeñas.
+pub struct RationalQuadratic {
+    pub length_scale: f64,
+    pub sigma_f: f64,
+    pub alpha: f64,
+}
+
+impl RationalQuadratic {
+    pub fn new(length_scale: f64, sigma_f: f64, alpha: f64) -> Self {
+        Self {
+            length_scale,
+            sigma_f,
+            alpha,
+        }
+    }
+}
+
+impl Kernel for RationalQuadratic {
+    fn eval(&self, x1: f64, x2: f64) -> f64 {
+        let diff = (x1 - x2).powi(2);
+        let base = 1.0 + diff / (2.0 * self.alpha * self.length_scale.powi(2));
+        self.sigma_f.powi(2) * base.powf(-self.alpha)
+    }
+}
+
+/// Kernel Periódico: modela ciclos (reversión a la media oscilante).
+pub struct Periodic {
+    pub length_scale: f64,
+    pub sigma_f: f64,
+    pub period: f64,
+}
+
+impl Periodic {
+    pub fn new(length_scale: f64, sigma_f: f64, period: f64) -> Self {
+        Self {
+            length_scale,
+            sigma_f,
+            period,
+        }
+    }
+}
+
+impl Kernel for Periodic {
+    fn eval(&self, x1: f64, x2: f64) -> f64 {
+        let s = (PI * (x1 - x2).abs() / self.period).sin();
+        self.sigma_f.powi(2) * (-2.0 * s * s / self.length_scale.powi(2)).exp()
+    }
+}
+
+/// Kernel Lineal: captura la tendencia (drift) del precio.
+/// k(x1, x2) = sigma_b² + sigma_v² · (x1 - c)(x2 - c)
+pub struct Linear {
+    pub sigma_b: f64,
+    pub sigma_v: f64,
+    pub offset: f64,
+}
+
+impl Linear {
+    pub fn new(sigma_b: f64, sigma_v: f64, offset: f64) -> Self {
+        Self {
+            sigma_b,
+            sigma_v,
+            offset,
+        }
+    }
+}
+
+impl Kernel for Linear {
+    fn eval(&self, x1: f64, x2: f64) -> f64 {
+        self.sigma_b.powi(2) + self.sigma_v.powi(2) * (x1 - self.offset) * (x2 - self.offset)
+    }
+}
+
+/// Suma de kernels: procesos independientes superpuestos (ej. Tendencia + Ciclo).
+pub struct SumKernel {
+    pub left: Box<dyn Kernel>,
+    pub right: Box<dyn Kernel>,
+}
+
+impl SumKernel {
+    pub fn new(left: Box<dyn Kernel>, right: Box<dyn Kernel>) -> Self {
+        Self { left, right }
+    }
+}
+
+impl Kernel for SumKernel {
+    fn eval(&self, x1: f64, x2: f64) -> f64 {
+        self.left.eval(x1, x2) + self.right.eval(x1, x2)
+    }
+}
+
+/// Producto de kernels: modulación (ej. Ciclo cuya amplitud decae con RBF).
+pub struct ProductKernel {
+    pub left: Box<dyn Kernel>,
+    pub right: Box<dyn Kernel>,
+}
+
+impl ProductKernel {
+    pub fn new(left: Box<dyn Kernel>, right: Box<dyn Kernel>) -> Self {
+        Self { left, right }
+    }
+}
+
+impl Kernel for ProductKernel {
+    fn eval(&self, x1: f64, x2: f64) -> f64 {
+        self.left.eval(x1, x2) * self.right.eval(x1, x2)
+    }
+}
+
+/// Kernel configurable por nombre: "rbf", "matern32", "matern52", "rq[:alpha]",
+/// "periodic[:periodo]", "linear", compuestos con `+` y `*` ("rbf+periodic:60").
+/// La escala de longitud y sigma_f son comunes a todos los términos.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KernelSpec {
+    Rbf,
+    Matern32,
+    Matern52,
+    RationalQuadratic(f64),
+    Periodic(f64),
+    Linear,
+    Sum(Box<KernelSpec>, Box<KernelSpec>),
+    Product(Box<KernelSpec>, Box<KernelSpec>),
+}
+
+impl KernelSpec {
+    /// `*` liga más que `+`: "rbf+periodic:60*rbf" = rbf + (periodic · rbf)
+    pub fn parse(spec: &str) -> Option<Self> {
+        let mut terms = spec.split('+').map(|term| {
+            let mut factors = term.split('*').map(Self::parse_atom);
+            let first = factors.next()??;
+            factors.try_fold(first, |acc, f| {
+                Some(KernelSpec::Product(Box::new(acc), Box::new(f?)))
+            })
+        });
+        let first = terms.next()??;
+        terms.try_fold(first, |acc, t| {
+            Some(KernelSpec::Sum(Box::new(acc), Box::new(t?)))
+        })
+    }
+
+    fn parse_atom(atom: &str) -> Option<Self> {
+        let (name, param) = match atom.trim().split_once(':') {
+            Some((name, param)) => (name, Some(param.parse::<f64>().ok()?)),
+            None => (atom.trim(), None),
+        };
+        if param.is_some_and(|p| !(p.is_finite() && p > 0.0)) {
+            return None;
+        }
+        match (name, param) {
+            ("rbf", None) => Some(KernelSpec::Rbf),
+            ("matern32", None) => Some(KernelSpec::Matern32),
+            ("matern52", None) => Some(KernelSpec::Matern52),
+            ("rq", alpha) => Some(KernelSpec::RationalQuadratic(alpha.unwrap_or(1.0))),
+            ("periodic", period) => Some(KernelSpec::Periodic(period.unwrap_or(60.0))),
+            ("linear", None) => Some(KernelSpec::Linear),
+            _ => None,
+        }
+    }
+
+    pub fn build(&self, length_scale: f64, sigma_f: f64) -> Box<dyn Kernel> {
+        match self {
+            KernelSpec::Rbf => Box::new(Rbf::new(length_scale, sigma_f)),
+            KernelSpec::Matern32 => Box::new(Matern32::new(length_scale, sigma_f)),
+            KernelSpec::Matern52 => Box::new(Matern52::new(length_scale, sigma_f)),
+            KernelSpec::RationalQuadratic(alpha) => {
+                Box::new(RationalQuadratic::new(length_scale, sigma_f, *alpha))
+            }
+            KernelSpec::Periodic(period) => Box::new(Periodic::new(length_scale, sigma_f, *period)),
+            KernelSpec::Linear => Box::new(Linear::new(sigma_f, sigma_f, 0.0)),
+            KernelSpec::Sum(left, right) => Box::new(SumKernel::new(
+                left.build(length_scale, sigma_f),
+                right.build(length_scale, sigma_f),
+            )),
+            KernelSpec::Product(left, right) => Box::new(ProductKernel::new(
+                left.build(length_scale, sigma_f),
+                right.build(length_scale, sigma_f),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kernels() -> Vec<Box<dyn Kernel>> {
+        vec![
+            Box::new(Rbf::new(1.5, 2.0)),
+            Box::new(Matern32::new(1.5, 2.0)),
+            Box::new(Matern52::new(1.5, 2.0)),
+            Box::new(RationalQuadratic::new(1.5, 2.0, 0.5)),
+            Box::new(Periodic::new(1.5, 2.0, 3.0)),
+            Box::new(SumKernel::new(
+                Box::new(Rbf::new(1.0, 1.0)),
+                Box::new(Periodic::new(1.0, 1.0, 4.0)),
+            )),
+            Box::new(ProductKernel::new(
+                Box::new(Matern32::new(2.0, 1.0)),
+                Box::new(RationalQuadratic::new(1.0, 1.0, 2.0)),
+            )),
+        ]
+    }
+
+    #[test]
+    fn stationary_kernels_are_symmetric_and_peak_at_zero_lag() {
+        for kernel in kernels() {
+            let k0 = kernel.eval(3.0, 3.0);
+            assert!(k0 > 0.0);
+            for (a, b) in [(0.0, 0.7), (2.0, -1.5), (10.0, 13.0)] {
+                let k = kernel.eval(a, b);
+                assert!((k - kernel.eval(b, a)).abs() < 1e-15);
+                assert!(k.abs() <= k0 + 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn known_values_at_unit_lag() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+        assert!(close(
+            Rbf::new(1.0, 2.0).eval(0.0, 1.0),
+            4.0 * (-0.5f64).exp()
+        ));
+        let r3 = 3f64.sqrt();
+        assert!(close(
+            Matern32::new(1.0, 1.0).eval(0.0, 1.0),
+            (1.0 + r3) * (-r3).exp()
+        ));
+        let r5 = 5f64.sqrt();
+        assert!(close(
+            Matern52::new(1.0, 1.0).eval(0.0, 1.0),
+            (1.0 + r5 + 5.0 / 3.0) * (-r5).exp()
+        ));
+        // (1 + 1/2)^-1
+        assert!(close(
+            RationalQuadratic::new(1.0, 1.0, 1.0).eval(0.0, 1.0),
+            2.0 / 3.0
+        ));
+        // Media vuelta: sin² = 1; vuelta completa: correlación total
+        let periodic = Periodic::new(1.0, 1.0, 2.0);
+        assert!(close(periodic.eval(0.0, 1.0), (-2.0f64).exp()));
+        assert!(close(periodic.eval(0.0, 2.0), 1.0));
+        assert_eq!(Linear::new(1.0, 1.0, 0.0).eval(2.0, 3.0), 7.0);
+    }
+
+    #[test]
+    fn sum_and_product_combine_pointwise() {
+        let sum = SumKernel::new(
+            Box::new(Rbf::new(1.0, 1.0)),
+            Box::new(Linear::new(0.0, 1.0, 0.0)),
+        );
+        let product = ProductKernel::new(
+            Box::new(Rbf::new(1.0, 1.0)),
+            Box::new(Linear::new(0.0, 1.0, 0.0)),
+        );
+        let rbf = (-0.5f64).exp();
+        assert!((sum.eval(1.0, 2.0) - (rbf + 2.0)).abs() < 1e-12);
+        assert!((product.eval(1.0, 2.0) - 2.0 * rbf).abs() < 1e-12);
+    }
+
+    #[test]
+    fn parse_specs_with_product_binding_tighter() {
+        assert_eq!(KernelSpec::parse("matern32"), Some(KernelSpec::Matern32));
+        assert_eq!(
+            KernelSpec::parse("rq"),
+            Some(KernelSpec::RationalQuadratic(1.0))
+        );
+        assert_eq!(
+            KernelSpec::parse("rbf+periodic:60*rbf"),
+            Some(KernelSpec::Sum(
+                Box::new(KernelSpec::Rbf),
+                Box::new(KernelSpec::Product(
+                    Box::new(KernelSpec::Periodic(60.0)),
+                    Box::new(KernelSpec::Rbf)
+                ))
+            ))
+        );
+        for bad in [
+            "",
+            "rbf+",
+            "rbf:2",
+            "periodic:0",
+            "rq:-1",
+            "rq:inf",
+            "cosine",
+        ] {
+            assert_eq!(KernelSpec::parse(bad), None, "{}", bad);
+        }
+        let built = KernelSpec::parse("rbf*linear").unwrap().build(1.0, 1.0);
+        assert!((built.eval(1.0, 2.0) - 3.0 * (-0.5f64).exp()).abs() < 1e-12);
+    }
+}
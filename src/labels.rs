@@ -0,0 +1,523 @@
+use crate::brain::{BrainConfig, OutputActivation};
+use ndarray::Array1;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Esquema de etiquetado de los movimientos de precio
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LabelScheme {
+    /// Original: 1 si el precio final supera al de entrada, 0 en otro caso
+    Binary,
+    /// Baja / Plano / Sube, con una banda muerta de `dead_band_ticks` alrededor de 0
+    ThreeClass { dead_band_ticks: f64 },
+    /// Triple barrera: toma de beneficio (sube), stop-loss (baja) o tiempo agotado (plano)
+    TripleBarrier {
+        take_profit_ticks: f64,
+        stop_loss_ticks: f64,
+    },
+    /// Regresión sobre el log-retorno en el horizonte
+    LogReturn,
+}
+
+/// Etiqueta resuelta
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Label {
+    /// Índice de clase ordenado de bajista a alcista (0 = baja)
+    Class(usize),
+    Value(f64),
+}
+
+impl LabelScheme {
+    /// Nombre en configuración (LABEL_SCHEME): "binary", "three_class[:banda]",
+    /// "triple_barrier[:toma:stop]" (en ticks, finitos y positivos), "log_return"
+    pub fn from_name(name: &str) -> Option<Self> {
+        let mut parts = name.split(':');
+        let scheme = parts.next()?;
+        let ticks: Vec<f64> = parts
+            .map(|p| p.parse().ok().filter(|t: &f64| t.is_finite() && *t > 0.0))
+            .collect::<Option<_>>()?;
+        match (scheme, ticks.as_slice()) {
+            ("binary", []) => Some(LabelScheme::Binary),
+            ("three_class", []) => Some(LabelScheme::ThreeClass {
+                dead_band_ticks: 1.0,
+            }),
+            ("three_class", &[dead_band_ticks]) => {
+                Some(LabelScheme::ThreeClass { dead_band_ticks })
+            }
+            ("triple_barrier", []) => Some(LabelScheme::TripleBarrier {
+                take_profit_ticks: 5.0,
+                stop_loss_ticks: 5.0,
+            }),
+            ("triple_barrier", &[take_profit_ticks, stop_loss_ticks]) => {
+                Some(LabelScheme::TripleBarrier {
+                    take_profit_ticks,
+                    stop_loss_ticks,
+                })
+            }
+            ("log_return", []) => Some(LabelScheme::LogReturn),
+            _ => None,
+        }
+    }
+
+    /// Ajusta la cabeza de salida de la red al esquema
+    pub fn configure(&self, config: &mut BrainConfig) {
+        match self {
+            LabelScheme::Binary => {
+                config.output = OutputActivation::Sigmoid;
+                config.output_dim = 1;
+            }
+            LabelScheme::ThreeClass { .. } | LabelScheme::TripleBarrier { .. } => {
+                config.output = OutputActivation::Softmax;
+                config.output_dim = 3;
+            }
+            LabelScheme::LogReturn => {
+                config.output = OutputActivation::Gaussian;
+                config.output_dim = 2;
+            }
+        }
+    }
+
+    /// Etiqueta los precios posteriores a la entrada dentro del horizonte.
+    /// `complete` indica que el horizonte ya se cumplió; si no, solo la triple
+    /// barrera puede resolver (cuando toca una barrera).
+    pub fn label(&self, entry: f64, path: &[f64], complete: bool, tick_size: f64) -> Option<Label> {
+        if let LabelScheme::TripleBarrier {
+            take_profit_ticks,
+            stop_loss_ticks,
+        } = *self
+        {
+            // La primera barrera tocada decide
+            for &p in path {
+                let ticks = (p - entry) / tick_size;
+                if ticks >= take_profit_ticks {
+                    return Some(Label::Class(2));
+                }
+                if ticks <= -stop_loss_ticks {
+                    return Some(Label::Class(0));
+                }
+            }
+            return complete.then_some(Label::Class(1));
+        }
+
+        if !complete {
+            return None;
+        }
+        // Sin actualizaciones dentro del horizonte el precio no cambió
+        let exit = path.last().copied().unwrap_or(entry);
+
+        Some(match *self {
+            LabelScheme::Binary => Label::Class(usize::from(exit > entry)),
+            LabelScheme::ThreeClass { dead_band_ticks } => {
+                let ticks = (exit - entry) / tick_size;
+                if ticks > dead_band_ticks {
+                    Label::Class(2)
+                } else if ticks < -dead_band_ticks {
+                    Label::Class(0)
+                } else {
+                    Label::Class(1)
+                }
+            }
+            LabelScheme::LogReturn => Label::Value((exit / entry).ln()),
+            LabelScheme::TripleBarrier { .. } => unreachable!(),
+        })
+    }
+
+    /// Resultado en [0, 1] comparable con la puntuación de `predict_with_uncertainty`
+    /// (para evaluar la calibración): clase / (K - 1) o 1 si el retorno es positivo.
+    pub fn outcome(&self, label: Label) -> f64 {
+        match (self, label) {
+            (LabelScheme::Binary, Label::Class(k)) => k as f64,
+            (_, Label::Class(k)) => k as f64 / 2.0,
+            (_, Label::Value(v)) => f64::from(u8::from(v > 0.0)),
+        }
+    }
+
+    /// Codifica la etiqueta como vector de targets para `BayesianBrain::train_targets`
+    pub fn encode(&self, label: Label, output_dim: usize) -> Array1<f64> {
+        match (self, label) {
+            (LabelScheme::Binary, Label::Class(k)) => Array1::from_elem(output_dim, k as f64),
+            (_, Label::Class(k)) => {
+                let mut one_hot = Array1::zeros(output_dim);
+                one_hot[k.min(output_dim - 1)] = 1.0;
+                one_hot
+            }
+            // Log-retornos en puntos básicos: escala O(1) para la cabeza Gaussiana
+            (_, Label::Value(v)) => {
+                let mut t = Array1::zeros(output_dim);
+                t[0] = v * 1e4;
+                t
+            }
+        }
+    }
+}
+
+/// Horizonte de predicción en uno de los tres relojes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Horizon {
+    /// Actualizaciones del libro (el original `prediction_queue.len() > 5`)
+    Updates(usize),
+    /// Tiempo de reloj en segundos
+    Seconds(f64),
+    /// Volumen acumulado desde la entrada
+    Volume(f64),
+}
+
+impl Horizon {
+    /// Formato de configuración: "5u" (updates), "500ms", "5s", "1000v" (volumen)
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if let Some(v) = text.strip_suffix("ms") {
+            return v
+                .parse::<f64>()
+                .ok()
+                .map(|ms| Horizon::Seconds(ms / 1000.0));
+        }
+        if let Some(v) = text.strip_suffix('s') {
+            return v.parse().ok().map(Horizon::Seconds);
+        }
+        if let Some(v) = text.strip_suffix('v') {
+            return v.parse().ok().map(Horizon::Volume);
+        }
+        if let Some(v) = text.strip_suffix('u') {
+            return v.parse().ok().map(Horizon::Updates);
+        }
+        None
+    }
+
+    /// Lista separada por comas ("1s,5s,30s"); None si alguna entrada no es válida
+    pub fn parse_list(text: &str) -> Option<Vec<Self>> {
+        text.split(',').map(Self::parse).collect()
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Horizon::Updates(n) => format!("{}u", n),
+            Horizon::Seconds(s) if *s < 1.0 => format!("{}ms", s * 1000.0),
+            Horizon::Seconds(s) => format!("{}s", s),
+            Horizon::Volume(v) => format!("{}v", v),
+        }
+    }
+
+    /// Si un nuevo punto del camino entra en el horizonte y si con él el horizonte
+    /// se cumple, dados los `seen` puntos ya incluidos.
+    ///
+    /// - Updates: los primeros n puntos; completo con n puntos.
+    /// - Seconds: los puntos con t <= t0 + h; completo cuando llega uno posterior
+    ///   (hasta entonces podría llegar otro precio antes del plazo).
+    /// - Volume: hasta el primer punto que alcanza el volumen (incluido).
+    fn step(
+        &self,
+        entry_time: f64,
+        entry_volume: f64,
+        seen: usize,
+        point: &PathPoint,
+    ) -> (bool, bool) {
+        match *self {
+            Horizon::Updates(n) => (seen < n, seen + 1 >= n),
+            Horizon::Seconds(h) => {
+                let expired = point.time - entry_time > h;
+                (!expired, expired)
+            }
+            Horizon::Volume(v) => (true, point.volume - entry_volume >= v),
+        }
+    }
+}
+
+/// Punto del camino: instante, volumen acumulado y precio
+#[derive(Debug, Clone, Copy)]
+struct PathPoint {
+    time: f64,
+    volume: f64,
+    price: f64,
+}
+
+/// Entrada pendiente de etiquetar
+struct Pending<T> {
+    payload: T,
+    entry: f64,
+    entry_time: f64,
+    entry_volume: f64,
+    /// Puntos incluidos en el horizonte y el último de ellos (la salida)
+    seen: usize,
+    last: Option<PathPoint>,
+}
+
+/// Entrada etiquetada
+pub struct Resolved<T> {
+    pub payload: T,
+    pub label: Label,
+    pub entry: f64,
+    pub exit: f64,
+    pub entry_time: f64,
+    pub exit_time: f64,
+}
+
+/// Cola de etiquetado: cada nueva actualización de precio extiende el camino de
+/// las entradas pendientes y devuelve las que ya tienen etiqueta. Cada entrada
+/// guarda solo su estado (puntos vistos y último precio): O(pendientes) por update.
+pub struct Labeler<T> {
+    pub scheme: LabelScheme,
+    pub horizon: Horizon,
+    pub tick_size: f64,
+    cum_volume: f64,
+    pending: VecDeque<Pending<T>>,
+}
+
+impl<T> Labeler<T> {
+    pub fn new(scheme: LabelScheme, horizon: Horizon, tick_size: f64) -> Self {
+        Self {
+            scheme,
+            horizon,
+            tick_size,
+            cum_volume: 0.0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Registra una entrada al precio `entry` en el instante `time` (segundos)
+    pub fn push(&mut self, payload: T, entry: f64, time: f64) {
+        self.pending.push_back(Pending {
+            payload,
+            entry,
+            entry_time: time,
+            entry_volume: self.cum_volume,
+            seen: 0,
+            last: None,
+        });
+    }
+
+    /// Nuevo precio observado en `time` con `volume` negociado/actualizado desde
+    /// la anterior llamada. Devuelve las entradas resueltas (en orden de entrada).
+    pub fn update(&mut self, price: f64, time: f64, volume: f64) -> Vec<Resolved<T>> {
+        self.cum_volume += volume;
+        let point = PathPoint {
+            time,
+            volume: self.cum_volume,
+            price,
+        };
+
+        let mut resolved = Vec::new();
+        let mut still_pending = VecDeque::with_capacity(self.pending.len());
+        for mut p in self.pending.drain(..) {
+            let (include, complete) =
+                self.horizon
+                    .step(p.entry_time, p.entry_volume, p.seen, &point);
+            if include {
+                p.seen += 1;
+                p.last = Some(point);
+            }
+            // Basta el último precio incluido: la triple barrera ya comprobó los
+            // anteriores y el resto de esquemas solo miran la salida
+            let path = p
+                .last
+                .as_ref()
+                .map_or(&[][..], |pt| std::slice::from_ref(&pt.price));
+            match self.scheme.label(p.entry, path, complete, self.tick_size) {
+                Some(label) => resolved.push(Resolved {
+                    label,
+                    entry: p.entry,
+                    // Salida: último punto considerado (o la entrada si no hubo ninguno)
+                    exit: p.last.map_or(p.entry, |pt| pt.price),
+                    entry_time: p.entry_time,
+                    exit_time: p.last.map_or(p.entry_time, |pt| pt.time),
+                    payload: p.payload,
+                }),
+                None => still_pending.push_back(p),
+            }
+        }
+        self.pending = still_pending;
+        resolved
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: f64 = 0.25;
+    const BARRIER: LabelScheme = LabelScheme::TripleBarrier {
+        take_profit_ticks: 2.0,
+        stop_loss_ticks: 3.0,
+    };
+
+    #[test]
+    fn triple_barrier_first_touch_decides() {
+        // +2 ticks toca la toma de beneficio antes que el stop de -3
+        assert_eq!(
+            BARRIER.label(10.0, &[10.25, 10.5, 9.0], false, TICK),
+            Some(Label::Class(2))
+        );
+        assert_eq!(
+            BARRIER.label(10.0, &[9.75, 9.25, 11.0], false, TICK),
+            Some(Label::Class(0))
+        );
+    }
+
+    #[test]
+    fn triple_barrier_waits_or_times_out_flat() {
+        let path = [10.25, 9.5, 10.25];
+        assert_eq!(BARRIER.label(10.0, &path, false, TICK), None);
+        assert_eq!(
+            BARRIER.label(10.0, &path, true, TICK),
+            Some(Label::Class(1))
+        );
+    }
+
+    #[test]
+    fn three_class_dead_band_is_exclusive() {
+        let scheme = LabelScheme::ThreeClass {
+            dead_band_ticks: 1.0,
+        };
+        assert_eq!(
+            scheme.label(10.0, &[10.25], true, TICK),
+            Some(Label::Class(1))
+        );
+        assert_eq!(
+            scheme.label(10.0, &[10.5], true, TICK),
+            Some(Label::Class(2))
+        );
+        assert_eq!(
+            scheme.label(10.0, &[9.5], true, TICK),
+            Some(Label::Class(0))
+        );
+        assert_eq!(scheme.label(10.0, &[10.5], false, TICK), None);
+    }
+
+    #[test]
+    fn binary_and_log_return_use_the_exit() {
+        assert_eq!(
+            LabelScheme::Binary.label(10.0, &[11.0, 9.0], true, TICK),
+            Some(Label::Class(0))
+        );
+        assert_eq!(
+            LabelScheme::Binary.label(10.0, &[], true, TICK),
+            Some(Label::Class(0))
+        );
+        match LabelScheme::LogReturn.label(10.0, &[9.0, 11.0], true, TICK) {
+            Some(Label::Value(v)) => assert!((v - 1.1f64.ln()).abs() < 1e-12),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_schemes_with_their_ticks() {
+        assert_eq!(LabelScheme::from_name("binary"), Some(LabelScheme::Binary));
+        assert_eq!(
+            LabelScheme::from_name("three_class:2"),
+            Some(LabelScheme::ThreeClass {
+                dead_band_ticks: 2.0
+            })
+        );
+        assert_eq!(LabelScheme::from_name("triple_barrier:2:3"), Some(BARRIER));
+        assert_eq!(
+            LabelScheme::from_name("triple_barrier"),
+            Some(LabelScheme::TripleBarrier {
+                take_profit_ticks: 5.0,
+                stop_loss_ticks: 5.0,
+            })
+        );
+        for bad in [
+            "three_class:0",
+            "three_class:-1",
+            "three_class:inf",
+            "triple_barrier:5",
+            "triple_barrier:5:0",
+            "binary:1",
+            "quintiles",
+        ] {
+            assert_eq!(LabelScheme::from_name(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn encode_and_configure_the_head() {
+        assert_eq!(
+            BARRIER.encode(Label::Class(2), 3).to_vec(),
+            vec![0.0, 0.0, 1.0]
+        );
+        assert_eq!(BARRIER.outcome(Label::Class(1)), 0.5);
+        let t = LabelScheme::LogReturn.encode(Label::Value(0.0012), 2);
+        assert!((t[0] - 12.0).abs() < 1e-9 && t[1] == 0.0);
+
+        let mut config = BrainConfig::legacy(3, 4, 0.1);
+        BARRIER.configure(&mut config);
+        assert_eq!(
+            (config.output, config.output_dim),
+            (OutputActivation::Softmax, 3)
+        );
+        LabelScheme::LogReturn.configure(&mut config);
+        assert_eq!(
+            (config.output, config.output_dim),
+            (OutputActivation::Gaussian, 2)
+        );
+    }
+
+    #[test]
+    fn labeler_resolves_triple_barrier_before_the_horizon() {
+        let mut labeler = Labeler::new(BARRIER, Horizon::Updates(10), TICK);
+        labeler.push("a", 10.0, 0.0);
+        assert!(labeler.update(10.25, 1.0, 0.0).is_empty());
+        let resolved = labeler.update(10.5, 2.0, 0.0);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].label, Label::Class(2));
+        assert_eq!((resolved[0].exit, resolved[0].exit_time), (10.5, 2.0));
+        assert!(labeler.is_empty());
+    }
+
+    #[test]
+    fn horizon_parse_and_name_round_trip() {
+        for text in ["5u", "500ms", "5s", "1000v"] {
+            assert_eq!(Horizon::parse(text).unwrap().name(), text);
+        }
+        assert_eq!(Horizon::parse("500ms"), Some(Horizon::Seconds(0.5)));
+        assert_eq!(Horizon::parse("5x"), None);
+        assert_eq!(
+            Horizon::parse_list("1s, 5u"),
+            Some(vec![Horizon::Seconds(1.0), Horizon::Updates(5)])
+        );
+        assert_eq!(Horizon::parse_list("1s,5x"), None);
+        assert_eq!(Horizon::parse_list(""), None);
+    }
+
+    #[test]
+    fn updates_horizon_exits_on_the_nth_update() {
+        let mut labeler = Labeler::new(LabelScheme::Binary, Horizon::Updates(3), TICK);
+        labeler.push((), 10.0, 0.0);
+        assert!(labeler.update(11.0, 1.0, 0.0).is_empty());
+        assert!(labeler.update(12.0, 2.0, 0.0).is_empty());
+        let resolved = labeler.update(9.0, 3.0, 0.0);
+        assert_eq!(resolved[0].label, Label::Class(0));
+        assert_eq!(resolved[0].exit, 9.0);
+    }
+
+    #[test]
+    fn seconds_horizon_exits_at_the_last_price_inside_the_window() {
+        let mut labeler = Labeler::new(LabelScheme::Binary, Horizon::Seconds(2.0), TICK);
+        labeler.push((), 10.0, 0.0);
+        assert!(labeler.update(10.5, 1.0, 0.0).is_empty());
+        assert!(labeler.update(10.75, 2.0, 0.0).is_empty());
+        // El primer precio fuera del plazo cierra con el último dentro
+        let resolved = labeler.update(9.0, 2.5, 0.0);
+        assert_eq!((resolved[0].exit, resolved[0].exit_time), (10.75, 2.0));
+        assert_eq!(resolved[0].label, Label::Class(1));
+    }
+
+    #[test]
+    fn volume_horizon_includes_the_crossing_update() {
+        let mut labeler = Labeler::new(LabelScheme::Binary, Horizon::Volume(100.0), TICK);
+        labeler.update(10.0, 0.0, 50.0);
+        labeler.push((), 10.0, 0.0);
+        assert!(labeler.update(9.5, 1.0, 60.0).is_empty());
+        let resolved = labeler.update(10.25, 2.0, 40.0);
+        assert_eq!(resolved[0].exit, 10.25);
+        assert_eq!(resolved[0].label, Label::Class(1));
+    }
+}
@@ -0,0 +1,31 @@
+pub mod backtest;
+pub mod bars;
+pub mod bayesian;
+pub mod brain;
+pub mod calibration;
+pub mod checkpoint;
+pub mod decision;
+pub mod drift;
+pub mod ensemble;
+pub mod features;
+pub mod fix_engine;
+pub mod gaussian;
+pub mod hawkes;
+pub mod hmm;
+pub mod horizons;
+pub mod kalman;
+pub mod kernel;
+pub mod labels;
+pub mod model;
+pub mod network;
+pub mod optim;
+pub mod pipeline;
+pub mod recorder;
+pub mod regimes;
+pub mod rolling;
+pub mod scaling;
+pub mod state;
+pub mod trades;
+pub mod training;
+pub mod tuning;
+pub mod velocity;
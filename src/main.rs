@@ -1,49 +1,397 @@
+use chrono::Utc;
 use dotenv::dotenv;
 use log::{error, info, warn};
-use std::collections::VecDeque;
 use std::env;
 use std::error::Error;
-use std::time::Instant;
+use std::path::Path;
+use std::str::FromStr;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::time::{interval, Duration};
 
-mod bayesian;
-mod brain; // Cambiado de model a brain
-mod features;
-mod fix_engine;
-mod gaussian;
-mod network;
-mod state;
-
-use bayesian::BayesianNetwork;
-use brain::BayesianBrain;
-use features::FeatureCollector;
-use gaussian::GaussianFilter;
-use state::OrderBook;
+use motor_fix_rust::bars::{self, BarSpec, BarsOptions};
+use motor_fix_rust::bayesian::{BayesianNetwork, ContextSample};
+use motor_fix_rust::brain::{Activation, BrainConfig, Init};
+use motor_fix_rust::calibration::Calibrator;
+use motor_fix_rust::checkpoint::Checkpoint;
+use motor_fix_rust::decision::{
+    Decision, DecisionInputs, DecisionLog, GateCheck, SignalThresholds, Verdict,
+};
+use motor_fix_rust::drift::{detector_from_name, DriftAction, FeatureDriftMonitor};
+use motor_fix_rust::ensemble::{Combiner, Ensemble};
+use motor_fix_rust::features::FeatureConfig;
+use motor_fix_rust::fix_engine;
+use motor_fix_rust::gaussian::TimeAxis;
+use motor_fix_rust::hawkes::{self, HawkesConfig, HawkesParams};
+use motor_fix_rust::hmm::{self, GaussianHmm, HmmConfig};
+use motor_fix_rust::horizons::HorizonHead;
+use motor_fix_rust::kalman::KalmanConfig;
+use motor_fix_rust::kernel::KernelSpec;
+use motor_fix_rust::labels::{Horizon, LabelScheme};
+use motor_fix_rust::network;
+use motor_fix_rust::optim::{LrSchedule, Optimizer};
+use motor_fix_rust::pipeline::{ContextSource, MarketPipeline, NoiseSource, PipelineConfig};
+use motor_fix_rust::recorder::MarketRecorder;
+use motor_fix_rust::regimes::RegimeStore;
+use motor_fix_rust::scaling::{Clipping, Normalization};
+use motor_fix_rust::training::{self, TrainOptions};
+use motor_fix_rust::tuning::{self, TrialParams, TuneOptions};
+use motor_fix_rust::velocity::{RateMode, TimeSource};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     dotenv().ok();
     env_logger::init();
 
+    // Subcomandos offline: `train`, `tune`, `bars`, `hawkes` y `hmm`, todos sobre una grabación
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("train") => return training::run(&TrainOptions::from_args(&args[2..])?),
+        Some("tune") => return tuning::run(&TuneOptions::from_args(&args[2..])?),
+        Some("bars") => return bars::run(&BarsOptions::from_args(&args[2..])?),
+        Some("hawkes") => return hawkes::run(&args[2..]),
+        Some("hmm") => return hmm::run(&args[2..]),
+        _ => {}
+    }
+
     info!("=== MOTOR FIX v1.3.0 - BAYESIAN BRAIN ACTIVE ===");
 
     // 1. Inicialización de Componentes
     let mut engine = fix_engine::FixEngine::new();
-    let mut order_book = OrderBook::new();
-    let mut collector = FeatureCollector::new(100);
 
-    // Arquitectura: 7 Inputs (Price, Vel, Noise, Context + 3 Depth Imbalances), 12 Hidden, 0.01 LR
-    let mut brain = BayesianBrain::new(7, 12, 0.01);
+    // Esquema de etiquetado (binary, three_class[:banda], triple_barrier[:toma:stop],
+    // log_return)
+    let label_scheme = match env::var("LABEL_SCHEME") {
+        Ok(name) => LabelScheme::from_name(&name).ok_or("LABEL_SCHEME inválido")?,
+        Err(_) => LabelScheme::Binary,
+    };
+
+    // Columnas de entrada (FEATURES: "legacy" = Price, Vel, Noise, Context + 3 Depth
+    // Imbalances; "micro" = microestructura completa; o lista, ver FeatureConfig::parse)
+    let mut feature_config = FeatureConfig::parse(&env::var("FEATURES").unwrap_or_default())?;
+    // BARS: un vector de features por barra cerrada ("1m", "tick:100", "volume:1e6"),
+    // que lleva las columnas de esa barra
+    let bar_spec = match env::var("BARS") {
+        Ok(spec) => Some(BarSpec::parse(&spec).ok_or("BARS inválido")?),
+        Err(_) => None,
+    };
+    if bar_spec.is_some() {
+        feature_config.bar = true;
+    }
+    let schema = feature_config.schema();
+    info!(
+        "Features v{} ({:016x}): {}",
+        schema.version,
+        schema.hash,
+        schema.columns.join(", ")
+    );
+
+    let (clipping, feature_clipping) =
+        Clipping::parse_spec(&env::var("CLIPPING").unwrap_or_default())
+            .ok_or("CLIPPING inválido")?;
+
+    // Regímenes adaptativos (cuantiles por símbolo), persistidos entre sesiones
+    let symbol = "1";
+    let regimes_path = env::var("REGIMES_PATH").unwrap_or_else(|_| "regimes.json".to_string());
+    let mut pipeline_config = PipelineConfig {
+        regime_bins: env::var("REGIME_BINS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(3),
+        features: feature_config,
+        // GP_KERNEL: kernel del filtro Gaussiano ("rbf", "matern32", "rbf+periodic:60");
+        // GP_TIME: eje temporal (tick o timestamp del mensaje, en segundos)
+        gp_kernel: match env::var("GP_KERNEL") {
+            Ok(spec) => KernelSpec::parse(&spec).ok_or("GP_KERNEL inválido")?,
+            Err(_) => KernelSpec::Rbf,
+        },
+        gp_time_axis: match env::var("GP_TIME") {
+            Ok(name) => TimeAxis::from_name(&name).ok_or("GP_TIME inválido")?,
+            Err(_) => TimeAxis::TickIndex,
+        },
+        // NORMALIZATION: zscore, ewma[:semivida], median_mad, minmax, rank
+        normalization: match env::var("NORMALIZATION") {
+            Ok(name) => Normalization::from_name(&name).ok_or("NORMALIZATION inválido")?,
+            Err(_) => Normalization::ZScore,
+        },
+        // CLIPPING: recorte por defecto y excepciones, p.ej. "winsor:0.01,ofi=clip:3"
+        clipping,
+        feature_clipping,
+        // TIME_SOURCE: reloj de la velocidad de ticks (receive, sending, entry)
+        time_source: match env::var("TIME_SOURCE") {
+            Ok(name) => TimeSource::from_name(&name).ok_or("TIME_SOURCE inválido")?,
+            Err(_) => TimeSource::Sending,
+        },
+        // VELOCITY: ventana deslizante o decaimiento exponencial ("window:1", "decay:0.5")
+        velocity: match env::var("VELOCITY") {
+            Ok(spec) => RateMode::parse(&spec).ok_or("VELOCITY inválido")?,
+            Err(_) => RateMode::Window(1.0),
+        },
+        // HAWKES_PARAMS: parámetros ajustados con `hawkes`; HAWKES_CONTEXT=1 usa su
+        // intensidad como velocidad de la red bayesiana
+        hawkes: HawkesConfig {
+            params: match env::var("HAWKES_PARAMS") {
+                Ok(path) => Some(HawkesParams::load(&path)?),
+                Err(_) => None,
+            },
+            ..HawkesConfig::default()
+        },
+        hawkes_context: env::var("HAWKES_CONTEXT").is_ok_and(|v| v == "1"),
+        // HMM_MODEL: modelo ajustado con `hmm` (sin él se ajusta sobre los primeros
+        // updates de la sesión)
+        hmm: HmmConfig {
+            model: match env::var("HMM_MODEL") {
+                Ok(path) => Some(GaussianHmm::load(&path)?),
+                Err(_) => None,
+            },
+            ..HmmConfig::default()
+        },
+        // NOISE_SOURCE: gp o kalman; KALMAN: modelo del microprecio ("level", "trend:200")
+        noise_source: match env::var("NOISE_SOURCE") {
+            Ok(name) => NoiseSource::from_name(&name).ok_or("NOISE_SOURCE inválido")?,
+            Err(_) => NoiseSource::Gp,
+        },
+        kalman: match env::var("KALMAN") {
+            Ok(spec) => KalmanConfig::parse(&spec).ok_or("KALMAN inválido")?,
+            Err(_) => KalmanConfig::default(),
+        },
+        bars: bar_spec,
+        ..PipelineConfig::default()
+    };
+
+    // Arquitectura: una entrada por columna del esquema, 12 Hidden, 0.01 LR
+    // La cabeza de salida depende del esquema de etiquetado
+    let mut brain_config = BrainConfig::legacy(schema.len(), 12, 0.01).with_schema(schema.clone());
+    label_scheme.configure(&mut brain_config);
+    let mut thresholds = SignalThresholds::default();
+
+    // TUNED_PARAMS: mejor combinación de `tune` (tuning.json). Cada parámetro puede
+    // fijarse también por separado, con prioridad sobre el archivo: BRAIN_LR,
+    // FEATURE_WINDOW, GP_WINDOW, CONTEXT_THRESHOLD, BUY_ABOVE, SELL_BELOW y
+    // CONTEXT_SOURCE (bayesian, hmm o blend); las capas con BRAIN_HIDDEN
+    if let Ok(path) = env::var("TUNED_PARAMS") {
+        let tuned = TrialParams::load_best(&path)?;
+        info!("Parámetros de {}: {:?}", path, tuned);
+        tuned.apply_to(&mut pipeline_config, &mut brain_config, &mut thresholds);
+    }
+    if let Some(lr) = env_number("BRAIN_LR")? {
+        brain_config.learning_rate = lr;
+    }
+    if let Some(window) = env_number("FEATURE_WINDOW")? {
+        pipeline_config.feature_window = window;
+    }
+    if let Some(window) = env_number("GP_WINDOW")? {
+        pipeline_config.gp_window = window;
+    }
+    if let Some(threshold) = env_number("CONTEXT_THRESHOLD")? {
+        pipeline_config.context_threshold = threshold;
+    }
+    if let Some(buy_above) = env_number("BUY_ABOVE")? {
+        thresholds.buy_above = buy_above;
+    }
+    if let Some(sell_below) = env_number("SELL_BELOW")? {
+        thresholds.sell_below = sell_below;
+    }
+    if let Ok(name) = env::var("CONTEXT_SOURCE") {
+        pipeline_config.context_source =
+            ContextSource::from_name(&name).ok_or("CONTEXT_SOURCE inválido")?;
+    }
+    // BRAIN_HIDDEN: capas ocultas ("32,16"); BRAIN_ACTIVATION: sigmoid, tanh, relu,
+    // gelu, identity; BRAIN_INIT: normal[:std], he, xavier
+    if let Ok(spec) = env::var("BRAIN_HIDDEN") {
+        brain_config.hidden = BrainConfig::parse_hidden(&spec).ok_or("BRAIN_HIDDEN inválido")?;
+    }
+    if let Ok(name) = env::var("BRAIN_ACTIVATION") {
+        brain_config.activation =
+            Activation::from_name(&name).ok_or("BRAIN_ACTIVATION inválido")?;
+    }
+    if let Ok(spec) = env::var("BRAIN_INIT") {
+        brain_config.init = Init::parse(&spec).ok_or("BRAIN_INIT inválido")?;
+    }
+    // OPTIMIZER: sgd[:momentum], adam, rmsprop; LR_SCHEDULE: constant, step:1000:0.5,
+    // exp:0.9999, inverse:0.001, cosine:5000:0.0001; WEIGHT_DECAY (L2); CLIP_NORM
+    if let Ok(spec) = env::var("OPTIMIZER") {
+        brain_config.optimizer = Optimizer::parse(&spec).ok_or("OPTIMIZER inválido")?;
+    }
+    if let Ok(spec) = env::var("LR_SCHEDULE") {
+        brain_config.schedule = LrSchedule::parse(&spec).ok_or("LR_SCHEDULE inválido")?;
+    }
+    if let Ok(value) = env::var("WEIGHT_DECAY") {
+        brain_config.weight_decay = value
+            .parse()
+            .ok()
+            .filter(|v: &f64| *v >= 0.0)
+            .ok_or("WEIGHT_DECAY inválido")?;
+    }
+    if let Ok(value) = env::var("CLIP_NORM") {
+        brain_config.clip_norm = Some(
+            value
+                .parse()
+                .ok()
+                .filter(|v: &f64| *v > 0.0)
+                .ok_or("CLIP_NORM inválido")?,
+        );
+    }
+    let mut regime_store = RegimeStore::load(&regimes_path)?;
+    let initial_edges = regime_store
+        .get(symbol)
+        .cloned()
+        .unwrap_or_else(BayesianNetwork::default_edges);
+    // Libro, filtro Gaussiano, red de contexto, regímenes y FeatureCollector
+    let mut pipeline = MarketPipeline::new(&pipeline_config, initial_edges);
+    // Conteos de la red aprendidos en sesiones anteriores (con los mismos cortes)
+    if let Some(counts) = regime_store.counts(symbol).cloned() {
+        if let Err(reason) = pipeline.bayes_net.restore_counts(counts) {
+            warn!(
+                "Conteos de la red en {} ignorados: {}",
+                regimes_path, reason
+            );
+        }
+    }
+
+    // Grabación opcional de los mensajes de market data (entrada de `train`)
+    let mut recorder = match env::var("RECORD_PATH") {
+        Ok(path) => Some(MarketRecorder::open(&path)?),
+        Err(_) => None,
+    };
+
+    // Registro de decisiones (JSON lines + últimas 10.000 en memoria)
+    let decisions_path =
+        env::var("DECISIONS_PATH").unwrap_or_else(|_| "decisions.jsonl".to_string());
+    let mut decision_log = DecisionLog::new(10_000).with_file(&decisions_path)?;
+
+    // Mini-batch de pares (features, target) resueltos; 1 = aprendizaje por muestra
+    let batch_size: usize = env::var("BRAIN_BATCH")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1);
 
-    let mut g_filter = GaussianFilter::new(20, 1.5, 1.0);
-    let bayes_net = BayesianNetwork::new(0.45);
+    // Horizontes de predicción: "5u" (updates), "500ms"/"5s" (reloj), "1000v" (volumen).
+    // Una cabeza por horizonte; la primera es la que emite el veredicto.
+    let horizons = match env::var("HORIZONS") {
+        Ok(list) => Horizon::parse_list(&list).ok_or("HORIZONS inválido")?,
+        Err(_) => vec![Horizon::Updates(5)],
+    };
+    // Recalibración de la probabilidad (none, platt, isotonic)
+    let calibrator = match env::var("CALIBRATION") {
+        Ok(name) => Calibrator::from_name(&name).ok_or("CALIBRATION inválido")?,
+        Err(_) => Calibrator::None,
+    };
 
-    let mut prediction_queue = VecDeque::new();
-    let mut last_velocity_calc = Instant::now();
-    let mut tick_count = 0.0;
-    let mut current_velocity = 0.0;
-    let mut msg_count: u64 = 0;
+    // Deriva del error (DRIFT_DETECTOR: adwin, page_hinkley, ddm; sin definir =
+    // desactivado) y de las features (ADWIN por columna).
+    // Acciones: log (por defecto), inflate, boost_lr, rollback
+    let drift_detector = match env::var("DRIFT_DETECTOR") {
+        Ok(name) => {
+            detector_from_name(&name).ok_or("DRIFT_DETECTOR inválido")?;
+            Some(name)
+        }
+        Err(_) => None,
+    };
+    let drift_action = match env::var("DRIFT_ACTION") {
+        Ok(name) => DriftAction::from_name(&name).ok_or("DRIFT_ACTION inválido")?,
+        Err(_) => DriftAction::Log,
+    };
+    let feature_drift_action = match env::var("FEATURE_DRIFT_ACTION") {
+        Ok(name) => DriftAction::from_name(&name).ok_or("FEATURE_DRIFT_ACTION inválido")?,
+        Err(_) => DriftAction::Log,
+    };
+    let mut feature_drift = FeatureDriftMonitor::new(0.002);
+    // mid: nivel de precio, no estacionario
+    feature_drift.ignored = schema.index_of("mid").into_iter().collect();
+
+    // Modelos acompañantes de la red (ENSEMBLE=logistic) y su combinación
+    // (ENSEMBLE_COMBINER: inverse_variance, stacking, mean)
+    let ensemble_members: Vec<String> = env::var("ENSEMBLE")
+        .unwrap_or_default()
+        .split(',')
+        .map(|m| m.trim().to_string())
+        .filter(|m| !m.is_empty())
+        .collect();
+    for name in &ensemble_members {
+        if Ensemble::member_from_name(name, brain_config.input_dim).is_none() {
+            return Err(format!("miembro de ENSEMBLE desconocido: {}", name).into());
+        }
+    }
+    let combiner_name =
+        env::var("ENSEMBLE_COMBINER").unwrap_or_else(|_| "inverse_variance".to_string());
+    Combiner::from_name(&combiner_name, 1).ok_or("ENSEMBLE_COMBINER inválido")?;
+    let build_ensemble = || -> Option<Ensemble> {
+        let members: Vec<_> = ensemble_members
+            .iter()
+            .filter_map(|name| Ensemble::member_from_name(name, brain_config.input_dim))
+            .collect();
+        if members.is_empty() {
+            return None;
+        }
+        let combiner = Combiner::from_name(&combiner_name, members.len() + 1)?;
+        Some(Ensemble::new(members, combiner))
+    };
+
+    let mut heads: Vec<HorizonHead<ContextSample>> = horizons
+        .iter()
+        .map(|&h| {
+            let mut head = HorizonHead::new(
+                h,
+                label_scheme,
+                pipeline_config.features.tick_size,
+                brain_config.clone(),
+                batch_size,
+            )?
+            .with_calibrator(calibrator.clone());
+            if let Some(ensemble) = build_ensemble() {
+                head = head.with_ensemble(ensemble);
+            }
+            Ok(
+                match drift_detector.as_deref().and_then(detector_from_name) {
+                    Some(detector) => head.with_drift(detector, drift_action),
+                    None => head,
+                },
+            )
+        })
+        .collect::<Result<_, String>>()?;
+
+    // Métricas de calibración y checkpoint cada METRICS_EVERY mensajes.
+    // Al arrancar se carga el checkpoint de cada cabeza si existe y es compatible
+    // (p.ej. el generado por `train`).
+    let checkpoint_path = env::var("CHECKPOINT_PATH").unwrap_or_else(|_| "brain.json".to_string());
+    let n_heads = heads.len();
+    for head in heads.iter_mut() {
+        let path = head_checkpoint_path(&checkpoint_path, &head.horizon.name(), n_heads);
+        if !Path::new(&path).exists() {
+            continue;
+        }
+        match Checkpoint::load(&path) {
+            Ok(ckpt) => match ckpt.check_compatible(label_scheme, head.horizon, &schema) {
+                Ok(()) => {
+                    info!(
+                        "🧠 Checkpoint {} cargado (guardado {})",
+                        path, ckpt.saved_at
+                    );
+                    if let Some(saved) = ckpt.brain.as_ref().map(|b| b.config()) {
+                        let optimization = |c: &BrainConfig| {
+                            (c.optimizer, c.schedule, c.weight_decay, c.clip_norm)
+                        };
+                        if optimization(saved) != optimization(&brain_config) {
+                            warn!(
+                                "El checkpoint {} usa otro optimizador ({:?}, {:?}); se mantiene el guardado",
+                                path, saved.optimizer, saved.schedule
+                            );
+                        }
+                    }
+                    head.restore(ckpt);
+                }
+                Err(reason) => warn!(
+                    "Checkpoint {} incompatible con la configuración ({}), se ignora",
+                    path, reason
+                ),
+            },
+            Err(e) => warn!("No se pudo leer el checkpoint {}: {}", path, e),
+        }
+    }
+    let metrics_every: u64 = env::var("METRICS_EVERY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1000);
 
     // 2. Conexión FIX
     let host = env::var("FIX_HOST")?;
@@ -67,7 +415,25 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // --- SUSCRIPCIÓN ---
     let mut md_buffer = Vec::new();
-    engine.build_market_data_request(&mut md_buffer, &sender_id, &target_id, seq_num, "1");
+    // MDEntryTypes suscritos: bid y ask; MD_ENTRY_TYPES=0,1,2 añade las operaciones
+    // en los brokers con cinta (el resto rechaza el 2 con 35=Y)
+    let md_entry_types: Vec<String> = env::var("MD_ENTRY_TYPES")
+        .unwrap_or_else(|_| "0,1".to_string())
+        .split(',')
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+    if pipeline_config.features.trade_window > 0.0 && !md_entry_types.iter().any(|t| t == "2") {
+        warn!("Features de operaciones sin suscripción a 269=2: añade el 2 a MD_ENTRY_TYPES");
+    }
+    engine.build_market_data_request(
+        &mut md_buffer,
+        &sender_id,
+        &target_id,
+        seq_num,
+        symbol,
+        &md_entry_types,
+    );
     stream.write_all(&md_buffer).await?;
     info!("📡 Suscripción enviada. Procesando profundidad de libro...");
     seq_num += 1;
@@ -94,72 +460,143 @@ async fn main() -> Result<(), Box<dyn Error>> {
                             if content.is_empty() { continue; }
                             let msg = content.replace('\x01', "|");
 
-                            if msg.contains("|35=W|") || msg.contains("|35=X|") {
-                                let entries: Vec<&str> = msg.split("|279=").collect();
+                            if msg.contains("|35=Y|") {
+                                warn!(
+                                    "Suscripción rechazada (35=Y): {}. Quita el 2 de MD_ENTRY_TYPES si el broker no publica operaciones.",
+                                    msg
+                                );
+                                continue;
+                            }
 
-                                for entry in entries.iter().skip(1) {
-                                    let fragment = format!("|279={}", entry);
-                                    let side = if extract_tag(&fragment, "269").unwrap_or(-1.0) == 0.0 { '0' } else { '1' };
-                                    let price = extract_tag(&fragment, "270").unwrap_or(0.0);
-                                    let volume = extract_tag(&fragment, "271").unwrap_or(0.0);
-                                    order_book.update('1', side, price, volume);
-                                    tick_count += 1.0;
+                            let now = Utc::now().timestamp_micros() as f64 / 1e6;
+                            if let Some(rec) = recorder.as_mut() {
+                                if msg.contains("|35=W|") || msg.contains("|35=X|") {
+                                    if let Err(e) = rec.record(now, &msg) {
+                                        warn!("No se pudo grabar el mensaje: {}", e);
+                                    }
                                 }
+                            }
+
+                            if let Some(snap) = pipeline.on_message(&msg, now) {
+                                let msg_count = pipeline.msg_count;
+                                if snap.regimes_refit || msg_count.is_multiple_of(metrics_every) {
+                                    regime_store.set(symbol, pipeline.regimes.edges());
+                                    regime_store.set_counts(symbol, pipeline.bayes_net.counts());
+                                    if let Err(e) = regime_store.save(&regimes_path) {
+                                        warn!("No se pudieron guardar los regímenes: {}", e);
+                                    }
+                                }
+
+                                for idx in feature_drift.update(&snap.raw_features) {
+                                    let source = format!("feature {}", schema.name(idx));
+                                    for head in heads.iter_mut() {
+                                        head.handle_drift(&source, feature_drift_action);
+                                    }
+                                }
+                                let norm_v = &snap.features;
+                                let (mid, spread, intensity, noise, context) = (snap.mid, snap.spread, snap.intensity, snap.noise, snap.context);
+                                let depth = &snap.depth;
+
+                                if !norm_v.is_empty() {
+                                    // Etiquetado: el nuevo precio resuelve las entradas pendientes de cada horizonte
+                                    for (i, head) in heads.iter_mut().enumerate() {
+                                        for resolved in head.update(mid, snap.time, snap.volume) {
+                                            // El contexto aprende del horizonte principal
+                                            if i == 0 {
+                                                let mut old_ctx = resolved.payload;
+                                                old_ctx.resolve(resolved.entry, resolved.exit, pipeline_config.features.tick_size);
+                                                pipeline.observe_context(&old_ctx);
+                                            }
+                                        }
+                                        head.push(norm_v.clone(), snap.context_sample, mid, snap.time);
+                                    }
 
-                                if let Some(mid) = order_book.get_mid_price() {
-                                    msg_count += 1;
-                                    g_filter.add_price(mid);
-
-                                    // 1. Obtener métricas de filtros
-                                    let spread = (order_book.get_best_ask().unwrap_or(mid) - order_book.get_best_bid().unwrap_or(mid)).abs() * 100000.0;
-                                    let imbalance = order_book.get_imbalance();
-                                    let intensity = order_book.get_book_intensity();
-                                    let noise = g_filter.compute_uncertainty();
-                                    let context = bayes_net.compute_context_score(spread, current_velocity, imbalance, intensity);
-
-                                    // 2. Velocidad de Ticks
-                                    let elapsed = last_velocity_calc.elapsed().as_secs_f64();
-                                    if elapsed >= 1.0 {
-                                        current_velocity = tick_count / elapsed;
-                                        tick_count = 0.0;
-                                        last_velocity_calc = Instant::now();
+                                    if msg_count.is_multiple_of(metrics_every) {
+                                        if let Some(rec) = recorder.as_mut() {
+                                            if let Err(e) = rec.flush() {
+                                                warn!("No se pudo volcar la grabación: {}", e);
+                                            }
+                                        }
+                                        for head in heads.iter_mut() {
+                                            if head.metrics.is_empty() { continue; }
+                                            let r = head.metrics.report(10);
+                                            info!("📊 SALUD [{}]: n={} | LogLoss: {:.4} | Brier: {:.4} | AUC: {} | ECE: {:.3}",
+                                                  head.horizon.name(), r.samples, r.log_loss, r.brier,
+                                                  r.auc.map_or("-".to_string(), |a| format!("{:.3}", a)), r.ece);
+                                            let path = head_checkpoint_path(&checkpoint_path, &head.horizon.name(), n_heads);
+                                            if let Err(e) = head.snapshot().save(&path) {
+                                                warn!("No se pudo guardar el checkpoint {}: {}", path, e);
+                                            }
+                                        }
                                     }
 
-                                    // 3. Empaquetar características (Incluye profundidad de 3 niveles)
-                                    collector.push_features(&order_book, current_velocity, noise, context);
-                                    let norm_v = collector.get_standardized_vector();
-
-                                    if !norm_v.is_empty() {
-                                        prediction_queue.push_back((norm_v.clone(), mid));
-
-                                        if prediction_queue.len() > 5 {
-                                            if let Some((old_features, old_price)) = prediction_queue.pop_front() {
-                                                // Entrenamiento Online
-                                                let target = if mid > old_price { 1.0 } else { 0.0 };
-                                                brain.train(&old_features, target);
-
-                                                if msg_count % 5 == 0 {
-                                                    // Predicción Bayesiana con Incertidumbre Epistémica
-                                                    let (prob, brain_uncertainty) = brain.predict_with_uncertainty(&norm_v);
-
-                                                    // Lógica de Veredicto
-                                                    let is_safe = noise < 0.70;
-                                                    let is_sane = bayes_net.is_context_favorable(context);
-                                                    let brain_conflicts = brain_uncertainty > 0.85;
-
-                                                    let signal = if is_safe && is_sane && !brain_conflicts {
-                                                        if prob > 0.75 { "🚀 BUY" }
-                                                        else if prob < 0.25 { "📉 SELL" }
-                                                        else { "⏳ WAIT" }
-                                                    } else {
-                                                        "🚫 BLOCKED"
-                                                    };
-
-                                                    info!("P: {:.1}% | B-UNCER: {:.2} | RUIDO: {:.2} | CTXT: {:.2} | [{}]",
-                                                          prob * 100.0, brain_uncertainty, noise, context, signal);
+                                    if heads[0].trained_samples > 0 && msg_count.is_multiple_of(5) {
+                                        // Predicción Bayesiana con Incertidumbre Epistémica
+                                        let predictions: Vec<_> = heads.iter().map(|h| h.predict(norm_v)).collect();
+                                        let (prob, brain_uncertainty) = (predictions[0].prob, predictions[0].uncertainty);
+
+                                        // Lógica de Veredicto
+                                        let inputs = DecisionInputs {
+                                            mid,
+                                            spread,
+                                            velocity: snap.velocity,
+                                            intensity,
+                                            depth: depth.clone(),
+                                            noise,
+                                            context,
+                                            columns: schema.columns.clone(),
+                                            raw_features: snap.raw_features.clone(),
+                                            normalized: norm_v.to_vec(),
+                                        };
+                                        let mut decision = Decision::evaluate(inputs, prob, brain_uncertainty, pipeline.bayes_net.context_threshold, &thresholds);
+                                        if let Some(disagreement) = predictions[0].disagreement {
+                                            decision = decision.with_gate(GateCheck {
+                                                gate: "disagreement",
+                                                value: disagreement,
+                                                threshold: thresholds.max_disagreement,
+                                                passed: disagreement <= thresholds.max_disagreement,
+                                            });
+                                        }
+                                        decision.horizons = predictions;
+
+                                        info!("P: {:.1}% | B-UNCER: {:.2} | RUIDO: {:.2} | CTXT: {:.2} | [{}]",
+                                              prob * 100.0, brain_uncertainty, noise, context, decision.verdict.label());
+
+                                        if decision.horizons.len() > 1 {
+                                            let detail: Vec<String> = decision.horizons.iter()
+                                                .map(|p| format!("{}={:.1}%±{:.2}", p.horizon, p.prob * 100.0, p.uncertainty))
+                                                .collect();
+                                            info!("   ↳ Horizontes: {}", detail.join(" "));
+                                        }
+
+                                        if decision.verdict == Verdict::Blocked {
+                                            info!("   ↳ Filtros fallidos: {}", decision.failed_gates().join(", "));
+                                        }
+
+                                        // Se explica con el modelo que puntuó: la red, el HMM o ambos (mezcla)
+                                        if !pipeline.bayes_net.is_context_favorable(context) {
+                                            if snap.context_source != ContextSource::Hmm {
+                                                let why = pipeline.bayes_net.explain_context(spread, snap.context_sample.velocity, depth, intensity);
+                                                let detail: Vec<String> = why.contributions.iter()
+                                                    .map(|c| format!("{}={:?}({:+.2})", c.node, c.state, c.contribution))
+                                                    .collect();
+                                                info!("   ↳ CTXT rechazado (base {:.2}): {}", why.base_rate, detail.join(" "));
+                                                decision.context_explanation = Some(why);
+                                            }
+                                            if snap.context_source != ContextSource::Bayesian {
+                                                if let Some(why) = pipeline.regime_detector.as_ref().and_then(|d| d.explain()) {
+                                                    let detail: Vec<String> = why.posterior.iter().zip(&why.favorable_rates).enumerate()
+                                                        .map(|(s, (p, rate))| format!("s{}={:.2}×{:.2}", s, p, rate))
+                                                        .collect();
+                                                    info!("   ↳ CTXT rechazado por el HMM ({:.2}, régimen {}): {}", why.score, why.map_state, detail.join(" "));
+                                                    decision.regime_explanation = Some(why);
                                                 }
                                             }
                                         }
+
+                                        if let Err(e) = decision_log.record(decision) {
+                                            warn!("No se pudo registrar la decisión: {}", e);
+                                        }
                                     }
                                 }
                             }
@@ -170,16 +607,28 @@ async fn main() -> Result<(), Box<dyn Error>> {
             }
         }
     }
+    if let Some(rec) = recorder.as_mut() {
+        rec.flush()?;
+    }
     Ok(())
 }
 
-fn extract_tag(msg: &str, tag: &str) -> Option<f64> {
-    let pattern = format!("|{}=", tag);
-    if let Some(start) = msg.find(&pattern) {
-        let val_start = start + pattern.len();
-        let end_offset = msg[val_start..].find('|').unwrap_or(msg[val_start..].len());
-        let val_str = &msg[val_start..val_start + end_offset];
-        return val_str.parse::<f64>().ok();
+/// Variable de entorno numérica: None si no está definida, error si no es válida
+fn env_number<T: FromStr>(name: &str) -> Result<Option<T>, String> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("{} inválido: {}", name, value)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Con varias cabezas cada una guarda en "<base>.<horizonte>.json"
+fn head_checkpoint_path(base: &str, horizon: &str, n_heads: usize) -> String {
+    if n_heads == 1 {
+        return base.to_string();
     }
-    None
+    let stem = base.strip_suffix(".json").unwrap_or(base);
+    format!("{}.{}.json", stem, horizon)
 }
@@ -1,6 +1,8 @@
 use ndarray::Array1;
+use serde::{Deserialize, Serialize};
 use std::f64::consts::E;
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogisticModel {
     pub weights: Array1<f64>,
     pub bias: f64,
@@ -34,7 +36,7 @@ impl LogisticModel {
     pub fn train(&mut self, features: &Array1<f64>, target: f64) -> f64 {
         // 1. Obtener predicción actual
         let prediction = self.predict(features);
-        
+
         // 2. Calcular el error (Gradiente)
         let error = prediction - target;
 
@@ -42,7 +44,7 @@ impl LogisticModel {
         // Usamos la derivada de la función de pérdida log-likelihood
         let gradient_w = features.mapv(|x| x * error);
         self.weights = &self.weights - &(gradient_w * self.learning_rate);
-        
+
         // 4. Actualizar bias
         self.bias -= self.learning_rate * error;
 
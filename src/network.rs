@@ -25,4 +25,3 @@ pub async fn connect_to_broker(host: &str, port: &str) -> Result<TcpStream, Box<
         }
     }
 }
-
@@ -0,0 +1,371 @@
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+
+/// Regla de actualización de los parámetros
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Optimizer {
+    /// SGD con momentum (momentum = 0.0 es SGD plano)
+    Sgd {
+        momentum: f64,
+    },
+    Adam {
+        beta1: f64,
+        beta2: f64,
+        eps: f64,
+    },
+    RmsProp {
+        decay: f64,
+        eps: f64,
+    },
+}
+
+impl Optimizer {
+    pub fn sgd() -> Self {
+        Optimizer::Sgd { momentum: 0.0 }
+    }
+
+    pub fn adam() -> Self {
+        Optimizer::Adam {
+            beta1: 0.9,
+            beta2: 0.999,
+            eps: 1e-8,
+        }
+    }
+
+    pub fn rms_prop() -> Self {
+        Optimizer::RmsProp {
+            decay: 0.9,
+            eps: 1e-8,
+        }
+    }
+
+    /// "sgd", "sgd:0.9" (momentum), "adam", "rmsprop"
+    pub fn parse(spec: &str) -> Option<Self> {
+        match spec.split_once(':') {
+            Some(("sgd", momentum)) => {
+                let momentum: f64 = momentum.parse().ok()?;
+                (0.0..1.0)
+                    .contains(&momentum)
+                    .then_some(Optimizer::Sgd { momentum })
+            }
+            Some(_) => None,
+            None => match spec {
+                "sgd" => Some(Optimizer::sgd()),
+                "adam" => Some(Optimizer::adam()),
+                "rmsprop" => Some(Optimizer::rms_prop()),
+                _ => None,
+            },
+        }
+    }
+
+    /// Aplica un paso sobre `param`. `step` empieza en 1 (corrección de sesgo de Adam).
+    pub fn apply(
+        &self,
+        param: &mut Array2<f64>,
+        grad: &Array2<f64>,
+        state: &mut ParamState,
+        lr: f64,
+        step: u64,
+    ) {
+        state.ensure_shape(param);
+        match *self {
+            Optimizer::Sgd { momentum } => {
+                if momentum == 0.0 {
+                    param.scaled_add(-lr, grad);
+                } else {
+                    // v = momentum * v + g ;  p -= lr * v
+                    state.m.mapv_inplace(|v| v * momentum);
+                    state.m += grad;
+                    param.scaled_add(-lr, &state.m);
+                }
+            }
+            Optimizer::Adam { beta1, beta2, eps } => {
+                state
+                    .m
+                    .zip_mut_with(grad, |m, &g| *m = beta1 * *m + (1.0 - beta1) * g);
+                state
+                    .v
+                    .zip_mut_with(grad, |v, &g| *v = beta2 * *v + (1.0 - beta2) * g * g);
+                let bc1 = 1.0 - beta1.powi(step as i32);
+                let bc2 = 1.0 - beta2.powi(step as i32);
+                ndarray::Zip::from(param)
+                    .and(&state.m)
+                    .and(&state.v)
+                    .for_each(|p, &m, &v| *p -= lr * (m / bc1) / ((v / bc2).sqrt() + eps));
+            }
+            Optimizer::RmsProp { decay, eps } => {
+                state
+                    .v
+                    .zip_mut_with(grad, |v, &g| *v = decay * *v + (1.0 - decay) * g * g);
+                ndarray::Zip::from(param)
+                    .and(grad)
+                    .and(&state.v)
+                    .for_each(|p, &g, &v| *p -= lr * g / (v.sqrt() + eps));
+            }
+        }
+    }
+}
+
+/// Momentos acumulados del optimizador para un tensor de parámetros
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ParamState {
+    m: Array2<f64>,
+    v: Array2<f64>,
+}
+
+impl ParamState {
+    fn ensure_shape(&mut self, param: &Array2<f64>) {
+        if self.m.raw_dim() != param.raw_dim() {
+            self.m = Array2::zeros(param.raw_dim());
+            self.v = Array2::zeros(param.raw_dim());
+        }
+    }
+
+    /// Olvida los momentos acumulados (p.ej. tras un cambio de régimen)
+    pub fn reset(&mut self) {
+        self.m.fill(0.0);
+        self.v.fill(0.0);
+    }
+}
+
+/// Calendario de la tasa de aprendizaje en función del paso
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LrSchedule {
+    Constant,
+    /// lr * gamma^(step / every)
+    Step {
+        every: u64,
+        gamma: f64,
+    },
+    /// lr * gamma^step
+    Exponential {
+        gamma: f64,
+    },
+    /// lr / (1 + decay * step)
+    InverseTime {
+        decay: f64,
+    },
+    /// Coseno con reinicios cada `period` pasos, entre lr y min_lr
+    Cosine {
+        period: u64,
+        min_lr: f64,
+    },
+}
+
+impl LrSchedule {
+    /// "constant", "step:1000:0.5", "exp:0.9999", "inverse:0.001", "cosine:5000:0.0001"
+    pub fn parse(spec: &str) -> Option<Self> {
+        let mut parts = spec.split(':');
+        let name = parts.next()?;
+        let params: Vec<&str> = parts.collect();
+        let float = |i: usize| {
+            params
+                .get(i)?
+                .parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite() && *v >= 0.0)
+        };
+        let schedule = match (name, params.len()) {
+            ("constant", 0) => LrSchedule::Constant,
+            ("step", 2) => LrSchedule::Step {
+                every: params[0].parse().ok()?,
+                gamma: float(1)?,
+            },
+            ("exp", 1) => LrSchedule::Exponential { gamma: float(0)? },
+            ("inverse", 1) => LrSchedule::InverseTime { decay: float(0)? },
+            ("cosine", 2) => LrSchedule::Cosine {
+                period: params[0].parse().ok()?,
+                min_lr: float(1)?,
+            },
+            _ => return None,
+        };
+        Some(schedule)
+    }
+
+    pub fn lr(&self, base: f64, step: u64) -> f64 {
+        match *self {
+            LrSchedule::Constant => base,
+            LrSchedule::Step { every, gamma } => base * gamma.powi((step / every.max(1)) as i32),
+            LrSchedule::Exponential { gamma } => base * gamma.powf(step as f64),
+            LrSchedule::InverseTime { decay } => base / (1.0 + decay * step as f64),
+            LrSchedule::Cosine { period, min_lr } => {
+                let t = (step % period.max(1)) as f64 / period.max(1) as f64;
+                min_lr + 0.5 * (base - min_lr) * (1.0 + (std::f64::consts::PI * t).cos())
+            }
+        }
+    }
+}
+
+/// Recorta los gradientes por norma global: si ||g|| > max_norm se reescalan.
+/// Devuelve la norma original.
+pub fn clip_global_norm(grads: &mut [&mut Array2<f64>], max_norm: f64) -> f64 {
+    let norm = grads
+        .iter()
+        .map(|g| g.iter().map(|x| x * x).sum::<f64>())
+        .sum::<f64>()
+        .sqrt();
+    if norm > max_norm && norm > 0.0 {
+        let scale = max_norm / norm;
+        for g in grads.iter_mut() {
+            g.mapv_inplace(|x| x * scale);
+        }
+    }
+    norm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    /// Aplica `grads` en orden sobre un parámetro escalar inicializado a 1
+    fn run(optimizer: Optimizer, grads: &[f64], lr: f64) -> f64 {
+        let mut param = array![[1.0]];
+        let mut state = ParamState::default();
+        for (step, &g) in grads.iter().enumerate() {
+            optimizer.apply(&mut param, &array![[g]], &mut state, lr, step as u64 + 1);
+        }
+        param[[0, 0]]
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn sgd_and_momentum_known_steps() {
+        assert!(close(run(Optimizer::sgd(), &[0.5], 0.1), 0.95));
+        // v = 0.5, luego 0.9 · 0.5 + 0.5 = 0.95
+        let momentum = Optimizer::Sgd { momentum: 0.9 };
+        assert!(close(run(momentum, &[0.5, 0.5], 0.1), 1.0 - 0.05 - 0.095));
+    }
+
+    #[test]
+    fn adam_bias_correction_known_steps() {
+        // Paso 1: m̂ = g y v̂ = g², el paso es lr · signo(g)
+        assert!((run(Optimizer::adam(), &[0.5], 0.1) - 0.9).abs() < 1e-7);
+        // Paso 2: m̂ = -0.005 / 0.19, v̂ = 0.00049975 / 0.001999 = 0.25
+        let expected = 0.9 + 0.1 * (0.005 / 0.19) / 0.5;
+        assert!((run(Optimizer::adam(), &[0.5, -0.5], 0.1) - expected).abs() < 1e-7);
+    }
+
+    #[test]
+    fn rms_prop_known_step() {
+        // v = 0.1 · 0.25: el paso es lr · g / sqrt(v)
+        let expected = 1.0 - 0.1 * 0.5 / 0.025f64.sqrt();
+        assert!((run(Optimizer::rms_prop(), &[0.5], 0.1) - expected).abs() < 1e-7);
+    }
+
+    #[test]
+    fn state_resets_and_follows_the_shape() {
+        let mut state = ParamState::default();
+        let mut param = array![[1.0, 2.0]];
+        let momentum = Optimizer::Sgd { momentum: 0.9 };
+        momentum.apply(&mut param, &array![[1.0, 1.0]], &mut state, 0.1, 1);
+        assert_eq!(state.m, array![[1.0, 1.0]]);
+        state.reset();
+        assert_eq!(state.m, array![[0.0, 0.0]]);
+        // Un cambio de forma reinicia los momentos
+        let mut column = array![[1.0], [1.0], [1.0]];
+        momentum.apply(
+            &mut column,
+            &array![[1.0], [0.0], [2.0]],
+            &mut state,
+            1.0,
+            1,
+        );
+        assert_eq!(column, array![[0.0], [1.0], [-1.0]]);
+    }
+
+    #[test]
+    fn schedule_values() {
+        let lr = |schedule: LrSchedule, step| schedule.lr(0.1, step);
+        assert_eq!(lr(LrSchedule::Constant, 1000), 0.1);
+
+        let step = LrSchedule::Step {
+            every: 10,
+            gamma: 0.5,
+        };
+        assert_eq!(
+            [lr(step, 0), lr(step, 9), lr(step, 10), lr(step, 25)],
+            [0.1, 0.1, 0.05, 0.025]
+        );
+
+        let exponential = LrSchedule::Exponential { gamma: 0.9 };
+        assert!(close(lr(exponential, 0), 0.1) && close(lr(exponential, 2), 0.081));
+
+        let inverse = LrSchedule::InverseTime { decay: 0.5 };
+        assert!(close(lr(inverse, 0), 0.1) && close(lr(inverse, 2), 0.05));
+
+        // Máximo al inicio de cada periodo, punto medio a mitad
+        let cosine = LrSchedule::Cosine {
+            period: 10,
+            min_lr: 0.01,
+        };
+        assert!(close(lr(cosine, 0), 0.1));
+        assert!(close(lr(cosine, 5), 0.055));
+        assert!(close(lr(cosine, 10), 0.1));
+        assert!(lr(cosine, 9) < 0.013);
+    }
+
+    #[test]
+    fn clipping_rescales_to_the_max_norm() {
+        let (mut a, mut b) = (array![[3.0]], array![[0.0, 4.0]]);
+        let norm = clip_global_norm(&mut [&mut a, &mut b], 1.0);
+        assert_eq!(norm, 5.0);
+        assert!(close(a[[0, 0]], 0.6) && close(b[[0, 1]], 0.8));
+
+        // Por debajo del máximo no se toca
+        let norm = clip_global_norm(&mut [&mut a, &mut b], 2.0);
+        assert!(close(norm, 1.0));
+        assert!(close(a[[0, 0]], 0.6));
+    }
+
+    #[test]
+    fn parse_optimizers_and_reject_bad_momentum() {
+        assert_eq!(Optimizer::parse("adam"), Some(Optimizer::adam()));
+        assert_eq!(Optimizer::parse("rmsprop"), Some(Optimizer::rms_prop()));
+        assert_eq!(
+            Optimizer::parse("sgd:0.9"),
+            Some(Optimizer::Sgd { momentum: 0.9 })
+        );
+        for bad in ["sgd:1", "sgd:-0.1", "sgd:x", "adam:0.9", "lbfgs"] {
+            assert_eq!(Optimizer::parse(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn parse_schedules_and_reject_bad_parameters() {
+        assert_eq!(LrSchedule::parse("constant"), Some(LrSchedule::Constant));
+        assert_eq!(
+            LrSchedule::parse("step:100:0.5"),
+            Some(LrSchedule::Step {
+                every: 100,
+                gamma: 0.5
+            })
+        );
+        assert_eq!(
+            LrSchedule::parse("cosine:5000:0.0001"),
+            Some(LrSchedule::Cosine {
+                period: 5000,
+                min_lr: 0.0001
+            })
+        );
+        assert_eq!(
+            LrSchedule::parse("inverse:0.01"),
+            Some(LrSchedule::InverseTime { decay: 0.01 })
+        );
+        for bad in [
+            "constant:1",
+            "step:100",
+            "step:-1:0.5",
+            "exp:-0.5",
+            "exp:inf",
+            "inverse:x",
+            "cosine:10",
+            "linear:1",
+        ] {
+            assert_eq!(LrSchedule::parse(bad), None, "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,481 @@
+use crate::bars::{Bar, BarAggregator, BarSpec};
+use crate::bayesian::{depth_imbalance, BayesianNetwork, ContextSample};
+use crate::features::{FeatureCollector, FeatureConfig, Signals};
+use crate::gaussian::{GaussianFilter, TimeAxis};
+use crate::hawkes::{HawkesConfig, HawkesModel};
+use crate::hmm::{HmmConfig, RegimeDetector};
+use crate::kalman::{KalmanConfig, KalmanEstimate, KalmanFilter};
+use crate::kernel::KernelSpec;
+use crate::regimes::{RegimeEdges, SymbolRegimes};
+use crate::scaling::{Clipping, Normalization};
+use crate::state::OrderBook;
+use crate::trades::{Aggressor, Trade, TradeTape};
+use crate::velocity::{
+    parse_entry_time, parse_utc_timestamp, tag_value, EventKind, RateMode, TickVelocity,
+    TimeSource, VelocityRates,
+};
+use log::warn;
+use ndarray::Array1;
+use serde::{Deserialize, Serialize};
+
+/// Origen de la puntuación de contexto que filtra las entradas
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextSource {
+    /// Red bayesiana sobre spread, velocidad, intensidad e imbalance discretizados
+    Bayesian,
+    /// Posteriores del HMM ponderadas por la tasa de acierto de cada régimen
+    Hmm,
+    /// Media de las dos
+    Blend,
+}
+
+impl ContextSource {
+    /// "bayesian", "hmm", "blend"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bayesian" => Some(ContextSource::Bayesian),
+            "hmm" => Some(ContextSource::Hmm),
+            "blend" => Some(ContextSource::Blend),
+            _ => None,
+        }
+    }
+}
+
+/// Origen de la señal de ruido que filtra las entradas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseSource {
+    /// Desviación del precio frente a la ventana del proceso Gaussiano
+    Gp,
+    /// Desviación de la innovación del filtro de Kalman
+    Kalman,
+}
+
+impl NoiseSource {
+    /// "gp", "kalman"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gp" => Some(NoiseSource::Gp),
+            "kalman" => Some(NoiseSource::Kalman),
+            _ => None,
+        }
+    }
+}
+
+/// Parámetros del cálculo de features (compartidos por el motor en vivo,
+/// el entrenamiento offline y el backtest)
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    /// Ventana de normalización del FeatureCollector
+    pub feature_window: usize,
+    /// Ventana, kernel y eje temporal del proceso Gaussiano
+    pub gp_window: usize,
+    pub gp_length_scale: f64,
+    pub gp_sigma_f: f64,
+    pub gp_kernel: KernelSpec,
+    pub gp_time_axis: TimeAxis,
+    pub context_threshold: f64,
+    pub regime_bins: usize,
+    pub regime_window: usize,
+    /// Columnas del vector de features
+    pub features: FeatureConfig,
+    /// Escalado y recorte de cada columna (con excepciones por nombre)
+    pub normalization: Normalization,
+    pub clipping: Clipping,
+    pub feature_clipping: Vec<(String, Clipping)>,
+    /// Reloj de los eventos para la velocidad de ticks y forma de contarlos
+    pub time_source: TimeSource,
+    pub velocity: RateMode,
+    /// Modelo de Hawkes de la actividad del libro (activo si lo piden las features
+    /// o `hawkes_context`)
+    pub hawkes: HawkesConfig,
+    /// La red bayesiana y los regímenes usan la intensidad de Hawkes como velocidad
+    pub hawkes_context: bool,
+    /// Puntuación de contexto y detector de regímenes (activo si lo piden las
+    /// features o `context_source`)
+    pub context_source: ContextSource,
+    pub hmm: HmmConfig,
+    /// Señal de ruido y filtro de Kalman del microprecio (activo si lo piden las
+    /// features o `noise_source`)
+    pub noise_source: NoiseSource,
+    pub kalman: KalmanConfig,
+    /// Granularidad del FeatureCollector: None = un vector por mensaje,
+    /// Some = un vector por barra cerrada
+    pub bars: Option<BarSpec>,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            feature_window: 100,
+            gp_window: 20,
+            gp_length_scale: 1.5,
+            gp_sigma_f: 1.0,
+            gp_kernel: KernelSpec::Rbf,
+            gp_time_axis: TimeAxis::TickIndex,
+            context_threshold: 0.45,
+            regime_bins: 3,
+            regime_window: 2000,
+            features: FeatureConfig::default(),
+            normalization: Normalization::ZScore,
+            clipping: Clipping::None,
+            feature_clipping: Vec::new(),
+            time_source: TimeSource::Sending,
+            velocity: RateMode::Window(1.0),
+            hawkes: HawkesConfig::default(),
+            hawkes_context: false,
+            context_source: ContextSource::Bayesian,
+            hmm: HmmConfig::default(),
+            noise_source: NoiseSource::Gp,
+            kalman: KalmanConfig::default(),
+            bars: None,
+        }
+    }
+}
+
+/// Estado del mercado tras procesar un mensaje con precio medio válido
+#[derive(Debug, Clone)]
+pub struct MarketSnapshot {
+    /// Segundos (reloj del mensaje o de recepción)
+    pub time: f64,
+    pub mid: f64,
+    /// Spread en ticks (`FeatureConfig::tick_size`)
+    pub spread: f64,
+    /// Eventos por segundo (total) y por tipo de entrada
+    pub velocity: f64,
+    pub rates: VelocityRates,
+    /// Intensidad condicional de Hawkes (0 sin modelo)
+    pub hawkes_intensity: f64,
+    pub depth: Vec<f64>,
+    pub intensity: f64,
+    pub noise: f64,
+    pub context: f64,
+    /// Fuente que puntuó el contexto (la red bayesiana si el HMM aún no tiene modelo)
+    pub context_source: ContextSource,
+    /// Posteriores de los regímenes del HMM (vacío sin modelo)
+    pub regimes: Vec<f64>,
+    /// Precio justo del filtro de Kalman (None sin filtro o sin datos)
+    pub kalman: Option<KalmanEstimate>,
+    /// Volumen de las entradas del mensaje (reloj de volumen)
+    pub volume: f64,
+    pub raw_features: Vec<f64>,
+    /// Vector normalizado para los modelos (vacío durante el calentamiento)
+    pub features: Array1<f64>,
+    pub context_sample: ContextSample,
+    /// Los cortes de régimen se reajustaron en este mensaje
+    pub regimes_refit: bool,
+    /// Barras cerradas por este mensaje (vacío sin `PipelineConfig::bars`)
+    pub bars: Vec<Bar>,
+}
+
+/// Del mensaje FIX de market data al vector de features
+pub struct MarketPipeline {
+    pub order_book: OrderBook,
+    /// Operaciones ejecutadas (269=2), fuera del libro
+    pub trades: TradeTape,
+    pub bars: Option<BarAggregator>,
+    pub collector: FeatureCollector,
+    pub g_filter: GaussianFilter,
+    pub bayes_net: BayesianNetwork,
+    pub regimes: SymbolRegimes,
+    pub current_velocity: f64,
+    pub rates: VelocityRates,
+    pub hawkes: Option<HawkesModel>,
+    hawkes_context: bool,
+    pub regime_detector: Option<RegimeDetector>,
+    context_source: ContextSource,
+    pub kalman: Option<KalmanFilter>,
+    noise_source: NoiseSource,
+    pub msg_count: u64,
+    time_source: TimeSource,
+    tick_velocity: TickVelocity,
+}
+
+impl MarketPipeline {
+    pub fn new(config: &PipelineConfig, initial_edges: RegimeEdges) -> Self {
+        let mut bayes_net = BayesianNetwork::new(config.context_threshold);
+        bayes_net.set_bins(&initial_edges);
+        Self {
+            order_book: OrderBook::new(),
+            trades: TradeTape::new(if config.features.trade_window > 0.0 {
+                config.features.trade_window
+            } else {
+                60.0
+            }),
+            bars: config.bars.map(BarAggregator::new),
+            collector: FeatureCollector::new(config.feature_window)
+                .with_config(config.features.clone())
+                .with_normalization(config.normalization)
+                .with_clipping(config.clipping, config.feature_clipping.clone()),
+            g_filter: GaussianFilter::with_kernel(
+                config.gp_window,
+                config
+                    .gp_kernel
+                    .build(config.gp_length_scale, config.gp_sigma_f),
+                config.gp_time_axis,
+            ),
+            bayes_net,
+            regimes: SymbolRegimes::new(config.regime_bins, config.regime_window, initial_edges),
+            current_velocity: 0.0,
+            rates: VelocityRates::default(),
+            hawkes: (config.features.hawkes || config.hawkes_context)
+                .then(|| HawkesModel::new(config.hawkes.clone())),
+            hawkes_context: config.hawkes_context,
+            regime_detector: (config.features.regime_states > 0
+                || config.context_source != ContextSource::Bayesian)
+                .then(|| {
+                    let mut hmm = config.hmm.clone();
+                    if config.features.regime_states > 0 {
+                        hmm.states = config.features.regime_states;
+                    }
+                    RegimeDetector::new(hmm)
+                }),
+            context_source: config.context_source,
+            kalman: (config.features.kalman || config.noise_source == NoiseSource::Kalman)
+                .then(|| KalmanFilter::new(config.kalman)),
+            noise_source: config.noise_source,
+            msg_count: 0,
+            time_source: config.time_source,
+            tick_velocity: TickVelocity::new(config.velocity),
+        }
+    }
+
+    /// Procesa un mensaje (separador '|') recibido en `time` segundos.
+    /// Devuelve None si no es market data (35=W/X), el libro no tiene precio medio
+    /// o el mensaje solo trae operaciones.
+    pub fn on_message(&mut self, msg: &str, time: f64) -> Option<MarketSnapshot> {
+        if !(msg.contains("|35=W|") || msg.contains("|35=X|")) {
+            return None;
+        }
+
+        // Reloj del mensaje: SendingTime si se pide y está presente
+        let msg_time = match self.time_source {
+            TimeSource::Receive => time,
+            _ => tag_value(msg, "52")
+                .and_then(parse_utc_timestamp)
+                .unwrap_or(time),
+        };
+        let top_before = (
+            self.order_book.get_best_bid(),
+            self.order_book.get_best_ask(),
+        );
+        let mut latest = msg_time;
+        let mut events = 0;
+        let mut book_events = 0;
+
+        let entries: Vec<&str> = msg.split("|279=").collect();
+        let mut msg_volume = 0.0;
+        let mut closed_bars = Vec::new();
+        for entry in entries.iter().skip(1) {
+            let fragment = format!("|279={}", entry);
+            let price = extract_tag(&fragment, "270").unwrap_or(0.0);
+            let volume = extract_tag(&fragment, "271").unwrap_or(0.0);
+            let event_time = match self.time_source {
+                TimeSource::Entry => tag_value(&fragment, "273")
+                    .and_then(|t| parse_entry_time(t, tag_value(&fragment, "272"), msg_time))
+                    .unwrap_or(msg_time),
+                _ => msg_time,
+            };
+            // 0 = bid, 1 = ask, 2 = operación; el resto (apertura, cierre, máximos...)
+            // no pertenece al libro
+            let kind = match extract_tag(&fragment, "269").map(|t| t as i64) {
+                Some(0) => {
+                    self.order_book.update('1', '0', price, volume);
+                    book_events += 1;
+                    EventKind::Bid
+                }
+                Some(1) => {
+                    self.order_book.update('1', '1', price, volume);
+                    book_events += 1;
+                    EventKind::Ask
+                }
+                Some(2) => {
+                    // Agresor informado por el broker (AggressorSide o Side) o inferido
+                    let aggressor = extract_tag(&fragment, "2446")
+                        .or_else(|| extract_tag(&fragment, "54"))
+                        .map_or(Aggressor::Unknown, Aggressor::from_fix);
+                    let aggressor = match aggressor {
+                        Aggressor::Unknown => self.trades.classify(
+                            price,
+                            self.order_book.get_best_bid(),
+                            self.order_book.get_best_ask(),
+                        ),
+                        known => known,
+                    };
+                    if let Some(bars) = self.bars.as_mut() {
+                        closed_bars.extend(bars.on_trade(event_time, price, volume));
+                    }
+                    self.trades.push(Trade {
+                        time: event_time,
+                        price,
+                        size: volume,
+                        aggressor,
+                    });
+                    EventKind::Trade
+                }
+                _ => continue,
+            };
+            latest = latest.max(event_time);
+            self.tick_velocity.record(kind, event_time);
+            events += 1;
+            msg_volume += volume;
+        }
+
+        // Velocidad de ticks en el reloj de los mensajes (reproducible offline)
+        self.rates = self.tick_velocity.rates(latest);
+        self.current_velocity = self.rates.total;
+        // Hawkes: un evento por mensaje (sus entradas comparten reloj)
+        let (hawkes_intensity, branching_ratio) = match self.hawkes.as_mut() {
+            Some(model) => {
+                if events > 0 {
+                    model.record(latest);
+                }
+                (model.intensity(latest), model.branching_ratio())
+            }
+            None => (0.0, 0.0),
+        };
+        let context_velocity = if self.hawkes_context && self.hawkes.is_some() {
+            hawkes_intensity
+        } else {
+            self.current_velocity
+        };
+
+        let mid = self.order_book.get_mid_price()?;
+        // Las barras solo ven cambios del mejor bid/ask, no de la profundidad
+        let top = (
+            self.order_book.get_best_bid(),
+            self.order_book.get_best_ask(),
+        );
+        if book_events > 0 && top != top_before {
+            if let (Some(bars), (Some(bid), Some(ask))) = (self.bars.as_mut(), top) {
+                closed_bars.extend(bars.on_quote(latest, bid, ask));
+            }
+        }
+        // Un mensaje solo de operaciones no cambia el libro: no es un update para
+        // las features ni el etiquetado (salvo que cierre una barra)
+        if book_events == 0 && closed_bars.is_empty() {
+            return None;
+        }
+        self.msg_count += 1;
+        self.g_filter.add_price_at(mid, latest);
+
+        // 1. Obtener métricas de filtros (spread en ticks)
+        let spread = (self.order_book.get_best_ask().unwrap_or(mid)
+            - self.order_book.get_best_bid().unwrap_or(mid))
+        .abs()
+            / self.collector.config.tick_size;
+        let depth = self.order_book.get_depth_vector(3);
+        let intensity = self.order_book.get_book_intensity();
+        // Ruido: GP o innovación del Kalman (el GP si el libro no da microprecio)
+        let kalman_noise = self.kalman.as_mut().and_then(|filter| {
+            filter.update(self.order_book.get_microprice()?);
+            Some(filter.compute_uncertainty())
+        });
+        let noise = match (self.noise_source, kalman_noise) {
+            (NoiseSource::Kalman, Some(uncertainty)) => uncertainty,
+            _ => self.g_filter.compute_uncertainty(),
+        };
+        let kalman = self.kalman.as_ref().and_then(KalmanFilter::estimate);
+        let regimes_refit = self.regimes.push(spread, context_velocity, intensity);
+        if regimes_refit {
+            self.bayes_net.set_bins(&self.regimes.edges());
+        }
+        let bayesian =
+            self.bayes_net
+                .compute_context_score(spread, context_velocity, &depth, intensity);
+        // Sin modelo ajustado todavía, el HMM cede a la red bayesiana
+        let regime_score = self.regime_detector.as_mut().and_then(|detector| {
+            detector.update(mid, spread);
+            detector.context_score()
+        });
+        let (context, context_source) = match (self.context_source, regime_score) {
+            (ContextSource::Hmm, Some(score)) => (score, ContextSource::Hmm),
+            (ContextSource::Blend, Some(score)) => ((bayesian + score) / 2.0, ContextSource::Blend),
+            _ => (bayesian, ContextSource::Bayesian),
+        };
+        let (regimes, regime) = match self.regime_detector.as_ref() {
+            Some(detector) => (detector.posterior().to_vec(), detector.map_state()),
+            None => (Vec::new(), None),
+        };
+
+        // 2. Empaquetar características (según la FeatureConfig); un NaN/inf
+        // descarta el vector para que no llegue a los modelos. Con barras solo
+        // se calcula al cerrarse una, con el estado del libro en ese momento.
+        self.trades.prune(latest);
+        let emit = self.bars.is_none() || !closed_bars.is_empty();
+        let features = if !emit {
+            Array1::zeros(0)
+        } else {
+            match self.collector.push_features(
+                &self.order_book,
+                &self.trades,
+                latest,
+                &Signals {
+                    rates: self.rates,
+                    noise,
+                    context,
+                    hawkes_intensity,
+                    branching_ratio,
+                    regimes: &regimes,
+                    kalman,
+                    bar: closed_bars.last(),
+                },
+            ) {
+                Ok(()) => self.collector.get_standardized_vector(),
+                Err(e) => {
+                    warn!("Vector de features descartado: {}", e);
+                    Array1::zeros(0)
+                }
+            }
+        };
+
+        Some(MarketSnapshot {
+            time,
+            mid,
+            spread,
+            velocity: self.current_velocity,
+            rates: self.rates,
+            hawkes_intensity,
+            intensity,
+            noise,
+            context,
+            context_source,
+            regimes,
+            kalman,
+            volume: msg_volume,
+            raw_features: self.collector.last_raw().to_vec(),
+            features,
+            context_sample: ContextSample {
+                spread,
+                velocity: context_velocity,
+                intensity,
+                imbalance: depth_imbalance(&depth),
+                regime,
+                favorable: false,
+            },
+            depth,
+            regimes_refit,
+            bars: closed_bars,
+        })
+    }
+
+    /// Resultado de una entrada: actualiza las CPTs de la red bayesiana y la tasa
+    /// de acierto del régimen en que se tomó
+    pub fn observe_context(&mut self, sample: &ContextSample) {
+        self.bayes_net.observe(sample);
+        if let (Some(detector), Some(state)) = (self.regime_detector.as_mut(), sample.regime) {
+            detector.observe(state, sample.favorable);
+        }
+    }
+}
+
+pub fn extract_tag(msg: &str, tag: &str) -> Option<f64> {
+    let pattern = format!("|{}=", tag);
+    if let Some(start) = msg.find(&pattern) {
+        let val_start = start + pattern.len();
+        let end_offset = msg[val_start..].find('|').unwrap_or(msg[val_start..].len());
+        let val_str = &msg[val_start..val_start + end_offset];
+        return val_str.parse::<f64>().ok();
+    }
+    None
+}
@@ -0,0 +1,44 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+/// Grabación de los mensajes de market data crudos para reproducirlos offline.
+/// Formato: una línea por mensaje, "<segundos unix>\t<mensaje con separador '|'>".
+pub struct MarketRecorder {
+    writer: BufWriter<File>,
+}
+
+impl MarketRecorder {
+    /// Abre la grabación en modo append
+    pub fn open(path: &str) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn record(&mut self, time: f64, msg: &str) -> Result<(), Box<dyn Error>> {
+        writeln!(self.writer, "{:.6}\t{}", time, msg)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Lee una grabación completa en orden; ignora las líneas mal formadas
+pub fn read_recording(path: &str) -> Result<Vec<(f64, String)>, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut messages = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if let Some((t, msg)) = line.split_once('\t') {
+            if let Ok(t) = t.parse() {
+                messages.push((t, msg.to_string()));
+            }
+        }
+    }
+    Ok(messages)
+}
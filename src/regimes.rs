@@ -0,0 +1,248 @@
+use crate::bayesian::{Discretizer, NetworkCounts};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Discretizador adaptativo: los cortes son cuantiles de una ventana rodante.
+/// Con `n_bins = 3` equivale a Low/Normal/High por terciles.
+pub struct AdaptiveDiscretizer {
+    pub n_bins: usize,
+    pub window_size: usize,
+    pub refit_every: usize,
+    values: VecDeque<f64>,
+    since_refit: usize,
+    current: Discretizer,
+}
+
+impl AdaptiveDiscretizer {
+    pub fn new(n_bins: usize, window_size: usize, initial: Discretizer) -> Self {
+        Self {
+            n_bins: n_bins.max(2),
+            window_size,
+            refit_every: window_size / 4 + 1,
+            values: VecDeque::with_capacity(window_size),
+            since_refit: 0,
+            current: initial,
+        }
+    }
+
+    /// Añade una observación. Devuelve true si los cortes se recalcularon.
+    pub fn push(&mut self, value: f64) -> bool {
+        if !value.is_finite() {
+            return false;
+        }
+        if self.values.len() >= self.window_size {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+        self.since_refit += 1;
+
+        // Solo reajustamos con la ventana llena y cada `refit_every` muestras
+        if self.values.len() < self.window_size || self.since_refit < self.refit_every {
+            return false;
+        }
+        self.since_refit = 0;
+        self.refit()
+    }
+
+    fn refit(&mut self) -> bool {
+        let mut sorted: Vec<f64> = self.values.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let n = sorted.len();
+        let mut cuts = Vec::with_capacity(self.n_bins - 1);
+        for k in 1..self.n_bins {
+            let idx = (k * n / self.n_bins).min(n - 1);
+            let cut = sorted[idx];
+            // Cortes estrictamente crecientes (datos con muchos empates)
+            if cuts.last().is_none_or(|&last| cut > last) {
+                cuts.push(cut);
+            }
+        }
+
+        // Si hay empates masivos perderíamos bins: conservamos los cortes anteriores
+        if cuts.len() != self.n_bins - 1 || cuts == self.current.cuts {
+            return false;
+        }
+        self.current = Discretizer::new(cuts);
+        true
+    }
+
+    pub fn discretizer(&self) -> &Discretizer {
+        &self.current
+    }
+}
+
+/// Cortes persistidos de un instrumento (mismos regímenes entre reinicios y backtests)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegimeEdges {
+    pub spread: Vec<f64>,
+    pub velocity: Vec<f64>,
+    pub intensity: Vec<f64>,
+}
+
+/// Discretizadores adaptativos de un símbolo para los nodos de la red
+pub struct SymbolRegimes {
+    pub spread: AdaptiveDiscretizer,
+    pub velocity: AdaptiveDiscretizer,
+    pub intensity: AdaptiveDiscretizer,
+}
+
+impl SymbolRegimes {
+    pub fn new(n_bins: usize, window_size: usize, edges: RegimeEdges) -> Self {
+        Self {
+            spread: AdaptiveDiscretizer::new(n_bins, window_size, Discretizer::new(edges.spread)),
+            velocity: AdaptiveDiscretizer::new(
+                n_bins,
+                window_size,
+                Discretizer::new(edges.velocity),
+            ),
+            intensity: AdaptiveDiscretizer::new(
+                n_bins,
+                window_size,
+                Discretizer::new(edges.intensity),
+            ),
+        }
+    }
+
+    /// Devuelve true si alguno de los nodos cambió sus cortes
+    pub fn push(&mut self, spread: f64, velocity: f64, intensity: f64) -> bool {
+        let s = self.spread.push(spread);
+        let v = self.velocity.push(velocity);
+        let i = self.intensity.push(intensity);
+        s || v || i
+    }
+
+    pub fn edges(&self) -> RegimeEdges {
+        RegimeEdges {
+            spread: self.spread.discretizer().cuts.clone(),
+            velocity: self.velocity.discretizer().cuts.clone(),
+            intensity: self.intensity.discretizer().cuts.clone(),
+        }
+    }
+}
+
+/// Almacén JSON de cortes por símbolo y de los conteos de la red aprendidos con ellos
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RegimeStore {
+    pub symbols: HashMap<String, RegimeEdges>,
+    #[serde(default)]
+    pub networks: HashMap<String, NetworkCounts>,
+}
+
+impl RegimeStore {
+    /// Carga el almacén; si el archivo no existe devuelve uno vacío
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        if !Path::new(path).exists() {
+            return Ok(Self::default());
+        }
+        let raw = fs::read_to_string(path)?;
+        let store: Self = serde_json::from_str(&raw)?;
+        info!(
+            "Regímenes cargados desde {} ({} símbolos)",
+            path,
+            store.symbols.len()
+        );
+        Ok(store)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&RegimeEdges> {
+        self.symbols.get(symbol)
+    }
+
+    pub fn set(&mut self, symbol: &str, edges: RegimeEdges) {
+        self.symbols.insert(symbol.to_string(), edges);
+    }
+
+    pub fn counts(&self, symbol: &str) -> Option<&NetworkCounts> {
+        self.networks.get(symbol)
+    }
+
+    pub fn set_counts(&mut self, symbol: &str, counts: NetworkCounts) {
+        self.networks.insert(symbol.to_string(), counts);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refit_cuts_are_window_quantiles() {
+        let mut regimes = AdaptiveDiscretizer::new(4, 8, Discretizer::new(vec![0.0]));
+        assert_eq!(regimes.refit_every, 3);
+        // Solo con la ventana llena: [1..8] se parte en cuartiles
+        let refits: Vec<bool> = (1..=8).map(|x| regimes.push(x as f64)).collect();
+        assert_eq!(
+            refits,
+            [false, false, false, false, false, false, false, true]
+        );
+        assert_eq!(regimes.discretizer().cuts, vec![3.0, 5.0, 7.0]);
+        let bins: Vec<usize> = (1..=8)
+            .map(|x| regimes.discretizer().bin(x as f64))
+            .collect();
+        assert_eq!(bins, vec![0, 0, 1, 1, 2, 2, 3, 3]);
+    }
+
+    #[test]
+    fn push_reports_only_effective_refits() {
+        let mut regimes = AdaptiveDiscretizer::new(4, 8, Discretizer::new(vec![0.0]));
+        (1..=8).for_each(|x| {
+            regimes.push(x as f64);
+        });
+        // Cada `refit_every` muestras: la ventana [4..11] desplaza los cortes
+        let refits: Vec<bool> = (9..=11).map(|x| regimes.push(x as f64)).collect();
+        assert_eq!(refits, [false, false, true]);
+        assert_eq!(regimes.discretizer().cuts, vec![6.0, 8.0, 10.0]);
+        // Los valores no finitos no cuentan
+        assert!(!regimes.push(f64::NAN));
+        // Reentran los valores que salen: mismos cortes, no es un reajuste
+        let refits: Vec<bool> = [4.0, 5.0, 6.0].iter().map(|&x| regimes.push(x)).collect();
+        assert_eq!(regimes.discretizer().cuts, vec![6.0, 8.0, 10.0]);
+        assert_eq!(refits, [false, false, false]);
+    }
+
+    #[test]
+    fn massive_ties_keep_the_previous_cuts() {
+        let mut regimes = AdaptiveDiscretizer::new(3, 6, Discretizer::new(vec![1.0, 2.0]));
+        let refits: Vec<bool> = (0..12).map(|_| regimes.push(5.0)).collect();
+        assert!(refits.iter().all(|r| !r));
+        assert_eq!(regimes.discretizer().cuts, vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn store_round_trips_edges_and_network_counts() {
+        let path = std::env::temp_dir().join(format!("regimes-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let edges = crate::bayesian::BayesianNetwork::default_edges();
+        let counts = crate::bayesian::BayesianNetwork::new(0.45).counts();
+        let mut store = RegimeStore::default();
+        store.set("EURUSD", edges.clone());
+        store.set_counts("EURUSD", counts.clone());
+        store.save(path).unwrap();
+        let loaded = RegimeStore::load(path).unwrap();
+        assert_eq!(loaded.get("EURUSD").map(|e| &e.spread), Some(&edges.spread));
+        let favorable = &loaded.counts("EURUSD").unwrap().favorable;
+        assert_eq!(favorable.parent_cards, counts.favorable.parent_cards);
+        assert!(favorable
+            .counts
+            .iter()
+            .zip(&counts.favorable.counts)
+            .all(|(a, b)| (a - b).abs() < 1e-12));
+
+        // Almacenes anteriores, sin conteos
+        fs::write(path, r#"{"symbols": {}}"#).unwrap();
+        let old = RegimeStore::load(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert!(old.networks.is_empty());
+        assert!(RegimeStore::load(path).unwrap().symbols.is_empty());
+    }
+}
@@ -0,0 +1,353 @@
+use ringbuffer::{AllocRingBuffer, RingBuffer};
+
+/// Media y varianza de una ventana deslizante con coste O(1) por muestra:
+/// Welford con altas y bajas sobre un ring buffer.
+#[derive(Clone)]
+pub struct RollingStats {
+    values: AllocRingBuffer<f64>,
+    mean: f64,
+    /// Suma de cuadrados de las desviaciones respecto a la media
+    m2: f64,
+    /// Altas desde el último recálculo exacto (acota el error de redondeo)
+    since_refresh: usize,
+}
+
+impl RollingStats {
+    pub fn new(window: usize) -> Self {
+        Self {
+            values: AllocRingBuffer::new(window.max(1)),
+            mean: 0.0,
+            m2: 0.0,
+            since_refresh: 0,
+        }
+    }
+
+    pub fn push(&mut self, x: f64) {
+        if self.values.is_full() {
+            if let Some(&old) = self.values.front() {
+                let n = self.values.len() as f64;
+                if n > 1.0 {
+                    let delta = old - self.mean;
+                    self.mean -= delta / (n - 1.0);
+                    self.m2 -= delta * (old - self.mean);
+                } else {
+                    self.mean = 0.0;
+                    self.m2 = 0.0;
+                }
+            }
+        }
+        self.values.push(x);
+
+        let n = self.values.len() as f64;
+        let delta = x - self.mean;
+        self.mean += delta / n;
+        self.m2 += delta * (x - self.mean);
+
+        // Cada ventana completa se recalcula desde el buffer: O(1) amortizado
+        self.since_refresh += 1;
+        if self.since_refresh >= self.values.capacity() {
+            self.refresh();
+        }
+    }
+
+    fn refresh(&mut self) {
+        let n = self.values.len() as f64;
+        self.mean = self.values.iter().sum::<f64>() / n;
+        self.m2 = self.values.iter().map(|v| (v - self.mean).powi(2)).sum();
+        self.since_refresh = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Varianza poblacional (ddof = 0)
+    pub fn variance(&self) -> f64 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        (self.m2 / self.values.len() as f64).max(0.0)
+    }
+
+    pub fn std(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    pub fn last(&self) -> Option<f64> {
+        self.values.back().copied()
+    }
+
+    /// Valores de la ventana, del más antiguo al más reciente
+    pub fn iter(&self) -> impl Iterator<Item = &f64> {
+        self.values.iter()
+    }
+}
+
+/// Media y varianza con pesos exponenciales (sin ventana): O(1) y memoria constante
+#[derive(Debug, Clone)]
+pub struct EwmaStats {
+    alpha: f64,
+    mean: f64,
+    variance: f64,
+    count: usize,
+}
+
+impl EwmaStats {
+    /// `halflife` en muestras: el peso de una observación se reduce a la mitad tras ellas
+    pub fn new(halflife: f64) -> Self {
+        Self {
+            alpha: 1.0 - 0.5f64.powf(1.0 / halflife.max(1e-9)),
+            mean: 0.0,
+            variance: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn push(&mut self, x: f64) {
+        if self.count == 0 {
+            self.mean = x;
+            self.variance = 0.0;
+        } else {
+            let delta = x - self.mean;
+            let incr = self.alpha * delta;
+            self.mean += incr;
+            self.variance = (1.0 - self.alpha) * (self.variance + delta * incr);
+        }
+        self.count += 1;
+    }
+
+    /// Muestras vistas desde el inicio
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    pub fn variance(&self) -> f64 {
+        self.variance
+    }
+
+    pub fn std(&self) -> f64 {
+        self.variance.sqrt()
+    }
+}
+
+/// Ventana deslizante que además mantiene sus valores ordenados: mediana,
+/// cuantiles, extremos y rango. Alta y baja por búsqueda binaria
+/// (O(log n) más el desplazamiento de memoria, despreciable para ventanas de cientos).
+#[derive(Clone)]
+pub struct SortedWindow {
+    values: AllocRingBuffer<f64>,
+    sorted: Vec<f64>,
+}
+
+impl SortedWindow {
+    pub fn new(window: usize) -> Self {
+        Self {
+            values: AllocRingBuffer::new(window.max(1)),
+            sorted: Vec::with_capacity(window.max(1)),
+        }
+    }
+
+    /// `x` debe ser finito
+    pub fn push(&mut self, x: f64) {
+        if self.values.is_full() {
+            if let Some(&old) = self.values.front() {
+                let idx = self.sorted.partition_point(|v| *v < old);
+                self.sorted.remove(idx);
+            }
+        }
+        self.values.push(x);
+        let idx = self.sorted.partition_point(|v| *v < x);
+        self.sorted.insert(idx, x);
+    }
+
+    pub fn len(&self) -> usize {
+        self.sorted.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sorted.is_empty()
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        self.sorted.first().copied()
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        self.sorted.last().copied()
+    }
+
+    /// Cuantil `q` en [0, 1] con interpolación lineal
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let n = self.sorted.len();
+        if n == 0 {
+            return None;
+        }
+        let pos = q.clamp(0.0, 1.0) * (n - 1) as f64;
+        let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+        let frac = pos - lo as f64;
+        Some(self.sorted[lo] + (self.sorted[hi] - self.sorted[lo]) * frac)
+    }
+
+    pub fn median(&self) -> Option<f64> {
+        self.quantile(0.5)
+    }
+
+    /// Mediana de las desviaciones absolutas respecto a la mediana (O(n))
+    pub fn mad(&self) -> Option<f64> {
+        let median = self.median()?;
+        let mut dev: Vec<f64> = self.sorted.iter().map(|v| (v - median).abs()).collect();
+        let mid = dev.len() / 2;
+        let (_, upper, _) = dev.select_nth_unstable_by(mid, f64::total_cmp);
+        let upper = *upper;
+        if dev.len() % 2 == 1 {
+            return Some(upper);
+        }
+        let lower = dev[..mid].iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        Some((lower + upper) / 2.0)
+    }
+
+    /// Valores de la ventana en orden ascendente
+    pub fn iter(&self) -> impl Iterator<Item = &f64> {
+        self.sorted.iter()
+    }
+
+    /// Rango medio de `x` dentro de la ventana, en [0, 1]
+    pub fn rank(&self, x: f64) -> Option<f64> {
+        let n = self.sorted.len();
+        if n == 0 {
+            return None;
+        }
+        let below = self.sorted.partition_point(|v| *v < x);
+        let not_above = self.sorted.partition_point(|v| *v <= x);
+        Some((below + not_above) as f64 / (2 * n) as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Media y varianza poblacional exactas de los últimos `window` valores
+    fn naive(values: &[f64], window: usize) -> (f64, f64) {
+        let tail = &values[values.len().saturating_sub(window)..];
+        let n = tail.len() as f64;
+        let mean = tail.iter().sum::<f64>() / n;
+        (
+            mean,
+            tail.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n,
+        )
+    }
+
+    #[test]
+    fn welford_removal_known_answer() {
+        let mut stats = RollingStats::new(3);
+        for x in [1.0, 2.0, 3.0, 4.0] {
+            stats.push(x);
+        }
+        // El 4 entra por Welford y el 1 sale por la baja (sin recálculo)
+        assert_eq!(stats.len(), 3);
+        assert!((stats.mean() - 3.0).abs() < 1e-15);
+        assert!((stats.variance() - 2.0 / 3.0).abs() < 1e-15);
+        assert_eq!(stats.last(), Some(4.0));
+        assert_eq!(
+            stats.iter().copied().collect::<Vec<_>>(),
+            vec![2.0, 3.0, 4.0]
+        );
+    }
+
+    #[test]
+    fn welford_tracks_the_exact_window_with_a_large_offset() {
+        let values: Vec<f64> = (0..5000)
+            .map(|i| 1e6 + (i as f64 * 0.7).sin() * (1.0 + (i / 1000) as f64))
+            .collect();
+        let mut stats = RollingStats::new(100);
+        for (i, &x) in values.iter().enumerate() {
+            stats.push(x);
+            let (mean, var) = naive(&values[..=i], 100);
+            assert!((stats.mean() - mean).abs() < 1e-8, "media en {}", i);
+            assert!(
+                (stats.variance() - var).abs() < 1e-6 * var.max(1.0),
+                "varianza en {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn window_of_one_has_no_variance() {
+        let mut stats = RollingStats::new(1);
+        for x in [5.0, -3.0, 8.0] {
+            stats.push(x);
+            assert_eq!((stats.mean(), stats.variance()), (x, 0.0));
+        }
+        assert_eq!(RollingStats::new(4).variance(), 0.0);
+    }
+
+    #[test]
+    fn ewma_known_answer() {
+        // Semivida 1: alpha = 0.5
+        let mut ewma = EwmaStats::new(1.0);
+        ewma.push(0.0);
+        ewma.push(2.0);
+        assert_eq!((ewma.mean(), ewma.variance()), (1.0, 1.0));
+        ewma.push(4.0);
+        assert_eq!((ewma.mean(), ewma.variance()), (2.5, 2.75));
+        assert_eq!(ewma.len(), 3);
+    }
+
+    #[test]
+    fn ewma_halves_the_weight_after_the_halflife() {
+        let mut ewma = EwmaStats::new(10.0);
+        ewma.push(1.0);
+        for _ in 0..10 {
+            ewma.push(0.0);
+        }
+        assert!((ewma.mean() - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sorted_window_quantiles_and_sliding() {
+        let mut window = SortedWindow::new(4);
+        for x in [5.0, 1.0, 3.0, 2.0, 4.0] {
+            window.push(x);
+        }
+        // Sale el 5: quedan [1, 2, 3, 4]
+        assert_eq!(
+            window.iter().copied().collect::<Vec<_>>(),
+            vec![1.0, 2.0, 3.0, 4.0]
+        );
+        assert_eq!((window.min(), window.max()), (Some(1.0), Some(4.0)));
+        assert_eq!(window.median(), Some(2.5));
+        assert_eq!(window.quantile(0.25), Some(1.75));
+        // Desviaciones [1.5, 0.5, 0.5, 1.5]: MAD = 1
+        assert_eq!(window.mad(), Some(1.0));
+    }
+
+    #[test]
+    fn sorted_window_rank_counts_ties_half() {
+        let mut window = SortedWindow::new(4);
+        for x in [1.0, 2.0, 2.0, 3.0] {
+            window.push(x);
+        }
+        assert_eq!(window.rank(2.0), Some(0.5));
+        assert_eq!(window.rank(0.0), Some(0.0));
+        assert_eq!(window.rank(9.0), Some(1.0));
+        assert_eq!(SortedWindow::new(3).rank(1.0), None);
+    }
+}
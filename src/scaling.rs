@@ -0,0 +1,329 @@
+use crate::rolling::{EwmaStats, RollingStats, SortedWindow};
+
+/// Escalado de cada columna antes de entrar en los modelos
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    /// z-score sobre la ventana (Welford deslizante)
+    ZScore,
+    /// z-score con media y varianza exponenciales de semivida en muestras
+    Ewma(f64),
+    /// (x - mediana) / (1.4826 · MAD): insensible a picos aislados
+    MedianMad,
+    /// Mínimo-máximo de la ventana llevado a [-1, 1]
+    MinMax,
+    /// Rango (cuantil empírico) dentro de la ventana llevado a [-1, 1]
+    Rank,
+}
+
+impl Normalization {
+    /// "zscore", "ewma" / "ewma:100", "median_mad", "minmax", "rank". La semivida
+    /// debe ser finita y positiva
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (name, param) = match name.split_once(':') {
+            Some((n, p)) => (n, Some(p)),
+            None => (name, None),
+        };
+        match name {
+            "zscore" => Some(Normalization::ZScore),
+            "ewma" => param
+                .map_or(Some(50.0), |p| p.parse().ok())
+                .filter(|h: &f64| h.is_finite() && *h > 0.0)
+                .map(Normalization::Ewma),
+            "median_mad" => Some(Normalization::MedianMad),
+            "minmax" => Some(Normalization::MinMax),
+            "rank" => Some(Normalization::Rank),
+            _ => None,
+        }
+    }
+}
+
+/// Recorte de valores extremos de una columna
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clipping {
+    None,
+    /// Recorta el valor ya escalado a [-c, c]
+    Clip(f64),
+    /// Lleva el valor crudo a los cuantiles [q, 1 - q] de la ventana antes de
+    /// escalarlo y de actualizar los estadísticos
+    Winsorize(f64),
+}
+
+impl Clipping {
+    /// "none", "clip:5", "winsor:0.01". El corte debe ser finito y positivo y el
+    /// cuantil estar en [0, 0.5) (si no, el intervalo queda invertido)
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (name, param) = match name.split_once(':') {
+            Some((n, p)) => (n, Some(p)),
+            None => (name, None),
+        };
+        match name {
+            "none" => Some(Clipping::None),
+            "clip" => param
+                .map_or(Some(5.0), |p| p.parse().ok())
+                .filter(|c: &f64| c.is_finite() && *c > 0.0)
+                .map(Clipping::Clip),
+            "winsor" => param
+                .map_or(Some(0.01), |p| p.parse().ok())
+                .filter(|q: &f64| (0.0..0.5).contains(q))
+                .map(Clipping::Winsorize),
+            _ => None,
+        }
+    }
+
+    /// Recorte por defecto y excepciones por feature:
+    /// "winsor:0.01,ofi=clip:3,spread_ticks=none"
+    pub fn parse_spec(spec: &str) -> Option<(Clipping, Vec<(String, Clipping)>)> {
+        let mut default = Clipping::None;
+        let mut overrides = Vec::new();
+        for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match item.split_once('=') {
+                Some((feature, clip)) => {
+                    overrides.push((feature.to_string(), Clipping::from_name(clip)?))
+                }
+                None => default = Clipping::from_name(item)?,
+            }
+        }
+        Some((default, overrides))
+    }
+}
+
+/// Estadísticos de la columna según el escalado
+#[derive(Clone)]
+enum Stats {
+    Window(RollingStats),
+    Ewma(EwmaStats),
+    Sorted(SortedWindow),
+}
+
+/// Escalador incremental de una columna
+#[derive(Clone)]
+pub struct ColumnScaler {
+    normalization: Normalization,
+    clipping: Clipping,
+    stats: Stats,
+    /// Ventana ordenada para winsorizar cuando el escalado no la mantiene ya
+    quantiles: Option<SortedWindow>,
+}
+
+impl ColumnScaler {
+    pub fn new(normalization: Normalization, clipping: Clipping, window: usize) -> Self {
+        let stats = match normalization {
+            Normalization::ZScore => Stats::Window(RollingStats::new(window)),
+            Normalization::Ewma(halflife) => Stats::Ewma(EwmaStats::new(halflife)),
+            Normalization::MedianMad | Normalization::MinMax | Normalization::Rank => {
+                Stats::Sorted(SortedWindow::new(window))
+            }
+        };
+        let quantiles = match (clipping, &stats) {
+            (Clipping::Winsorize(_), Stats::Window(_) | Stats::Ewma(_)) => {
+                Some(SortedWindow::new(window))
+            }
+            _ => None,
+        };
+        Self {
+            normalization,
+            clipping,
+            stats,
+            quantiles,
+        }
+    }
+
+    /// Muestras en los estadísticos
+    pub fn len(&self) -> usize {
+        match &self.stats {
+            Stats::Window(s) => s.len(),
+            Stats::Ewma(s) => s.len(),
+            Stats::Sorted(s) => s.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Incorpora `x` (finito) y devuelve su valor escalado con los estadísticos
+    /// actualizados. Una escala nula (columna constante) da 0.
+    pub fn push(&mut self, x: f64) -> f64 {
+        let x = self.winsorize(x);
+        match &mut self.stats {
+            Stats::Window(s) => s.push(x),
+            Stats::Ewma(s) => s.push(x),
+            Stats::Sorted(s) => s.push(x),
+        }
+        if let Some(q) = self.quantiles.as_mut() {
+            q.push(x);
+        }
+
+        let scaled = match (&self.stats, self.normalization) {
+            (Stats::Window(s), _) => standardize(x - s.mean(), s.std()),
+            (Stats::Ewma(s), _) => standardize(x - s.mean(), s.std()),
+            (Stats::Sorted(s), Normalization::MedianMad) => {
+                let median = s.median().unwrap_or(x);
+                standardize(x - median, robust_scale(s, median))
+            }
+            (Stats::Sorted(s), Normalization::MinMax) => {
+                let (lo, hi) = (s.min().unwrap_or(x), s.max().unwrap_or(x));
+                standardize(2.0 * (x - lo) - (hi - lo), hi - lo)
+            }
+            (Stats::Sorted(s), _) => 2.0 * s.rank(x).unwrap_or(0.5) - 1.0,
+        };
+
+        match self.clipping {
+            Clipping::Clip(c) => scaled.clamp(-c, c),
+            _ => scaled,
+        }
+    }
+
+    fn winsorize(&self, x: f64) -> f64 {
+        let Clipping::Winsorize(q) = self.clipping else {
+            return x;
+        };
+        let window = match (&self.quantiles, &self.stats) {
+            (Some(w), _) | (None, Stats::Sorted(w)) => w,
+            _ => return x,
+        };
+        // Sin historia suficiente los cuantiles no son fiables
+        if window.len() < 10 {
+            return x;
+        }
+        match (window.quantile(q), window.quantile(1.0 - q)) {
+            (Some(lo), Some(hi)) => x.clamp(lo, hi),
+            _ => x,
+        }
+    }
+}
+
+fn standardize(deviation: f64, scale: f64) -> f64 {
+    if scale > 1e-12 {
+        deviation / scale
+    } else {
+        0.0
+    }
+}
+
+/// 1.4826 · MAD (consistente con la desviación típica de una Normal); si la MAD es
+/// nula (columna casi discreta) se usa la desviación absoluta media respecto a la mediana
+fn robust_scale(window: &SortedWindow, median: f64) -> f64 {
+    let mad = window.mad().unwrap_or(0.0);
+    if mad > 0.0 {
+        return 1.4826 * mad;
+    }
+    let mean_abs =
+        window.iter().map(|v| (v - median).abs()).sum::<f64>() / window.len().max(1) as f64;
+    1.2533 * mean_abs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Último valor escalado tras empujar toda la serie
+    fn scale_last(
+        normalization: Normalization,
+        clipping: Clipping,
+        window: usize,
+        xs: &[f64],
+    ) -> f64 {
+        let mut scaler = ColumnScaler::new(normalization, clipping, window);
+        xs.iter().map(|&x| scaler.push(x)).last().unwrap()
+    }
+
+    #[test]
+    fn zscore_and_clip() {
+        let z = 1.0 / (2.0f64 / 3.0).sqrt();
+        let scaled = scale_last(Normalization::ZScore, Clipping::None, 3, &[1.0, 2.0, 3.0]);
+        assert!((scaled - z).abs() < 1e-12);
+        let clipped = scale_last(
+            Normalization::ZScore,
+            Clipping::Clip(1.0),
+            3,
+            &[1.0, 2.0, 3.0],
+        );
+        assert_eq!(clipped, 1.0);
+    }
+
+    #[test]
+    fn median_mad_ignores_the_spike_in_its_scale() {
+        // Mediana 3, desviaciones [0, 1, 1, 2, 97]: MAD = 1
+        let xs = [1.0, 2.0, 3.0, 4.0, 100.0];
+        let scaled = scale_last(Normalization::MedianMad, Clipping::None, 5, &xs);
+        assert!((scaled - 97.0 / 1.4826).abs() < 1e-9);
+        // MAD nula: se usa la desviación absoluta media (5 / 5)
+        let xs = [0.0, 0.0, 0.0, 0.0, 5.0];
+        let scaled = scale_last(Normalization::MedianMad, Clipping::None, 5, &xs);
+        assert!((scaled - 5.0 / 1.2533).abs() < 1e-9);
+    }
+
+    #[test]
+    fn minmax_and_rank_map_to_unit_interval() {
+        let xs = [0.0, 10.0, 5.0];
+        assert_eq!(
+            scale_last(Normalization::MinMax, Clipping::None, 3, &xs),
+            0.0
+        );
+        assert_eq!(
+            scale_last(Normalization::MinMax, Clipping::None, 3, &[0.0, 10.0]),
+            1.0
+        );
+        let rank = scale_last(Normalization::Rank, Clipping::None, 3, &[1.0, 2.0, 3.0]);
+        assert!((rank - 2.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn constant_column_scales_to_zero() {
+        for normalization in [
+            Normalization::ZScore,
+            Normalization::Ewma(5.0),
+            Normalization::MinMax,
+        ] {
+            assert_eq!(
+                scale_last(normalization, Clipping::None, 10, &[4.0; 10]),
+                0.0
+            );
+        }
+    }
+
+    #[test]
+    fn winsorize_clamps_to_the_window_quantiles_before_updating() {
+        let mut scaler = ColumnScaler::new(Normalization::ZScore, Clipping::Winsorize(0.1), 20);
+        let last = |scaler: &ColumnScaler| match &scaler.stats {
+            Stats::Window(stats) => stats.last(),
+            _ => None,
+        };
+        // Con menos de 10 valores no se recorta
+        for i in 0..10 {
+            scaler.push(i as f64);
+            assert_eq!(last(&scaler), Some(i as f64));
+        }
+        // Cuantiles 0.1 y 0.9 de 0..=9: 0.9 y 8.1
+        scaler.push(1000.0);
+        assert_eq!(last(&scaler), Some(8.1));
+        // Ahora sobre 11 valores el cuantil 0.1 cae justo en el 1
+        scaler.push(-1000.0);
+        assert_eq!(last(&scaler), Some(1.0));
+        assert_eq!(
+            scaler.quantiles.as_ref().and_then(SortedWindow::min),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn parse_normalization_and_clipping() {
+        assert_eq!(
+            Normalization::from_name("ewma:100"),
+            Some(Normalization::Ewma(100.0))
+        );
+        assert_eq!(Normalization::from_name("ewma:x"), None);
+        assert_eq!(Normalization::from_name("ewma:0"), None);
+        assert_eq!(Normalization::from_name("ewma:-10"), None);
+        assert_eq!(Normalization::from_name("ewma:inf"), None);
+        assert_eq!(Normalization::from_name("zscores"), None);
+        assert_eq!(Clipping::from_name("winsor:0.5"), None);
+        assert_eq!(Clipping::from_name("clip:-1"), None);
+        assert_eq!(Clipping::from_name("clip:inf"), None);
+        let (default, overrides) = Clipping::parse_spec("winsor:0.01,ofi=clip:3").unwrap();
+        assert_eq!(default, Clipping::Winsorize(0.01));
+        assert_eq!(overrides, vec![("ofi".to_string(), Clipping::Clip(3.0))]);
+        assert!(Clipping::parse_spec("ofi=bad").is_none());
+    }
+}
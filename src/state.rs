@@ -5,6 +5,12 @@ pub struct OrderBook {
     pub asks: BTreeMap<i64, f64>,
 }
 
+impl Default for OrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl OrderBook {
     pub fn new() -> Self {
         Self {
@@ -31,19 +37,43 @@ impl OrderBook {
     }
 
     pub fn get_mid_price(&self) -> Option<f64> {
-        let best_bid = self.bids.keys().rev().next()?;
+        let best_bid = self.bids.keys().next_back()?;
         let best_ask = self.asks.keys().next()?;
         Some((*best_bid as f64 + *best_ask as f64) / 200000.0)
     }
 
     pub fn get_best_bid(&self) -> Option<f64> {
-        self.bids.keys().rev().next().map(|&p| p as f64 / 100000.0)
+        self.bids.keys().next_back().map(|&p| p as f64 / 100000.0)
     }
 
     pub fn get_best_ask(&self) -> Option<f64> {
         self.asks.keys().next().map(|&p| p as f64 / 100000.0)
     }
 
+    /// Microprecio: mid ponderado por la cola contraria del mejor nivel
+    /// (el mid si el mejor nivel no tiene volumen)
+    pub fn get_microprice(&self) -> Option<f64> {
+        let (&pb, &qb) = self.bids.iter().next_back()?;
+        let (&pa, &qa) = self.asks.iter().next()?;
+        let (pb, pa) = (pb as f64 / 100000.0, pa as f64 / 100000.0);
+        if qb + qa > 0.0 {
+            Some((pb * qa + pa * qb) / (qb + qa))
+        } else {
+            Some((pb + pa) / 2.0)
+        }
+    }
+
+    /// Mejores `levels` niveles de un lado (0 = bid, 1 = ask) como (precio, volumen),
+    /// del más cercano al más lejano
+    pub fn top_levels(&self, side: char, levels: usize) -> Vec<(f64, f64)> {
+        let to_level = |(&p, &v): (&i64, &f64)| (p as f64 / 100000.0, v);
+        if side == '0' {
+            self.bids.iter().rev().take(levels).map(to_level).collect()
+        } else {
+            self.asks.iter().take(levels).map(to_level).collect()
+        }
+    }
+
     /// Imbalance simple (Nivel 1) para compatibilidad
     pub fn get_imbalance(&self) -> f64 {
         let b_vol = self.bids.values().next().unwrap_or(&0.0);
@@ -85,4 +115,3 @@ impl OrderBook {
         depth_v
     }
 }
-
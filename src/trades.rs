@@ -0,0 +1,236 @@
+use std::collections::VecDeque;
+
+/// Lado agresor de una operación (quien cruzó el spread)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggressor {
+    Buy,
+    Sell,
+    Unknown,
+}
+
+impl Aggressor {
+    /// +1 compra, -1 venta, 0 sin clasificar
+    pub fn sign(self) -> f64 {
+        match self {
+            Aggressor::Buy => 1.0,
+            Aggressor::Sell => -1.0,
+            Aggressor::Unknown => 0.0,
+        }
+    }
+
+    /// Valor FIX de Side (54) o AggressorSide (2446): 1 = compra, 2 = venta
+    pub fn from_fix(side: f64) -> Self {
+        match side as i64 {
+            1 => Aggressor::Buy,
+            2 => Aggressor::Sell,
+            _ => Aggressor::Unknown,
+        }
+    }
+}
+
+/// Operación ejecutada (MDEntryType 269=2)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trade {
+    /// Segundos
+    pub time: f64,
+    pub price: f64,
+    pub size: f64,
+    pub aggressor: Aggressor,
+}
+
+/// Cinta de operaciones (time and sales) de los últimos `window` segundos,
+/// con sumas acumuladas para consultar el flujo en O(1)
+#[derive(Debug, Clone)]
+pub struct TradeTape {
+    pub window: f64,
+    trades: VecDeque<Trade>,
+    buy_volume: f64,
+    sell_volume: f64,
+    volume: f64,
+    notional: f64,
+    last_price: Option<f64>,
+    last_sign: f64,
+}
+
+impl TradeTape {
+    pub fn new(window: f64) -> Self {
+        Self {
+            window,
+            trades: VecDeque::new(),
+            buy_volume: 0.0,
+            sell_volume: 0.0,
+            volume: 0.0,
+            notional: 0.0,
+            last_price: None,
+            last_sign: 0.0,
+        }
+    }
+
+    /// Clasifica el agresor cuando el broker no lo informa: regla de la cotización
+    /// (precio frente al mejor bid/ask y al mid) y, si cae en el mid, regla del tick
+    pub fn classify(&self, price: f64, best_bid: Option<f64>, best_ask: Option<f64>) -> Aggressor {
+        if let (Some(bid), Some(ask)) = (best_bid, best_ask) {
+            let mid = (bid + ask) / 2.0;
+            if price >= ask || price > mid {
+                return Aggressor::Buy;
+            }
+            if price <= bid || price < mid {
+                return Aggressor::Sell;
+            }
+        }
+        match self.last_price {
+            Some(last) if price > last => Aggressor::Buy,
+            Some(last) if price < last => Aggressor::Sell,
+            // Sin cambio de precio se hereda el signo anterior
+            Some(_) if self.last_sign > 0.0 => Aggressor::Buy,
+            Some(_) if self.last_sign < 0.0 => Aggressor::Sell,
+            _ => Aggressor::Unknown,
+        }
+    }
+
+    pub fn push(&mut self, trade: Trade) {
+        if !(trade.price.is_finite() && trade.size.is_finite()) || trade.size <= 0.0 {
+            return;
+        }
+        self.add(&trade, 1.0);
+        self.last_price = Some(trade.price);
+        if trade.aggressor != Aggressor::Unknown {
+            self.last_sign = trade.aggressor.sign();
+        }
+        self.trades.push_back(trade);
+        self.prune(trade.time);
+    }
+
+    /// Descarta las operaciones más antiguas que `now - window`
+    pub fn prune(&mut self, now: f64) {
+        while let Some(&old) = self.trades.front() {
+            if now - old.time <= self.window {
+                break;
+            }
+            self.add(&old, -1.0);
+            self.trades.pop_front();
+        }
+        if self.trades.is_empty() {
+            // Evita arrastrar el error de redondeo de las restas
+            self.buy_volume = 0.0;
+            self.sell_volume = 0.0;
+            self.volume = 0.0;
+            self.notional = 0.0;
+        }
+    }
+
+    fn add(&mut self, trade: &Trade, weight: f64) {
+        match trade.aggressor {
+            Aggressor::Buy => self.buy_volume += weight * trade.size,
+            Aggressor::Sell => self.sell_volume += weight * trade.size,
+            Aggressor::Unknown => {}
+        }
+        self.volume += weight * trade.size;
+        self.notional += weight * trade.size * trade.price;
+    }
+
+    pub fn len(&self) -> usize {
+        self.trades.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trades.is_empty()
+    }
+
+    pub fn last(&self) -> Option<&Trade> {
+        self.trades.back()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Trade> {
+        self.trades.iter()
+    }
+
+    /// Volumen comprador menos vendedor de la ventana
+    pub fn signed_volume(&self) -> f64 {
+        self.buy_volume - self.sell_volume
+    }
+
+    /// Precio medio ponderado por volumen de la ventana
+    pub fn vwap(&self) -> Option<f64> {
+        (self.volume > 0.0).then(|| self.notional / self.volume)
+    }
+
+    /// Operaciones por segundo en la ventana
+    pub fn intensity(&self) -> f64 {
+        if self.window > 0.0 {
+            self.trades.len() as f64 / self.window
+        } else {
+            0.0
+        }
+    }
+
+    /// (compras - ventas) / (compras + ventas) en volumen, en [-1, 1]
+    pub fn aggressor_imbalance(&self) -> f64 {
+        let classified = self.buy_volume + self.sell_volume;
+        if classified > 1e-12 {
+            ((self.buy_volume - self.sell_volume) / classified).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(time: f64, price: f64, size: f64, aggressor: Aggressor) -> Trade {
+        Trade {
+            time,
+            price,
+            size,
+            aggressor,
+        }
+    }
+
+    #[test]
+    fn quote_rule_then_tick_rule() {
+        let mut tape = TradeTape::new(10.0);
+        let (bid, ask) = (Some(1.0), Some(1.5));
+        assert_eq!(tape.classify(1.5, bid, ask), Aggressor::Buy);
+        assert_eq!(tape.classify(1.375, bid, ask), Aggressor::Buy);
+        assert_eq!(tape.classify(1.125, bid, ask), Aggressor::Sell);
+        // En el mid decide el tick; sin historia no se clasifica
+        assert_eq!(tape.classify(1.25, bid, ask), Aggressor::Unknown);
+        tape.push(trade(0.0, 1.5, 1.0, Aggressor::Buy));
+        assert_eq!(tape.classify(1.25, bid, ask), Aggressor::Sell);
+        tape.push(trade(1.0, 1.25, 1.0, Aggressor::Sell));
+        // Sin cambio de precio se hereda el último signo
+        assert_eq!(tape.classify(1.25, None, None), Aggressor::Sell);
+    }
+
+    #[test]
+    fn window_flow_known_answers() {
+        let mut tape = TradeTape::new(2.0);
+        tape.push(trade(0.0, 10.0, 1.0, Aggressor::Buy));
+        tape.push(trade(1.0, 12.0, 3.0, Aggressor::Sell));
+        tape.push(trade(1.5, 11.0, 2.0, Aggressor::Unknown));
+        assert_eq!(tape.signed_volume(), -2.0);
+        assert_eq!(tape.vwap(), Some((10.0 + 36.0 + 22.0) / 6.0));
+        assert_eq!(tape.intensity(), 1.5);
+        assert_eq!(tape.aggressor_imbalance(), -0.5);
+        // A t = 2.5 sale la primera operación
+        tape.prune(2.5);
+        assert_eq!(tape.len(), 2);
+        assert_eq!(tape.signed_volume(), -3.0);
+        assert_eq!(tape.aggressor_imbalance(), -1.0);
+        tape.prune(10.0);
+        assert!(tape.is_empty());
+        assert_eq!((tape.vwap(), tape.signed_volume()), (None, 0.0));
+    }
+
+    #[test]
+    fn invalid_trades_are_ignored() {
+        let mut tape = TradeTape::new(5.0);
+        tape.push(trade(0.0, f64::NAN, 1.0, Aggressor::Buy));
+        tape.push(trade(0.0, 1.0, 0.0, Aggressor::Buy));
+        assert!(tape.is_empty());
+        assert_eq!(Aggressor::from_fix(2.0), Aggressor::Sell);
+        assert_eq!(Aggressor::from_fix(7.0).sign(), 0.0);
+    }
+}
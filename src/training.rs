@@ -0,0 +1,707 @@
+use crate::bars::BarSpec;
+use crate::bayesian::{BayesianNetwork, ContextSample};
+use crate::brain::{Activation, BayesianBrain, BrainConfig, Init};
+use crate::calibration::{CalibrationReport, Calibrator, OnlineMetrics};
+use crate::checkpoint::Checkpoint;
+use crate::ensemble::Predictor;
+use crate::features::{FeatureConfig, FeatureSchema};
+use crate::gaussian::TimeAxis;
+use crate::hawkes::{HawkesConfig, HawkesParams};
+use crate::hmm::{GaussianHmm, HmmConfig};
+use crate::kalman::KalmanConfig;
+use crate::kernel::KernelSpec;
+use crate::labels::{Horizon, LabelScheme, Labeler};
+use crate::model::LogisticModel;
+use crate::optim::{LrSchedule, Optimizer};
+use crate::pipeline::{ContextSource, MarketPipeline, NoiseSource, PipelineConfig};
+use crate::recorder::read_recording;
+use crate::regimes::RegimeEdges;
+use crate::scaling::{Clipping, Normalization};
+use crate::velocity::{RateMode, TimeSource};
+use log::info;
+use ndarray::{Array1, Axis};
+use rand::seq::SliceRandom;
+use std::error::Error;
+
+/// Muestra etiquetada del dataset
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub features: Array1<f64>,
+    /// Targets en el formato de la cabeza de la red
+    pub targets: Array1<f64>,
+    /// Resultado en [0, 1] (`LabelScheme::outcome`)
+    pub outcome: f64,
+    pub entry_time: f64,
+    pub exit_time: f64,
+}
+
+/// Muestras etiquetadas junto con el esquema de sus columnas
+#[derive(Debug, Clone)]
+pub struct Dataset {
+    pub schema: FeatureSchema,
+    pub samples: Vec<Sample>,
+}
+
+/// Reproduce una grabación por el mismo pipeline que el motor en vivo y etiqueta
+/// cada vector de features con el horizonte dado. Las muestras quedan en
+/// orden de entrada.
+pub fn build_dataset(
+    messages: &[(f64, String)],
+    pipeline_config: &PipelineConfig,
+    initial_edges: RegimeEdges,
+    scheme: LabelScheme,
+    horizon: Horizon,
+    tick_size: f64,
+    output_dim: usize,
+) -> Dataset {
+    let mut pipeline = MarketPipeline::new(pipeline_config, initial_edges);
+    let mut labeler: Labeler<(Array1<f64>, ContextSample)> =
+        Labeler::new(scheme, horizon, tick_size);
+    let mut samples = Vec::new();
+
+    for (time, msg) in messages {
+        let Some(snap) = pipeline.on_message(msg, *time) else {
+            continue;
+        };
+        if snap.features.is_empty() {
+            continue;
+        }
+        for resolved in labeler.update(snap.mid, snap.time, snap.volume) {
+            let (features, mut ctx) = resolved.payload;
+            // El contexto aprende igual que en vivo
+            ctx.resolve(resolved.entry, resolved.exit, tick_size);
+            pipeline.observe_context(&ctx);
+
+            samples.push(Sample {
+                targets: scheme.encode(resolved.label, output_dim),
+                outcome: scheme.outcome(resolved.label),
+                features,
+                entry_time: resolved.entry_time,
+                exit_time: resolved.exit_time,
+            });
+        }
+        labeler.push((snap.features, snap.context_sample), snap.mid, snap.time);
+    }
+
+    samples.sort_by(|a, b| a.entry_time.total_cmp(&b.entry_time));
+    Dataset {
+        schema: pipeline.collector.schema(),
+        samples,
+    }
+}
+
+/// Partición cronológica train / validación / test
+pub struct WalkForwardSplit<'a> {
+    pub train: &'a [Sample],
+    pub validation: &'a [Sample],
+    pub test: &'a [Sample],
+}
+
+/// Corta por fracciones en orden temporal. Se purgan del final de cada tramo las
+/// muestras cuyo horizonte termina dentro del siguiente (evita fuga de etiquetas).
+pub fn walk_forward_split(
+    samples: &[Sample],
+    train_frac: f64,
+    val_frac: f64,
+) -> WalkForwardSplit<'_> {
+    let n = samples.len();
+    let train_end = ((n as f64 * train_frac) as usize).min(n);
+    let val_end = ((n as f64 * (train_frac + val_frac)) as usize).clamp(train_end, n);
+
+    let purge = |start: usize, end: usize| -> usize {
+        match samples.get(end) {
+            Some(next) => samples[start..end]
+                .iter()
+                .position(|s| s.exit_time >= next.entry_time)
+                .map_or(end, |i| start + i),
+            None => end,
+        }
+    };
+    let train = &samples[..purge(0, train_end)];
+    let validation = &samples[train_end..purge(train_end, val_end)];
+    let test = &samples[val_end..];
+    WalkForwardSplit {
+        train,
+        validation,
+        test,
+    }
+}
+
+/// Métricas prequential de un modelo sobre un tramo (sin entrenar)
+pub fn evaluate(model: &dyn Predictor, samples: &[Sample]) -> CalibrationReport {
+    let mut metrics = OnlineMetrics::new(samples.len().max(1));
+    for s in samples {
+        metrics.observe(model.predict(&s.features).0, s.outcome);
+    }
+    metrics.report(10)
+}
+
+/// Modelo a entrenar offline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OfflineModel {
+    Brain,
+    Logistic,
+}
+
+/// Opciones de `train`
+#[derive(Debug, Clone)]
+pub struct TrainOptions {
+    pub recording: String,
+    pub output: String,
+    pub model: OfflineModel,
+    pub scheme: LabelScheme,
+    pub horizon: Horizon,
+    pub epochs: usize,
+    pub batch_size: usize,
+    /// Épocas sin mejorar en validación antes de parar
+    pub patience: usize,
+    pub train_frac: f64,
+    pub val_frac: f64,
+    /// Capas ocultas, activación e inicialización de la red
+    pub hidden: Vec<usize>,
+    pub activation: Activation,
+    pub init: Init,
+    pub learning_rate: f64,
+    pub optimizer: Optimizer,
+    pub schedule: LrSchedule,
+    pub weight_decay: f64,
+    pub clip_norm: Option<f64>,
+    pub features: FeatureConfig,
+    pub normalization: Normalization,
+    pub clipping: Clipping,
+    pub feature_clipping: Vec<(String, Clipping)>,
+    /// Un vector de features por barra en lugar de por mensaje
+    pub bars: Option<BarSpec>,
+    pub time_source: TimeSource,
+    pub velocity: RateMode,
+    pub hawkes: HawkesConfig,
+    pub hawkes_context: bool,
+    pub context_source: ContextSource,
+    pub hmm: HmmConfig,
+    pub noise_source: NoiseSource,
+    pub kalman: KalmanConfig,
+    pub gp_kernel: KernelSpec,
+    pub gp_time_axis: TimeAxis,
+}
+
+impl TrainOptions {
+    /// `train <grabación> [--out brain.json] [--model brain|logistic] [--labels binary]
+    /// [--horizon 5u] [--epochs 20] [--batch 32] [--patience 3] [--train 0.6] [--val 0.2]
+    /// [--hidden 12 | --hidden 32,16] [--activation sigmoid] [--init normal:0.1] [--lr 0.01] [--optimizer adam] [--schedule cosine:5000:0.0001]
+    /// [--weight-decay 0.0001] [--clip-norm 5] [--features legacy] [--normalization zscore]
+    /// [--clipping winsor:0.01,ofi=clip:3] [--bars 1m] [--time-source sending]
+    /// [--velocity window:1] [--hawkes-params hawkes.json] [--hawkes-context on]
+    /// [--context bayesian|hmm|blend] [--hmm-model hmm.json] [--noise gp|kalman]
+    /// [--kalman trend:200] [--gp-kernel rbf+periodic:60] [--gp-time tick|timestamp]`
+    pub fn from_args(args: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut opts = Self {
+            recording: String::new(),
+            output: "brain.json".to_string(),
+            model: OfflineModel::Brain,
+            scheme: LabelScheme::Binary,
+            horizon: Horizon::Updates(5),
+            epochs: 20,
+            batch_size: 32,
+            patience: 3,
+            train_frac: 0.6,
+            val_frac: 0.2,
+            hidden: vec![12],
+            activation: Activation::Sigmoid,
+            init: Init::Normal(0.1),
+            learning_rate: 0.01,
+            optimizer: Optimizer::sgd(),
+            schedule: LrSchedule::Constant,
+            weight_decay: 0.0,
+            clip_norm: None,
+            features: FeatureConfig::default(),
+            normalization: Normalization::ZScore,
+            clipping: Clipping::None,
+            feature_clipping: Vec::new(),
+            bars: None,
+            time_source: TimeSource::Sending,
+            velocity: RateMode::Window(1.0),
+            hawkes: HawkesConfig::default(),
+            hawkes_context: false,
+            context_source: ContextSource::Bayesian,
+            hmm: HmmConfig::default(),
+            noise_source: NoiseSource::Gp,
+            kalman: KalmanConfig::default(),
+            gp_kernel: KernelSpec::Rbf,
+            gp_time_axis: TimeAxis::TickIndex,
+        };
+
+        let mut it = args.iter();
+        while let Some(arg) = it.next() {
+            if !arg.starts_with("--") {
+                opts.recording = arg.clone();
+                continue;
+            }
+            let value = it
+                .next()
+                .ok_or_else(|| format!("falta el valor de {}", arg))?;
+            match arg.as_str() {
+                "--out" => opts.output = value.clone(),
+                "--model" => {
+                    opts.model = match value.as_str() {
+                        "brain" => OfflineModel::Brain,
+                        "logistic" => OfflineModel::Logistic,
+                        _ => return Err(format!("modelo desconocido: {}", value).into()),
+                    }
+                }
+                "--labels" => {
+                    opts.scheme = LabelScheme::from_name(value)
+                        .ok_or_else(|| format!("esquema desconocido: {}", value))?
+                }
+                "--horizon" => {
+                    opts.horizon = Horizon::parse(value)
+                        .ok_or_else(|| format!("horizonte inválido: {}", value))?
+                }
+                "--epochs" => opts.epochs = value.parse()?,
+                "--batch" => opts.batch_size = value.parse()?,
+                "--patience" => opts.patience = value.parse()?,
+                "--train" => opts.train_frac = value.parse()?,
+                "--val" => opts.val_frac = value.parse()?,
+                "--hidden" => {
+                    opts.hidden = BrainConfig::parse_hidden(value)
+                        .ok_or_else(|| format!("capas ocultas inválidas: {}", value))?
+                }
+                "--activation" => {
+                    opts.activation = Activation::from_name(value)
+                        .ok_or_else(|| format!("activación desconocida: {}", value))?
+                }
+                "--init" => {
+                    opts.init = Init::parse(value)
+                        .ok_or_else(|| format!("inicialización inválida: {}", value))?
+                }
+                "--lr" => opts.learning_rate = value.parse()?,
+                "--optimizer" => {
+                    opts.optimizer = Optimizer::parse(value)
+                        .ok_or_else(|| format!("optimizador desconocido: {}", value))?
+                }
+                "--schedule" => {
+                    opts.schedule = LrSchedule::parse(value)
+                        .ok_or_else(|| format!("calendario de LR inválido: {}", value))?
+                }
+                "--weight-decay" => opts.weight_decay = value.parse()?,
+                "--clip-norm" => opts.clip_norm = Some(value.parse()?),
+                "--features" => opts.features = FeatureConfig::parse(value)?,
+                "--normalization" => {
+                    opts.normalization = Normalization::from_name(value)
+                        .ok_or_else(|| format!("normalización desconocida: {}", value))?
+                }
+                "--clipping" => {
+                    (opts.clipping, opts.feature_clipping) = Clipping::parse_spec(value)
+                        .ok_or_else(|| format!("recorte inválido: {}", value))?
+                }
+                "--bars" => {
+                    opts.bars = Some(
+                        BarSpec::parse(value)
+                            .ok_or_else(|| format!("barra inválida: {}", value))?,
+                    )
+                }
+                "--time-source" => {
+                    opts.time_source = TimeSource::from_name(value)
+                        .ok_or_else(|| format!("reloj desconocido: {}", value))?
+                }
+                "--velocity" => {
+                    opts.velocity = RateMode::parse(value)
+                        .ok_or_else(|| format!("velocidad inválida: {}", value))?
+                }
+                "--hawkes-params" => opts.hawkes.params = Some(HawkesParams::load(value)?),
+                "--hawkes-context" => opts.hawkes_context = value == "on",
+                "--context" => {
+                    opts.context_source = ContextSource::from_name(value)
+                        .ok_or_else(|| format!("contexto desconocido: {}", value))?
+                }
+                "--hmm-model" => opts.hmm.model = Some(GaussianHmm::load(value)?),
+                "--noise" => {
+                    opts.noise_source = NoiseSource::from_name(value)
+                        .ok_or_else(|| format!("fuente de ruido desconocida: {}", value))?
+                }
+                "--kalman" => {
+                    opts.kalman = KalmanConfig::parse(value)
+                        .ok_or_else(|| format!("modelo de Kalman inválido: {}", value))?
+                }
+                "--gp-kernel" => {
+                    opts.gp_kernel = KernelSpec::parse(value)
+                        .ok_or_else(|| format!("kernel inválido: {}", value))?
+                }
+                "--gp-time" => {
+                    opts.gp_time_axis = TimeAxis::from_name(value)
+                        .ok_or_else(|| format!("eje temporal desconocido: {}", value))?
+                }
+                _ => return Err(format!("opción desconocida: {}", arg).into()),
+            }
+        }
+        if opts.recording.is_empty() {
+            return Err(
+                "uso: train <grabación> [--out brain.json] [--model brain|logistic] ...".into(),
+            );
+        }
+        if opts.weight_decay < 0.0 || opts.clip_norm.is_some_and(|c| c <= 0.0) {
+            return Err("--weight-decay debe ser >= 0 y --clip-norm > 0".into());
+        }
+        // Con barras, cada vector lleva la barra que lo cierra
+        if opts.bars.is_some() {
+            opts.features.bar = true;
+        }
+        Ok(opts)
+    }
+
+    /// Configuración de la red equivalente a la del motor en vivo
+    pub fn brain_config(&self) -> BrainConfig {
+        let schema = self.features.schema();
+        let mut config = BrainConfig {
+            hidden: self.hidden.clone(),
+            activation: self.activation,
+            init: self.init,
+            optimizer: self.optimizer,
+            schedule: self.schedule,
+            weight_decay: self.weight_decay,
+            clip_norm: self.clip_norm,
+            ..BrainConfig::legacy(schema.len(), 12, self.learning_rate)
+        }
+        .with_schema(schema);
+        self.scheme.configure(&mut config);
+        config
+    }
+}
+
+/// Épocas de mini-batch sobre `train`, conservando la red con mejor log-loss de validación
+pub fn train_brain(
+    config: BrainConfig,
+    train: &[Sample],
+    validation: &[Sample],
+    epochs: usize,
+    batch_size: usize,
+    patience: usize,
+) -> Result<BayesianBrain, String> {
+    let mut brain = BayesianBrain::from_config(config)?;
+    let mut best = (f64::INFINITY, brain.clone());
+    let mut stale = 0;
+    let mut order: Vec<usize> = (0..train.len()).collect();
+    let mut rng = rand::thread_rng();
+
+    for epoch in 1..=epochs {
+        order.shuffle(&mut rng);
+        for chunk in order.chunks(batch_size.max(1)) {
+            let rows: Vec<_> = chunk.iter().map(|&i| train[i].features.view()).collect();
+            let targets: Vec<_> = chunk.iter().map(|&i| train[i].targets.view()).collect();
+            if let (Ok(inputs), Ok(targets)) = (
+                ndarray::stack(Axis(0), &rows),
+                ndarray::stack(Axis(0), &targets),
+            ) {
+                brain.train_batch(&inputs, &targets);
+            }
+        }
+
+        let val = evaluate(&brain, validation);
+        info!(
+            "Época {}: LogLoss val {:.4} | Brier {:.4} | AUC {}",
+            epoch,
+            val.log_loss,
+            val.brier,
+            val.auc.map_or("-".to_string(), |a| format!("{:.3}", a))
+        );
+        if val.log_loss < best.0 || validation.is_empty() {
+            best = (val.log_loss, brain.clone());
+            stale = 0;
+        } else {
+            stale += 1;
+            if stale >= patience {
+                info!("Parada temprana en la época {}", epoch);
+                break;
+            }
+        }
+    }
+    Ok(best.1)
+}
+
+/// Épocas de SGD por muestra sobre `train`, conservando el mejor en validación
+pub fn train_logistic(
+    input_dim: usize,
+    learning_rate: f64,
+    train: &[Sample],
+    validation: &[Sample],
+    epochs: usize,
+    patience: usize,
+) -> LogisticModel {
+    let mut model = LogisticModel::new(input_dim, learning_rate);
+    let mut best = (f64::INFINITY, model.clone());
+    let mut stale = 0;
+    let mut order: Vec<usize> = (0..train.len()).collect();
+    let mut rng = rand::thread_rng();
+
+    for epoch in 1..=epochs {
+        order.shuffle(&mut rng);
+        for &i in &order {
+            model.train(&train[i].features, train[i].outcome);
+        }
+        let val = evaluate(&model, validation);
+        info!(
+            "Época {}: LogLoss val {:.4} | Brier {:.4}",
+            epoch, val.log_loss, val.brier
+        );
+        if val.log_loss < best.0 || validation.is_empty() {
+            best = (val.log_loss, model.clone());
+            stale = 0;
+        } else {
+            stale += 1;
+            if stale >= patience {
+                info!("Parada temprana en la época {}", epoch);
+                break;
+            }
+        }
+    }
+    best.1
+}
+
+/// Subcomando `train`: grabación -> dataset -> entrenamiento -> checkpoint
+pub fn run(opts: &TrainOptions) -> Result<(), Box<dyn Error>> {
+    let messages = read_recording(&opts.recording)?;
+    info!("Grabación {}: {} mensajes", opts.recording, messages.len());
+
+    let config = opts.brain_config();
+    let dataset = build_dataset(
+        &messages,
+        &PipelineConfig {
+            features: opts.features.clone(),
+            normalization: opts.normalization,
+            clipping: opts.clipping,
+            feature_clipping: opts.feature_clipping.clone(),
+            bars: opts.bars,
+            time_source: opts.time_source,
+            velocity: opts.velocity,
+            hawkes: opts.hawkes.clone(),
+            hawkes_context: opts.hawkes_context,
+            context_source: opts.context_source,
+            hmm: opts.hmm.clone(),
+            noise_source: opts.noise_source,
+            kalman: opts.kalman,
+            gp_kernel: opts.gp_kernel.clone(),
+            gp_time_axis: opts.gp_time_axis,
+            ..PipelineConfig::default()
+        },
+        BayesianNetwork::default_edges(),
+        opts.scheme,
+        opts.horizon,
+        opts.features.tick_size,
+        config.output_dim,
+    );
+    info!("Columnas: {}", dataset.schema.columns.join(", "));
+    let samples = dataset.samples;
+    let split = walk_forward_split(&samples, opts.train_frac, opts.val_frac);
+    info!(
+        "Dataset: {} muestras | train {} | val {} | test {}",
+        samples.len(),
+        split.train.len(),
+        split.validation.len(),
+        split.test.len()
+    );
+    if split.train.is_empty() {
+        return Err("dataset vacío: la grabación no produce muestras etiquetadas".into());
+    }
+
+    let checkpoint = match opts.model {
+        OfflineModel::Brain => {
+            let brain = train_brain(
+                config,
+                split.train,
+                split.validation,
+                opts.epochs,
+                opts.batch_size,
+                opts.patience,
+            )?;
+            let test = evaluate(&brain, split.test);
+            Checkpoint::new(
+                opts.scheme,
+                opts.horizon,
+                Some(brain),
+                Calibrator::None,
+                Some(test),
+            )
+        }
+        OfflineModel::Logistic => {
+            let model = train_logistic(
+                config.input_dim,
+                opts.learning_rate,
+                split.train,
+                split.validation,
+                opts.epochs,
+                opts.patience,
+            );
+            let test = evaluate(&model, split.test);
+            // Sin red: el motor usará el logístico como único predictor
+            let mut checkpoint = Checkpoint::new(
+                opts.scheme,
+                opts.horizon,
+                None,
+                Calibrator::None,
+                Some(test),
+            );
+            checkpoint.logistic = Some(model);
+            checkpoint
+        }
+    };
+
+    if let Some(test) = &checkpoint.metrics {
+        info!(
+            "Test (fuera de muestra): n={} | LogLoss {:.4} | Brier {:.4} | AUC {} | ECE {:.3}",
+            test.samples,
+            test.log_loss,
+            test.brier,
+            test.auc.map_or("-".to_string(), |a| format!("{:.3}", a)),
+            test.ece
+        );
+    }
+    checkpoint.save(&opts.output)?;
+    info!("Checkpoint guardado en {}", opts.output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::MarketRecorder;
+
+    /// Muestras con entrada en t = i y salida en t = i + horizon
+    fn samples(n: usize, horizon: f64) -> Vec<Sample> {
+        (0..n)
+            .map(|i| Sample {
+                features: Array1::from_elem(1, i as f64),
+                targets: Array1::zeros(1),
+                outcome: 0.0,
+                entry_time: i as f64,
+                exit_time: i as f64 + horizon,
+            })
+            .collect()
+    }
+
+    fn entries(slice: &[Sample]) -> Vec<f64> {
+        slice.iter().map(|s| s.entry_time).collect()
+    }
+
+    #[test]
+    fn split_without_overlap_keeps_every_sample() {
+        let data = samples(10, 0.5);
+        let split = walk_forward_split(&data, 0.6, 0.2);
+        assert_eq!(entries(split.train), vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(entries(split.validation), vec![6.0, 7.0]);
+        assert_eq!(entries(split.test), vec![8.0, 9.0]);
+    }
+
+    #[test]
+    fn split_purges_labels_that_end_in_the_next_fold() {
+        // Horizonte 1.5: la muestra 5 sale en 6.5, dentro de validación (entra en 6)
+        let data = samples(10, 1.5);
+        let split = walk_forward_split(&data, 0.6, 0.2);
+        assert_eq!(entries(split.train), vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(entries(split.validation), vec![6.0]);
+        assert_eq!(entries(split.test), vec![8.0, 9.0]);
+        let first_val = split.validation[0].entry_time;
+        assert!(split.train.iter().all(|s| s.exit_time < first_val));
+    }
+
+    #[test]
+    fn split_handles_degenerate_fractions() {
+        let data = samples(4, 10.0);
+        let split = walk_forward_split(&data, 1.0, 0.5);
+        assert_eq!(split.train.len(), 4);
+        assert!(split.validation.is_empty() && split.test.is_empty());
+        assert!(walk_forward_split(&[], 0.6, 0.2).train.is_empty());
+    }
+
+    #[test]
+    fn logistic_learns_a_separable_problem() {
+        let data: Vec<Sample> = (0..200)
+            .map(|i| {
+                let x = i as f64 / 100.0 - 1.0;
+                Sample {
+                    features: Array1::from_elem(1, x),
+                    targets: Array1::zeros(1),
+                    outcome: f64::from(u8::from(x > 0.0)),
+                    entry_time: i as f64,
+                    exit_time: i as f64,
+                }
+            })
+            .collect();
+        let model = train_logistic(1, 0.5, &data, &data, 50, 50);
+        let report = evaluate(&model, &data);
+        assert_eq!(report.auc, Some(1.0));
+        assert!(report.log_loss < 0.2, "log-loss {}", report.log_loss);
+    }
+
+    /// Mensaje 35=X con el mejor bid/ask a un tick de `mid`
+    fn quote(mid: f64) -> String {
+        format!(
+            "|35=X|55=1|279=0|269=0|270={:.5}|271=100|279=0|269=1|270={:.5}|271=100|",
+            mid - 0.00001,
+            mid + 0.00001
+        )
+    }
+
+    #[test]
+    fn dataset_replays_a_recording_in_entry_order() {
+        let path = std::env::temp_dir().join(format!("dataset-{}.rec", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut recorder = MarketRecorder::open(path).unwrap();
+        for i in 0..60 {
+            let mid = 1.1 + 0.00002 * ((i % 7) as f64 - 3.0);
+            recorder
+                .record(1_700_000_000.0 + i as f64 * 0.1, &quote(mid))
+                .unwrap();
+        }
+        recorder.flush().unwrap();
+        let messages = read_recording(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(messages.len(), 60);
+        assert_eq!(messages[0].1, quote(1.09994));
+
+        let dataset = build_dataset(
+            &messages,
+            &PipelineConfig::default(),
+            BayesianNetwork::default_edges(),
+            LabelScheme::Binary,
+            Horizon::Updates(2),
+            0.00001,
+            1,
+        );
+        assert!(!dataset.samples.is_empty());
+        assert!(dataset
+            .samples
+            .windows(2)
+            .all(|w| w[0].entry_time <= w[1].entry_time));
+        for s in &dataset.samples {
+            assert_eq!(s.features.len(), dataset.schema.len());
+            assert!(s.exit_time > s.entry_time);
+        }
+    }
+
+    #[test]
+    fn options_parse_flags_and_reject_bad_values() {
+        let args: Vec<String> = [
+            "rec.rec",
+            "--horizon",
+            "5s",
+            "--hidden",
+            "16",
+            "--model",
+            "logistic",
+            "--bars",
+            "tick:50",
+        ]
+        .map(String::from)
+        .to_vec();
+        let opts = TrainOptions::from_args(&args).unwrap();
+        assert_eq!(opts.recording, "rec.rec");
+        assert_eq!(opts.horizon, Horizon::Seconds(5.0));
+        assert_eq!(
+            (opts.hidden, opts.model),
+            (vec![16], OfflineModel::Logistic)
+        );
+        // Con barras, las columnas de la barra se activan solas
+        assert!(opts.features.bar);
+        let bad = ["rec.rec", "--horizon", "5x"].map(String::from).to_vec();
+        assert!(TrainOptions::from_args(&bad).is_err());
+    }
+}
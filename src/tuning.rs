@@ -0,0 +1,586 @@
+use crate::backtest::{run_backtest, BacktestConfig, FoldMetrics};
+use crate::brain::BrainConfig;
+use crate::decision::SignalThresholds;
+use crate::features::FeatureConfig;
+use crate::labels::{Horizon, LabelScheme};
+use crate::optim::{LrSchedule, Optimizer};
+use crate::pipeline::{ContextSource, PipelineConfig};
+use crate::recorder::read_recording;
+use crate::scaling::Normalization;
+use log::{info, warn};
+use rand::seq::SliceRandom;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+
+/// Valores candidatos de cada hiperparámetro
+#[derive(Debug, Clone)]
+pub struct SearchSpace {
+    pub hidden: Vec<usize>,
+    pub learning_rate: Vec<f64>,
+    pub feature_window: Vec<usize>,
+    pub gp_window: Vec<usize>,
+    pub context_threshold: Vec<f64>,
+    /// Corte de compra; el de venta es simétrico (1 - buy_above)
+    pub buy_above: Vec<f64>,
+    /// Puntuación de contexto: varias para compararlas con los mismos tramos
+    pub context_source: Vec<ContextSource>,
+}
+
+impl Default for SearchSpace {
+    /// Alrededor de los valores del motor (12, 0.01, 100, 20, 0.45, 0.75)
+    fn default() -> Self {
+        Self {
+            hidden: vec![8, 12, 16],
+            learning_rate: vec![0.003, 0.01, 0.03],
+            feature_window: vec![50, 100, 200],
+            gp_window: vec![10, 20, 40],
+            context_threshold: vec![0.35, 0.45, 0.55],
+            buy_above: vec![0.65, 0.75, 0.85],
+            context_source: vec![ContextSource::Bayesian],
+        }
+    }
+}
+
+/// Una combinación de hiperparámetros
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TrialParams {
+    pub hidden: usize,
+    pub learning_rate: f64,
+    pub feature_window: usize,
+    pub gp_window: usize,
+    pub context_threshold: f64,
+    pub buy_above: f64,
+    pub context_source: ContextSource,
+}
+
+impl TrialParams {
+    fn apply(&self, base: &BacktestConfig) -> BacktestConfig {
+        let mut config = base.clone();
+        self.apply_to(
+            &mut config.pipeline,
+            &mut config.brain,
+            &mut config.thresholds,
+        );
+        config
+    }
+
+    /// Fija los parámetros en las configuraciones del motor (backtest o en vivo)
+    pub fn apply_to(
+        &self,
+        pipeline: &mut PipelineConfig,
+        brain: &mut BrainConfig,
+        thresholds: &mut SignalThresholds,
+    ) {
+        brain.hidden = vec![self.hidden];
+        brain.learning_rate = self.learning_rate;
+        pipeline.feature_window = self.feature_window;
+        pipeline.gp_window = self.gp_window;
+        pipeline.context_threshold = self.context_threshold;
+        pipeline.context_source = self.context_source;
+        thresholds.buy_above = self.buy_above;
+        thresholds.sell_below = 1.0 - self.buy_above;
+    }
+
+    /// Mejor combinación de un resultado de `tune` (el primero de la lista)
+    pub fn load_best(path: &str) -> Result<Self, Box<dyn Error>> {
+        let results: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let best = results
+            .get(0)
+            .and_then(|r| r.get("params"))
+            .ok_or_else(|| format!("{}: sin combinaciones", path))?;
+        Ok(Self::deserialize(best)?)
+    }
+}
+
+impl SearchSpace {
+    /// Producto cartesiano de todos los candidatos
+    pub fn grid(&self) -> Vec<TrialParams> {
+        let mut trials = Vec::new();
+        for &hidden in &self.hidden {
+            for &learning_rate in &self.learning_rate {
+                for &feature_window in &self.feature_window {
+                    for &gp_window in &self.gp_window {
+                        for &context_threshold in &self.context_threshold {
+                            for &buy_above in &self.buy_above {
+                                for &context_source in &self.context_source {
+                                    trials.push(TrialParams {
+                                        hidden,
+                                        learning_rate,
+                                        feature_window,
+                                        gp_window,
+                                        context_threshold,
+                                        buy_above,
+                                        context_source,
+                                    });
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        trials
+    }
+
+    /// `n` combinaciones distintas de la rejilla elegidas al azar, cada una
+    /// evaluada con todas las fuentes de contexto (comparación pareada)
+    pub fn random(&self, n: usize) -> Vec<TrialParams> {
+        let Some(&first) = self.context_source.first() else {
+            return Vec::new();
+        };
+        let mut trials = Self {
+            context_source: vec![first],
+            ..self.clone()
+        }
+        .grid();
+        trials.shuffle(&mut rand::thread_rng());
+        trials.truncate(n);
+        trials
+            .into_iter()
+            .flat_map(|t| {
+                self.context_source
+                    .iter()
+                    .map(move |&context_source| TrialParams {
+                        context_source,
+                        ..t
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Qué se optimiza en los tramos de validación
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Objective {
+    /// Menor log-loss medio (calidad de la probabilidad)
+    LogLoss,
+    /// Mayor PnL medio de las señales en ticks
+    Pnl,
+}
+
+/// Resultado de una combinación
+#[derive(Debug, Clone, Serialize)]
+pub struct TrialResult {
+    pub params: TrialParams,
+    /// Media del objetivo en los tramos de validación (menor es mejor)
+    pub score: f64,
+    /// Desviación del objetivo entre tramos (estabilidad)
+    pub score_std: f64,
+    pub validation: Vec<FoldMetrics>,
+    /// Último tramo: nunca usado para elegir
+    pub test: FoldMetrics,
+}
+
+fn objective_value(objective: Objective, fold: &FoldMetrics) -> f64 {
+    match objective {
+        Objective::LogLoss => fold.log_loss,
+        Objective::Pnl => -fold.pnl_ticks,
+    }
+}
+
+/// Evalúa cada combinación con el backtest walk-forward, en paralelo.
+/// Tramos: el primero es calentamiento (modelo sin entrenar), los intermedios
+/// puntúan y el último queda como test fuera de muestra. Ordenado de mejor a peor.
+pub fn search(
+    messages: &[(f64, String)],
+    base: &BacktestConfig,
+    trials: Vec<TrialParams>,
+    objective: Objective,
+) -> Vec<TrialResult> {
+    let mut results: Vec<TrialResult> = trials
+        .into_par_iter()
+        .filter_map(|params| {
+            let mut folds = match run_backtest(messages, &params.apply(base)) {
+                Ok(folds) => folds,
+                Err(e) => {
+                    warn!("Combinación {:?} descartada: {}", params, e);
+                    return None;
+                }
+            };
+            let test = folds.pop()?;
+            let validation: Vec<FoldMetrics> = folds.into_iter().skip(1).collect();
+            if validation.is_empty() {
+                return None;
+            }
+            let values: Vec<f64> = validation
+                .iter()
+                .map(|f| objective_value(objective, f))
+                .collect();
+            let score = values.iter().sum::<f64>() / values.len() as f64;
+            let score_std = (values.iter().map(|v| (v - score).powi(2)).sum::<f64>()
+                / values.len() as f64)
+                .sqrt();
+            Some(TrialResult {
+                params,
+                score,
+                score_std,
+                validation,
+                test,
+            })
+        })
+        .collect();
+    results.sort_by(|a, b| a.score.total_cmp(&b.score));
+    results
+}
+
+/// Opciones de `tune`
+#[derive(Debug, Clone)]
+pub struct TuneOptions {
+    pub recording: String,
+    pub output: String,
+    /// None = rejilla completa
+    pub random: Option<usize>,
+    pub folds: usize,
+    pub top: usize,
+    pub objective: Objective,
+    /// Fuentes de contexto a comparar (A/B con los mismos tramos)
+    pub context_sources: Vec<ContextSource>,
+    /// Features, etiquetado y normalización, comunes a todas las combinaciones
+    pub features: FeatureConfig,
+    pub scheme: LabelScheme,
+    pub horizon: Horizon,
+    pub normalization: Normalization,
+    /// Optimización de la red, común a todas las combinaciones
+    pub optimizer: Optimizer,
+    pub schedule: LrSchedule,
+    pub weight_decay: f64,
+    pub clip_norm: Option<f64>,
+}
+
+impl TuneOptions {
+    /// `tune <grabación> [--random 50] [--folds 5] [--top 5] [--objective log_loss|pnl]
+    /// [--context bayesian,hmm] [--features micro] [--labels binary] [--horizon 5u]
+    /// [--normalization zscore] [--optimizer adam] [--schedule cosine:5000:0.0001]
+    /// [--weight-decay 0.0001] [--clip-norm 5] [--out tuning.json]`
+    pub fn from_args(args: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut opts = Self {
+            recording: String::new(),
+            output: "tuning.json".to_string(),
+            random: None,
+            folds: 5,
+            top: 5,
+            objective: Objective::LogLoss,
+            context_sources: vec![ContextSource::Bayesian],
+            features: FeatureConfig::default(),
+            scheme: LabelScheme::Binary,
+            horizon: Horizon::Updates(5),
+            normalization: Normalization::ZScore,
+            optimizer: Optimizer::sgd(),
+            schedule: LrSchedule::Constant,
+            weight_decay: 0.0,
+            clip_norm: None,
+        };
+
+        let mut it = args.iter();
+        while let Some(arg) = it.next() {
+            if !arg.starts_with("--") {
+                opts.recording = arg.clone();
+                continue;
+            }
+            let value = it
+                .next()
+                .ok_or_else(|| format!("falta el valor de {}", arg))?;
+            match arg.as_str() {
+                "--out" => opts.output = value.clone(),
+                "--random" => opts.random = Some(value.parse()?),
+                "--folds" => opts.folds = value.parse()?,
+                "--top" => opts.top = value.parse()?,
+                "--objective" => {
+                    opts.objective = match value.as_str() {
+                        "log_loss" => Objective::LogLoss,
+                        "pnl" => Objective::Pnl,
+                        _ => return Err(format!("objetivo desconocido: {}", value).into()),
+                    }
+                }
+                "--context" => {
+                    opts.context_sources = value
+                        .split(',')
+                        .map(|name| {
+                            ContextSource::from_name(name.trim())
+                                .ok_or_else(|| format!("contexto desconocido: {}", name))
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+                "--features" => opts.features = FeatureConfig::parse(value)?,
+                "--labels" => {
+                    opts.scheme = LabelScheme::from_name(value)
+                        .ok_or_else(|| format!("esquema desconocido: {}", value))?
+                }
+                "--horizon" => {
+                    opts.horizon = Horizon::parse(value)
+                        .ok_or_else(|| format!("horizonte inválido: {}", value))?
+                }
+                "--normalization" => {
+                    opts.normalization = Normalization::from_name(value)
+                        .ok_or_else(|| format!("normalización desconocida: {}", value))?
+                }
+                "--optimizer" => {
+                    opts.optimizer = Optimizer::parse(value)
+                        .ok_or_else(|| format!("optimizador desconocido: {}", value))?
+                }
+                "--schedule" => {
+                    opts.schedule = LrSchedule::parse(value)
+                        .ok_or_else(|| format!("calendario de LR inválido: {}", value))?
+                }
+                "--weight-decay" => opts.weight_decay = value.parse()?,
+                "--clip-norm" => opts.clip_norm = Some(value.parse()?),
+                _ => return Err(format!("opción desconocida: {}", arg).into()),
+            }
+        }
+        if opts.recording.is_empty() {
+            return Err(
+                "uso: tune <grabación> [--random 50] [--folds 5] [--objective log_loss|pnl] ..."
+                    .into(),
+            );
+        }
+        if opts.folds < 3 {
+            return Err("se necesitan al menos 3 tramos (calentamiento, validación, test)".into());
+        }
+        if opts.weight_decay < 0.0 || opts.clip_norm.is_some_and(|c| c <= 0.0) {
+            return Err("--weight-decay debe ser >= 0 y --clip-norm > 0".into());
+        }
+        Ok(opts)
+    }
+}
+
+/// Subcomando `tune`: búsqueda y resumen de las mejores combinaciones
+pub fn run(opts: &TuneOptions) -> Result<(), Box<dyn Error>> {
+    let messages = read_recording(&opts.recording)?;
+    let space = SearchSpace {
+        context_source: opts.context_sources.clone(),
+        ..SearchSpace::default()
+    };
+    let trials = match opts.random {
+        Some(n) => space.random(n),
+        None => space.grid(),
+    };
+    info!(
+        "Búsqueda: {} combinaciones sobre {} mensajes, {} tramos, {} hilos",
+        trials.len(),
+        messages.len(),
+        opts.folds,
+        rayon::current_num_threads()
+    );
+
+    let schema = opts.features.schema();
+    info!("Columnas: {}", schema.columns.join(", "));
+    let mut brain = BrainConfig {
+        optimizer: opts.optimizer,
+        schedule: opts.schedule,
+        weight_decay: opts.weight_decay,
+        clip_norm: opts.clip_norm,
+        ..BrainConfig::legacy(schema.len(), 12, 0.01)
+    }
+    .with_schema(schema);
+    opts.scheme.configure(&mut brain);
+    let base = BacktestConfig {
+        pipeline: PipelineConfig {
+            features: opts.features.clone(),
+            normalization: opts.normalization,
+            ..PipelineConfig::default()
+        },
+        brain,
+        scheme: opts.scheme,
+        horizon: opts.horizon,
+        tick_size: opts.features.tick_size,
+        folds: opts.folds,
+        ..BacktestConfig::default()
+    };
+    let results = search(&messages, &base, trials, opts.objective);
+
+    for (rank, r) in results.iter().take(opts.top).enumerate() {
+        info!(
+            "#{} {:?} | val {:.4} ± {:.4} | test LogLoss {:.4} AUC {} señales {} acierto {:.1}% PnL {:+.1} ticks",
+            rank + 1,
+            r.params,
+            r.score,
+            r.score_std,
+            r.test.log_loss,
+            r.test.auc.map_or("-".to_string(), |a| format!("{:.3}", a)),
+            r.test.signals,
+            r.test.hit_rate * 100.0,
+            r.test.pnl_ticks
+        );
+    }
+    if opts.context_sources.len() > 1 {
+        for &source in &opts.context_sources {
+            let runs: Vec<&TrialResult> = results
+                .iter()
+                .filter(|r| r.params.context_source == source)
+                .collect();
+            let n = runs.len().max(1) as f64;
+            info!(
+                "Contexto {:?}: {} combinaciones | val media {:.4} | test PnL medio {:+.1} ticks",
+                source,
+                runs.len(),
+                runs.iter().map(|r| r.score).sum::<f64>() / n,
+                runs.iter().map(|r| r.test.pnl_ticks).sum::<f64>() / n
+            );
+        }
+    }
+    let top: Vec<&TrialResult> = results.iter().take(opts.top).collect();
+    fs::write(&opts.output, serde_json::to_string_pretty(&top)?)?;
+    info!("Resultados guardados en {}", opts.output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording(n: usize) -> Vec<(f64, String)> {
+        (0..n)
+            .map(|i| {
+                let mid = 1.1 + 0.00002 * ((i % 7) as f64 - 3.0);
+                let msg = format!(
+                    "|35=X|55=1|279=0|269=0|270={:.5}|271=100|279=0|269=1|270={:.5}|271=100|",
+                    mid - 0.00001,
+                    mid + 0.00001
+                );
+                (1_700_000_000.0 + i as f64 * 0.1, msg)
+            })
+            .collect()
+    }
+
+    fn small_space() -> SearchSpace {
+        SearchSpace {
+            hidden: vec![4, 8],
+            learning_rate: vec![0.01, 0.1],
+            feature_window: vec![50],
+            gp_window: vec![10],
+            context_threshold: vec![0.45],
+            buy_above: vec![0.6, 0.7, 0.8],
+            context_source: vec![ContextSource::Bayesian],
+        }
+    }
+
+    #[test]
+    fn random_draws_distinct_grid_points() {
+        let space = small_space();
+        assert_eq!(space.grid().len(), 12);
+        let trials = space.random(5);
+        assert_eq!(trials.len(), 5);
+        for (i, a) in trials.iter().enumerate() {
+            for b in &trials[i + 1..] {
+                assert!(
+                    (a.hidden, a.learning_rate, a.buy_above)
+                        != (b.hidden, b.learning_rate, b.buy_above)
+                );
+            }
+        }
+        assert_eq!(space.random(100).len(), 12);
+    }
+
+    #[test]
+    fn random_pairs_every_draw_across_context_sources() {
+        let sources = [ContextSource::Bayesian, ContextSource::Hmm];
+        let space = SearchSpace {
+            context_source: sources.to_vec(),
+            ..small_space()
+        };
+        assert_eq!(space.grid().len(), 24);
+        let trials = space.random(4);
+        assert_eq!(trials.len(), 8);
+        for pair in trials.chunks(2) {
+            assert_eq!([pair[0].context_source, pair[1].context_source], sources);
+            let rest = |t: &TrialParams| (t.hidden, t.learning_rate, t.buy_above);
+            assert_eq!(rest(&pair[0]), rest(&pair[1]));
+        }
+        let empty = SearchSpace {
+            context_source: Vec::new(),
+            ..small_space()
+        };
+        assert!(empty.random(4).is_empty());
+    }
+
+    #[test]
+    fn apply_sets_symmetric_thresholds() {
+        let params = small_space().grid()[2];
+        let config = params.apply(&BacktestConfig::default());
+        assert_eq!(config.brain.hidden, vec![4]);
+        assert_eq!(config.pipeline.feature_window, 50);
+        assert_eq!(config.thresholds.buy_above, 0.8);
+        assert!((config.thresholds.sell_below - 0.2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn search_scores_the_middle_folds_and_holds_out_the_last() {
+        let messages = recording(60);
+        let base = BacktestConfig {
+            folds: 4,
+            ..BacktestConfig::default()
+        };
+        let trials = small_space().grid()[..2].to_vec();
+        let results = search(&messages, &base, trials, Objective::LogLoss);
+        assert_eq!(results.len(), 2);
+        assert!(results[0].score <= results[1].score);
+        for r in &results {
+            let folds: Vec<usize> = r.validation.iter().map(|f| f.fold).collect();
+            assert_eq!((folds, r.test.fold), (vec![1, 2], 3));
+            let mean = (r.validation[0].log_loss + r.validation[1].log_loss) / 2.0;
+            assert!((r.score - mean).abs() < 1e-12);
+        }
+
+        // Con dos tramos no queda validación
+        let base = BacktestConfig { folds: 2, ..base };
+        let trials = small_space().grid()[..1].to_vec();
+        assert!(search(&messages, &base, trials, Objective::Pnl).is_empty());
+    }
+
+    #[test]
+    fn options_require_three_folds_and_a_known_objective() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let opts = TuneOptions::from_args(&args(&["rec.rec", "--random", "20"])).unwrap();
+        assert_eq!((opts.random, opts.folds), (Some(20), 5));
+        assert_eq!(opts.objective, Objective::LogLoss);
+        assert!(TuneOptions::from_args(&args(&["rec.rec", "--folds", "2"])).is_err());
+        assert!(TuneOptions::from_args(&args(&["rec.rec", "--objective", "sharpe"])).is_err());
+        assert!(TuneOptions::from_args(&args(&["--top", "3"])).is_err());
+    }
+
+    #[test]
+    fn load_best_reads_the_first_result() {
+        let path = std::env::temp_dir().join(format!("tuning-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let trials = small_space().grid();
+        let params = |t: &TrialParams| serde_json::json!({ "params": t, "score": 0.5 });
+        let results = serde_json::json!([params(&trials[3]), params(&trials[0])]);
+        fs::write(path, results.to_string()).unwrap();
+        let best = TrialParams::load_best(path).unwrap();
+        assert_eq!(
+            (best.hidden, best.learning_rate, best.buy_above),
+            (
+                trials[3].hidden,
+                trials[3].learning_rate,
+                trials[3].buy_above
+            )
+        );
+
+        fs::write(path, "[]").unwrap();
+        let err = TrialParams::load_best(path).err().unwrap().to_string();
+        fs::remove_file(path).unwrap();
+        assert!(err.ends_with("sin combinaciones"), "{}", err);
+        assert!(TrialParams::load_best(path).is_err());
+    }
+
+    #[test]
+    fn options_set_features_labels_and_horizon() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let opts = TuneOptions::from_args(&args(&[
+            "rec.rec",
+            "--features",
+            "micro",
+            "--horizon",
+            "10u",
+            "--normalization",
+            "median_mad",
+        ]))
+        .unwrap();
+        assert_eq!(opts.features, FeatureConfig::microstructure());
+        assert_eq!(opts.horizon, Horizon::Updates(10));
+        assert_eq!(opts.normalization, Normalization::MedianMad);
+        assert!(TuneOptions::from_args(&args(&["rec.rec", "--labels", "quintiles"])).is_err());
+    }
+}
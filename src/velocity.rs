@@ -0,0 +1,321 @@
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use std::collections::VecDeque;
+
+/// Reloj con el que se fechan los eventos del libro
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeSource {
+    /// Hora de recepción (o la grabada junto al mensaje)
+    Receive,
+    /// SendingTime (52) de la cabecera
+    Sending,
+    /// MDEntryTime (273) de cada entrada, con SendingTime como respaldo
+    Entry,
+}
+
+impl TimeSource {
+    /// "receive", "sending", "entry"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "receive" => Some(TimeSource::Receive),
+            "sending" => Some(TimeSource::Sending),
+            "entry" => Some(TimeSource::Entry),
+            _ => None,
+        }
+    }
+}
+
+/// UTCTimestamp FIX ("YYYYMMDD-HH:MM:SS[.sss]") en segundos unix
+pub fn parse_utc_timestamp(value: &str) -> Option<f64> {
+    let dt = NaiveDateTime::parse_from_str(value, "%Y%m%d-%H:%M:%S%.f").ok()?;
+    Some(dt.and_utc().timestamp_micros() as f64 / 1e6)
+}
+
+/// MDEntryTime (273, "HH:MM:SS[.sss]") con MDEntryDate (272, "YYYYMMDD") o, si
+/// falta, el día de `reference` (segundos unix); se corrige el cruce de medianoche
+pub fn parse_entry_time(time: &str, date: Option<&str>, reference: f64) -> Option<f64> {
+    let t = NaiveTime::parse_from_str(time, "%H:%M:%S%.f").ok()?;
+    let day = match date {
+        Some(d) => NaiveDate::parse_from_str(d, "%Y%m%d").ok()?,
+        None => chrono::DateTime::from_timestamp(reference.floor() as i64, 0)?.date_naive(),
+    };
+    let secs = day.and_time(t).and_utc().timestamp_micros() as f64 / 1e6;
+    if date.is_some() {
+        return Some(secs);
+    }
+    // Una entrada de justo antes de medianoche recibida después de ella
+    Some(if secs - reference > 43200.0 {
+        secs - 86400.0
+    } else if reference - secs > 43200.0 {
+        secs + 86400.0
+    } else {
+        secs
+    })
+}
+
+/// Valor textual de un tag en un mensaje con separador '|'
+pub fn tag_value<'a>(msg: &'a str, tag: &str) -> Option<&'a str> {
+    let pattern = format!("|{}=", tag);
+    let start = msg.find(&pattern)? + pattern.len();
+    let len = msg[start..].find('|').unwrap_or(msg.len() - start);
+    Some(&msg[start..start + len])
+}
+
+/// Cómo se convierte la serie de eventos en una tasa (eventos por segundo)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateMode {
+    /// Eventos de los últimos N segundos / N
+    Window(f64),
+    /// Conteo con decaimiento exponencial de semivida en segundos
+    Decay(f64),
+}
+
+impl RateMode {
+    /// "window:1", "decay:0.5" (segundos, finitos y positivos)
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (kind, secs) = spec.split_once(':').unwrap_or((spec, "1"));
+        let secs: f64 = secs
+            .parse()
+            .ok()
+            .filter(|s: &f64| s.is_finite() && *s > 0.0)?;
+        match kind {
+            "window" => Some(RateMode::Window(secs)),
+            "decay" => Some(RateMode::Decay(secs)),
+            _ => None,
+        }
+    }
+}
+
+/// Tasa de llegada de un tipo de evento
+#[derive(Debug, Clone)]
+pub struct EventRate {
+    mode: RateMode,
+    /// Ventana deslizante: instantes de los eventos
+    times: VecDeque<f64>,
+    /// Decaimiento: intensidad tras el último evento y su instante
+    level: f64,
+    last: Option<f64>,
+}
+
+impl EventRate {
+    pub fn new(mode: RateMode) -> Self {
+        Self {
+            mode,
+            times: VecDeque::new(),
+            level: 0.0,
+            last: None,
+        }
+    }
+
+    /// Evento en `time`; un reloj que retrocede se trata como simultáneo
+    pub fn record(&mut self, time: f64) {
+        let time = self.last.map_or(time, |last| time.max(last));
+        match self.mode {
+            RateMode::Window(window) => {
+                self.times.push_back(time);
+                self.evict(time, window);
+            }
+            RateMode::Decay(halflife) => {
+                let tau = halflife / std::f64::consts::LN_2;
+                self.level = self.decayed(time, tau) + 1.0 / tau;
+            }
+        }
+        self.last = Some(time);
+    }
+
+    /// Eventos por segundo en `now`
+    pub fn rate(&mut self, now: f64) -> f64 {
+        let now = self.last.map_or(now, |last| now.max(last));
+        match self.mode {
+            RateMode::Window(window) => {
+                self.evict(now, window);
+                self.times.len() as f64 / window
+            }
+            RateMode::Decay(halflife) => self.decayed(now, halflife / std::f64::consts::LN_2),
+        }
+    }
+
+    fn evict(&mut self, now: f64, window: f64) {
+        while self.times.front().is_some_and(|&t| now - t >= window) {
+            self.times.pop_front();
+        }
+    }
+
+    fn decayed(&self, now: f64, tau: f64) -> f64 {
+        match self.last {
+            Some(last) => self.level * (-(now - last) / tau).exp(),
+            None => 0.0,
+        }
+    }
+}
+
+/// Tipo de entrada de market data que cuenta la velocidad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Bid,
+    Ask,
+    Trade,
+}
+
+/// Tasas por tipo de evento en un instante
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VelocityRates {
+    pub total: f64,
+    pub bid: f64,
+    pub ask: f64,
+    pub trade: f64,
+}
+
+/// Velocidad de ticks fechada con los timestamps de los mensajes: reproducible
+/// en backtest e insensible a los retrasos de procesado
+#[derive(Debug, Clone)]
+pub struct TickVelocity {
+    total: EventRate,
+    bid: EventRate,
+    ask: EventRate,
+    trade: EventRate,
+}
+
+impl TickVelocity {
+    pub fn new(mode: RateMode) -> Self {
+        Self {
+            total: EventRate::new(mode),
+            bid: EventRate::new(mode),
+            ask: EventRate::new(mode),
+            trade: EventRate::new(mode),
+        }
+    }
+
+    pub fn record(&mut self, kind: EventKind, time: f64) {
+        self.total.record(time);
+        match kind {
+            EventKind::Bid => self.bid.record(time),
+            EventKind::Ask => self.ask.record(time),
+            EventKind::Trade => self.trade.record(time),
+        }
+    }
+
+    pub fn rates(&mut self, now: f64) -> VelocityRates {
+        VelocityRates {
+            total: self.total.rate(now),
+            bid: self.bid.rate(now),
+            ask: self.ask.rate(now),
+            trade: self.trade.rate(now),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-01-02 00:00:00 UTC
+    const DAY: f64 = 1_704_153_600.0;
+
+    #[test]
+    fn utc_timestamps_with_and_without_millis() {
+        assert_eq!(
+            parse_utc_timestamp("20240102-03:04:05.250"),
+            Some(DAY + 11045.25)
+        );
+        assert_eq!(parse_utc_timestamp("20240102-00:00:00"), Some(DAY));
+        assert_eq!(parse_utc_timestamp("2024-01-02 00:00:00"), None);
+    }
+
+    #[test]
+    fn entry_time_takes_the_day_of_the_reference_across_midnight() {
+        // Con fecha explícita no se corrige nada
+        assert_eq!(
+            parse_entry_time("23:59:59", Some("20240102"), DAY + 5.0),
+            Some(DAY + 86399.0)
+        );
+        // Entrada de antes de medianoche recibida a las 00:00:05
+        assert_eq!(
+            parse_entry_time("23:59:59.5", None, DAY + 5.0),
+            Some(DAY - 0.5)
+        );
+        // Entrada de después de medianoche con el reloj local aún en el día anterior
+        assert_eq!(
+            parse_entry_time("00:00:01", None, DAY - 2.0),
+            Some(DAY + 1.0)
+        );
+        assert_eq!(
+            parse_entry_time("12:00:00", None, DAY + 3600.0),
+            Some(DAY + 43200.0)
+        );
+        assert_eq!(parse_entry_time("25:00:00", None, DAY), None);
+    }
+
+    #[test]
+    fn tag_value_matches_whole_tags() {
+        let msg = "|35=X|135=7|52=20240102-00:00:00";
+        assert_eq!(tag_value(msg, "35"), Some("X"));
+        assert_eq!(tag_value(msg, "52"), Some("20240102-00:00:00"));
+        assert_eq!(tag_value(msg, "5"), None);
+        assert_eq!(tag_value("|35=|", "35"), Some(""));
+    }
+
+    #[test]
+    fn window_rate_evicts_events_exactly_one_window_old() {
+        let mut rate = EventRate::new(RateMode::Window(1.0));
+        rate.record(0.0);
+        rate.record(0.5);
+        assert_eq!(rate.rate(0.999), 2.0);
+        assert_eq!(rate.rate(1.0), 1.0);
+        assert_eq!(rate.rate(1.5), 0.0);
+
+        let mut rate = EventRate::new(RateMode::Window(2.0));
+        rate.record(3.0);
+        // Un reloj que retrocede cuenta como simultáneo
+        rate.record(2.0);
+        assert_eq!(rate.rate(4.9), 1.0);
+        assert_eq!(rate.rate(5.0), 0.0);
+    }
+
+    #[test]
+    fn decay_rate_halves_after_one_halflife() {
+        let mut rate = EventRate::new(RateMode::Decay(2.0));
+        assert_eq!(rate.rate(0.0), 0.0);
+        rate.record(0.0);
+        let start = rate.rate(0.0);
+        assert!((start - std::f64::consts::LN_2 / 2.0).abs() < 1e-12);
+        assert!((rate.rate(2.0) - start / 2.0).abs() < 1e-12);
+        assert!((rate.rate(4.0) - start / 4.0).abs() < 1e-12);
+        // Un flujo constante de 1 evento/s converge a unos 1 evento/s
+        let mut steady = EventRate::new(RateMode::Decay(2.0));
+        for t in 0..200 {
+            steady.record(t as f64);
+        }
+        assert!((steady.rate(199.5) - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn velocity_splits_rates_by_kind() {
+        let mut velocity = TickVelocity::new(RateMode::Window(1.0));
+        velocity.record(EventKind::Bid, 0.1);
+        velocity.record(EventKind::Bid, 0.2);
+        velocity.record(EventKind::Trade, 0.3);
+        let rates = velocity.rates(0.5);
+        assert_eq!(
+            rates,
+            VelocityRates {
+                total: 3.0,
+                bid: 2.0,
+                ask: 0.0,
+                trade: 1.0
+            }
+        );
+    }
+
+    #[test]
+    fn parse_names_and_modes() {
+        assert_eq!(TimeSource::from_name("entry"), Some(TimeSource::Entry));
+        assert_eq!(TimeSource::from_name("local"), None);
+        assert_eq!(RateMode::parse("window:5"), Some(RateMode::Window(5.0)));
+        assert_eq!(RateMode::parse("decay"), Some(RateMode::Decay(1.0)));
+        assert_eq!(RateMode::parse("decay:0"), None);
+        assert_eq!(RateMode::parse("window:-1"), None);
+        assert_eq!(RateMode::parse("window:inf"), None);
+        assert_eq!(RateMode::parse("decay:NaN"), None);
+        assert_eq!(RateMode::parse("burst:1"), None);
+    }
+}